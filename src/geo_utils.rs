@@ -0,0 +1,151 @@
+use geo::{HaversineBearing, HaversineDestination, HaversineDistance, Point};
+
+/// The helpers take `(lat, lon)` pairs in degrees, `geo` points are `(lon, lat)`
+fn to_point((lat, lon): (f32, f32)) -> Point<f32> {
+    Point::new(lon, lat)
+}
+
+/// Distance in metres
+pub fn distance(from: (f32, f32), to: (f32, f32)) -> f32 {
+    to_point(from).haversine_distance(&to_point(to))
+}
+
+/// Initial bearing from `from` to `to` in the range -180 to 180, 0 is north
+pub fn bearing_between(from: (f32, f32), to: (f32, f32)) -> f32 {
+    to_point(from).haversine_bearing(to_point(to))
+}
+
+/// Point reached travelling `distance_m` from `from` on `bearing`, the longitude stays in
+/// -180 to 180 when the antimeridian is crossed
+pub fn destination(from: (f32, f32), bearing: f32, distance_m: f32) -> (f32, f32) {
+    let point = to_point(from).haversine_destination(bearing, distance_m);
+    (point.y(), normalize_lon(point.x()))
+}
+
+/// Longitude wrapped into -180 to 180, 190 gives -170
+pub fn normalize_lon(lon: f32) -> f32 {
+    if (-180. ..=180.).contains(&lon) {
+        return lon;
+    }
+    (lon + 180.).rem_euclid(360.) - 180.
+}
+
+/// Angle between two bearings in the range 0 to 180, 10 for 5 and 355
+pub fn bearing_difference(bearing_1: f32, bearing_2: f32) -> f32 {
+    let difference = (bearing_1 - bearing_2).rem_euclid(360.);
+    if difference > 180. {
+        360. - difference
+    } else {
+        difference
+    }
+}
+
+/// Degrees of latitude and longitude, min and max are inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f32,
+    pub min_lon: f32,
+    pub max_lat: f32,
+    pub max_lon: f32,
+}
+
+impl BoundingBox {
+    /// Smallest box around the points extended by `buffer_m` on every side, `None` without points
+    pub fn around_points(points: &[(f32, f32)], buffer_m: f32) -> Option<Self> {
+        let (first, rest) = points.split_first()?;
+        let (min_lat, min_lon, max_lat, max_lon) = rest.iter().fold(
+            (first.0, first.1, first.0, first.1),
+            |(min_lat, min_lon, max_lat, max_lon), (lat, lon)| {
+                (
+                    min_lat.min(*lat),
+                    min_lon.min(*lon),
+                    max_lat.max(*lat),
+                    max_lon.max(*lon),
+                )
+            },
+        );
+        // the corner closest to a pole needs the most longitude for the same distance
+        let widest_lat = if min_lat.abs() > max_lat.abs() {
+            min_lat
+        } else {
+            max_lat
+        };
+        Some(Self {
+            min_lat: destination((min_lat, min_lon), 180., buffer_m).0,
+            max_lat: destination((max_lat, max_lon), 0., buffer_m).0,
+            min_lon: destination((widest_lat, min_lon), -90., buffer_m).1,
+            max_lon: destination((widest_lat, max_lon), 90., buffer_m).1,
+        })
+    }
+
+    pub fn contains(&self, lat: f32, lon: f32) -> bool {
+        lat >= self.min_lat && lat <= self.max_lat && lon >= self.min_lon && lon <= self.max_lon
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        bearing_between, bearing_difference, destination, distance, normalize_lon, BoundingBox,
+    };
+
+    #[test]
+    fn bearing_difference_wraps() {
+        assert_eq!(bearing_difference(5., 355.), 10.);
+        assert_eq!(bearing_difference(355., 5.), 10.);
+        assert_eq!(bearing_difference(-175., 175.), 10.);
+        assert_eq!(bearing_difference(90., -90.), 180.);
+        assert_eq!(bearing_difference(10., 100.), 90.);
+        assert_eq!(bearing_difference(45., 45.), 0.);
+    }
+
+    #[test]
+    fn bearing_distance_destination() {
+        let start = (57., 24.);
+        assert!((bearing_between(start, (58., 24.)) - 0.).abs() < 0.01);
+        assert!((bearing_between(start, (57., 23.)) + 90.).abs() < 1.);
+        assert!((bearing_between(start, (56., 24.)).abs() - 180.).abs() < 0.01);
+
+        // a degree of latitude is about 111.2km
+        assert!((distance(start, (58., 24.)) - 111_195.).abs() < 10.);
+        assert_eq!(distance(start, start), 0.);
+
+        let (lat, lon) = destination(start, 90., 10_000.);
+        assert!((lat - 57.).abs() < 0.01);
+        assert!(lon > 24.);
+        assert!((distance(start, (lat, lon)) - 10_000.).abs() < 1.);
+    }
+
+    #[test]
+    fn destination_across_antimeridian() {
+        assert_eq!(normalize_lon(190.), -170.);
+        assert_eq!(normalize_lon(-190.), 170.);
+        assert_eq!(normalize_lon(180.), 180.);
+        assert_eq!(normalize_lon(24.5), 24.5);
+
+        let fiji = (-17., 179.95);
+        let (lat, lon) = destination(fiji, 90., 20_000.);
+        assert!((lat + 17.).abs() < 0.01);
+        assert!(lon < -179.8 && lon > -180.);
+        assert!((distance(fiji, (lat, lon)) - 20_000.).abs() < 10.);
+    }
+
+    #[test]
+    fn bounding_box_around_points() {
+        assert_eq!(BoundingBox::around_points(&[], 1000.), None);
+
+        let bbox = BoundingBox::around_points(&[(57., 24.), (57.2, 23.8)], 10_000.)
+            .expect("to get a bounding box");
+        assert!(bbox.contains(57., 24.));
+        assert!(bbox.contains(57.2, 23.8));
+        assert!(bbox.contains(57.1, 23.9));
+        // 10km is about 0.09 degrees of latitude and 0.16 of longitude here
+        assert!(bbox.contains(57.28, 24.15));
+        assert!(bbox.contains(56.92, 23.65));
+        assert!(!bbox.contains(57.3, 24.));
+        assert!(!bbox.contains(56.9, 24.));
+        assert!(!bbox.contains(57.1, 24.2));
+        assert!(!bbox.contains(57.1, 23.6));
+        assert!((distance((57., 24.), (bbox.min_lat, 24.)) - 10_000.).abs() < 1.);
+    }
+}
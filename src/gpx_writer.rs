@@ -1,17 +1,102 @@
 use geo::Point;
-use gpx::{write, Gpx, GpxVersion, Route as GpxRoute, Waypoint};
+use gpx::{write, Gpx, GpxVersion, Route as GpxRoute, Track, TrackSegment, Waypoint};
 use std::{collections::HashMap, fs::File, io::Error, path::PathBuf};
 
-use crate::{ipc_handler::RouteMessage, router::route::RouteStatElement};
+use crate::{
+    geo_utils,
+    ipc_handler::{CoordsMessage, RouteMessage, WaypointMessage},
+    router::{route::RouteStatElement, rules::RulesRouteSort},
+};
 
 #[derive(Debug)]
 pub enum GpxWriterError {
     FileCreateError { error: Error },
 }
 
+/// Points added along segments longer than `min_segment_m`, `spacing_m` apart, so that the
+/// line between sparse points follows the great circle instead of a chord
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpxInterpolation {
+    pub spacing_m: f32,
+    pub min_segment_m: f32,
+}
+
+impl GpxInterpolation {
+    /// Evenly spaced points between `from` and `to`, neither end is included
+    fn get_points(&self, from: &CoordsMessage, to: &CoordsMessage) -> Vec<(f32, f32)> {
+        let from = (from.lat, from.lon);
+        let to = (to.lat, to.lon);
+        let distance_m = geo_utils::distance(from, to);
+        if distance_m <= self.min_segment_m || !self.spacing_m.is_finite() || self.spacing_m <= 0. {
+            return Vec::new();
+        }
+        let count = (distance_m / self.spacing_m).ceil() as usize;
+        let step_m = distance_m / count as f32;
+        let bearing = geo_utils::bearing_between(from, to);
+        (1..count)
+            .map(|idx| geo_utils::destination(from, bearing, step_m * idx as f32))
+            .collect()
+    }
+}
+
 pub struct GpxWriter {
     routes: Vec<RouteMessage>,
     file_name: PathBuf,
+    tracks: bool,
+    interpolation: Option<GpxInterpolation>,
+    best_by: Option<RulesRouteSort>,
+    itinerary_waypoints: bool,
+}
+
+/// Options from the command line for the GPX outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GpxOptions {
+    pub interpolation: Option<GpxInterpolation>,
+    /// Write only the best route by this order
+    pub best_by: Option<RulesRouteSort>,
+    pub itinerary_waypoints: bool,
+}
+
+/// Named GPX waypoints for the itinerary waypoints of the route `route_name`
+fn get_itinerary_waypoints(route_name: &str, waypoints: &[WaypointMessage]) -> Vec<Waypoint> {
+    waypoints
+        .iter()
+        .enumerate()
+        .map(|(idx, itinerary_waypoint)| {
+            let mut waypoint = Waypoint::new(Point::new(
+                itinerary_waypoint.lon.into(),
+                itinerary_waypoint.lat.into(),
+            ));
+            waypoint.name = Some(format!("{route_name} waypoint {}", idx + 1));
+            waypoint.comment = Some(if itinerary_waypoint.reached {
+                "Reached".to_string()
+            } else {
+                "Not reached".to_string()
+            });
+            waypoint
+        })
+        .collect()
+}
+
+/// With `best_by` only the route that is not reversed and comes first in that order, the
+/// earliest one on a tie so that the output order does not matter otherwise
+fn get_written_routes(
+    routes: Vec<RouteMessage>,
+    best_by: Option<RulesRouteSort>,
+) -> Vec<RouteMessage> {
+    let Some(sort) = best_by else {
+        return routes;
+    };
+    routes
+        .into_iter()
+        .filter(|route| !route.reversed)
+        .min_by(|a, b| {
+            a.stats
+                .get_sort_key(sort)
+                .total_cmp(&b.stats.get_sort_key(sort))
+        })
+        .into_iter()
+        .collect()
 }
 
 fn sort_by_longest(map: HashMap<String, RouteStatElement>) -> Vec<(String, RouteStatElement)> {
@@ -22,26 +107,71 @@ fn sort_by_longest(map: HashMap<String, RouteStatElement>) -> Vec<(String, Route
 
 impl GpxWriter {
     pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf) -> Self {
-        Self { routes, file_name }
+        Self {
+            routes,
+            file_name,
+            tracks: false,
+            interpolation: None,
+            best_by: None,
+            itinerary_waypoints: false,
+        }
+    }
+    /// Write each route as a track with one segment instead of a GPX route
+    pub fn tracks(mut self, tracks: bool) -> Self {
+        self.tracks = tracks;
+        self
+    }
+    /// Add points along long segments, the instructions stay on the route points
+    pub fn interpolation(mut self, interpolation: Option<GpxInterpolation>) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+    /// Write only the best route by `best_by` and no CSV summary, for loading straight into
+    /// a GPS
+    pub fn best_by(mut self, best_by: Option<RulesRouteSort>) -> Self {
+        self.best_by = best_by;
+        self
+    }
+    /// Add the itinerary waypoints of each route, reversed routes share them with the route
+    /// before them and are skipped
+    pub fn itinerary_waypoints(mut self, itinerary_waypoints: bool) -> Self {
+        self.itinerary_waypoints = itinerary_waypoints;
+        self
+    }
+    pub fn options(self, options: GpxOptions) -> Self {
+        self.interpolation(options.interpolation)
+            .best_by(options.best_by)
+            .itinerary_waypoints(options.itinerary_waypoints)
     }
     pub fn write_gpx(self) -> Result<(), GpxWriterError> {
         let mut gpx = Gpx::default();
         gpx.version = GpxVersion::Gpx11;
 
         let mut csv_contents =
-            String::from("id,len,junctions,mean_point_lat,mean_point_lon,dir_change_ratio\n");
-        for (idx, route) in self.routes.into_iter().enumerate() {
+            String::from("id,len,junctions,mean_point_lat,mean_point_lon,dir_change_ratio,retraced_len,waypoints_reached,waypoints_total\n");
+        for (idx, route) in get_written_routes(self.routes, self.best_by)
+            .into_iter()
+            .enumerate()
+        {
+            let route_name = if route.reversed {
+                format!("r_{idx}_reversed")
+            } else {
+                format!("r_{idx}")
+            };
             csv_contents.push_str(&format!(
-                "r_{},{},{},{},{},{}\n",
-                idx,
+                "{},{},{},{},{},{},{},{},{}\n",
+                route_name,
                 route.stats.len_m / 1000.,
                 route.stats.junction_count,
                 route.stats.mean_point.lat,
                 route.stats.mean_point.lon,
-                route.stats.direction_change_ratio
+                route.stats.direction_change_ratio,
+                route.stats.retraced_len_m / 1000.,
+                route.stats.waypoints_reached,
+                route.stats.waypoints_total
             ));
             let mut gpx_route = GpxRoute::new();
-            gpx_route.name = Some(format!("r_{idx}"));
+            gpx_route.name = Some(route_name);
 
             let mut description = String::new();
             description.push_str(&format!("Length: {:.2}km\n", route.stats.len_m / 1000.));
@@ -57,6 +187,15 @@ impl GpxWriter {
                 "Direction change degrees per km: {:.2}\n",
                 route.stats.direction_change_ratio
             ));
+            description.push_str(&format!(
+                "Retraced length: {:.2}km\n",
+                route.stats.retraced_len_m / 1000.
+            ));
+            description.push_str(&format!("Roundabouts: {}\n", route.stats.roundabout_count));
+            description.push_str(&format!(
+                "Waypoints reached: {}/{}\n",
+                route.stats.waypoints_reached, route.stats.waypoints_total
+            ));
             description.push_str(&format!("Road types:\n"));
             for (road_type, stat) in sort_by_longest(route.stats.highway).iter() {
                 description.push_str(&format!(
@@ -81,20 +220,92 @@ impl GpxWriter {
                     stat.percentage,
                 ));
             }
+            for (title, stat_map) in [
+                ("Bridges", route.stats.bridge),
+                ("Tunnels", route.stats.tunnel),
+                ("Fords", route.stats.ford),
+                ("Lit", route.stats.lit),
+            ] {
+                if stat_map.is_empty() {
+                    continue;
+                }
+                description.push_str(&format!("{title}:\n"));
+                for (tag_value, stat) in sort_by_longest(stat_map).iter() {
+                    description.push_str(&format!(
+                        " - {tag_value}: {:.2}km, {:.2}%\n",
+                        stat.len_m / 1000.,
+                        stat.percentage,
+                    ));
+                }
+            }
 
             gpx_route.description = Some(description);
 
-            for coord in &route.coords {
-                let waypoint = Waypoint::new(Point::new(coord.lon.into(), coord.lat.into()));
+            let mut instructions = route.instructions.iter().peekable();
+            for (point_idx, coord) in route.coords.iter().enumerate() {
+                let mut waypoint = Waypoint::new(Point::new(coord.lon.into(), coord.lat.into()));
+                if let Some(instruction) =
+                    instructions.next_if(|instruction| instruction.point_idx == point_idx)
+                {
+                    waypoint.name = Some(instruction.text());
+                    if instruction.distance_to_next_m > 0. {
+                        waypoint.comment = Some(format!(
+                            "Next instruction in {:.2}km",
+                            instruction.distance_to_next_m / 1000.
+                        ));
+                    }
+                }
                 gpx_route.points.push(waypoint);
+
+                if let (Some(interpolation), Some(next_coord)) =
+                    (&self.interpolation, route.coords.get(point_idx + 1))
+                {
+                    gpx_route.points.extend(
+                        interpolation
+                            .get_points(coord, next_coord)
+                            .into_iter()
+                            .map(|(lat, lon)| Waypoint::new(Point::new(lon.into(), lat.into()))),
+                    );
+                }
             }
 
-            gpx.routes.push(gpx_route);
+            for refuel_point in &route.refuel_points {
+                let mut waypoint =
+                    Waypoint::new(Point::new(refuel_point.lon.into(), refuel_point.lat.into()));
+                waypoint.name = Some("Refuel suggested".to_string());
+                waypoint.comment = Some(format!(
+                    "{} at {:.2}km",
+                    gpx_route.name.as_deref().unwrap_or_default(),
+                    refuel_point.cum_dist_m / 1000.
+                ));
+                gpx.waypoints.push(waypoint);
+            }
+
+            if self.itinerary_waypoints && !route.reversed {
+                gpx.waypoints.extend(get_itinerary_waypoints(
+                    gpx_route.name.as_deref().unwrap_or_default(),
+                    &route.itinerary_waypoints,
+                ));
+            }
+
+            if self.tracks {
+                let mut track = Track::new();
+                track.name = gpx_route.name;
+                track.description = gpx_route.description;
+                let mut segment = TrackSegment::new();
+                segment.points = gpx_route.points;
+                track.segments.push(segment);
+                gpx.tracks.push(track);
+            } else {
+                gpx.routes.push(gpx_route);
+            }
         }
 
-        let mut csv_filename = PathBuf::from(&self.file_name);
-        csv_filename.set_extension("csv");
-        std::fs::write(csv_filename, csv_contents).unwrap();
+        if self.best_by.is_none() {
+            let mut csv_filename = PathBuf::from(&self.file_name);
+            csv_filename.set_extension("csv");
+            std::fs::write(csv_filename, csv_contents).unwrap();
+        }
         let file = File::create(self.file_name)
             .or_else(|error| Err(GpxWriterError::FileCreateError { error }))?;
 
@@ -103,3 +314,160 @@ impl GpxWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        geo_utils,
+        ipc_handler::{CoordsMessage, RouteMessage, WaypointMessage},
+        router::{route::RouteStats, rules::RulesRouteSort},
+    };
+
+    use super::{get_itinerary_waypoints, get_written_routes, GpxInterpolation, GpxWriter};
+
+    #[test]
+    fn itinerary_waypoints() {
+        let waypoints = get_itinerary_waypoints(
+            "r_1",
+            &[
+                WaypointMessage {
+                    lat: 57.,
+                    lon: 24.,
+                    reached: true,
+                },
+                WaypointMessage {
+                    lat: 57.1,
+                    lon: 24.1,
+                    reached: false,
+                },
+            ],
+        );
+        let names = waypoints
+            .iter()
+            .map(|waypoint| (waypoint.name.clone(), waypoint.comment.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (
+                    Some("r_1 waypoint 1".to_string()),
+                    Some("Reached".to_string())
+                ),
+                (
+                    Some("r_1 waypoint 2".to_string()),
+                    Some("Not reached".to_string())
+                ),
+            ]
+        );
+        assert!(get_itinerary_waypoints("r_1", &[]).is_empty());
+    }
+
+    #[test]
+    fn best_route_only() {
+        let message = |len_m, direction_change_ratio, reversed| RouteMessage {
+            stats: RouteStats {
+                len_m,
+                direction_change_ratio,
+                ..RouteStats::default()
+            },
+            reversed,
+            ..RouteMessage::default()
+        };
+        let routes = vec![
+            message(50., 0.9, true),
+            message(200., 0.5, false),
+            message(100., 0.2, false),
+            message(300., 0.5, false),
+        ];
+        let written = |best_by| {
+            get_written_routes(routes.clone(), best_by)
+                .iter()
+                .map(|route| (route.stats.len_m, route.reversed))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(written(Some(RulesRouteSort::Length)), vec![(100., false)]);
+        // a tie keeps the earlier route
+        assert_eq!(
+            written(Some(RulesRouteSort::Curviness)),
+            vec![(200., false)]
+        );
+        assert_eq!(written(None).len(), 4);
+        assert!(get_written_routes(Vec::new(), Some(RulesRouteSort::Length)).is_empty());
+    }
+
+    #[test]
+    fn write_tracks() {
+        let file_name =
+            std::env::temp_dir().join(format!("ridi-router-tracks-{}.gpx", std::process::id()));
+        let route = RouteMessage {
+            coords: vec![
+                CoordsMessage { lat: 57., lon: 24. },
+                CoordsMessage {
+                    lat: 57.01,
+                    lon: 24.01,
+                },
+            ],
+            ..RouteMessage::default()
+        };
+
+        GpxWriter::new(vec![route], file_name.clone())
+            .tracks(true)
+            .write_gpx()
+            .unwrap();
+        let contents = std::fs::read_to_string(&file_name).unwrap();
+        assert!(contents.contains("<trk>"));
+        assert!(contents.contains("<trkseg>"));
+        assert!(!contents.contains("<rte>"));
+
+        std::fs::remove_file(&file_name).unwrap();
+        std::fs::remove_file(file_name.with_extension("csv")).unwrap();
+    }
+
+    #[test]
+    fn interpolate_long_segments() {
+        let interpolation = GpxInterpolation {
+            spacing_m: 10_000.,
+            min_segment_m: 20_000.,
+        };
+        let from = CoordsMessage { lat: 57., lon: 20. };
+        // about 33km
+        let short = CoordsMessage {
+            lat: 57.,
+            lon: 20.55,
+        };
+        // about 66km
+        let long = CoordsMessage {
+            lat: 57.,
+            lon: 21.1,
+        };
+
+        assert!(interpolation
+            .get_points(
+                &from,
+                &CoordsMessage {
+                    lat: 57.,
+                    lon: 20.2
+                }
+            )
+            .is_empty());
+        assert_eq!(interpolation.get_points(&from, &short).len(), 3);
+
+        let points = interpolation.get_points(&from, &long);
+        assert_eq!(points.len(), 6);
+        let total_m = geo_utils::distance((from.lat, from.lon), (long.lat, long.lon));
+        let step_m = total_m / 7.;
+        for (idx, point) in points.iter().enumerate() {
+            let distance_m = geo_utils::distance((from.lat, from.lon), *point);
+            assert!((distance_m - step_m * (idx + 1) as f32).abs() < 10.);
+        }
+        // the great circle between points on the same parallel bends towards the pole
+        assert!(points[3].0 > 57.);
+
+        let no_spacing = GpxInterpolation {
+            spacing_m: 0.,
+            min_segment_m: 0.,
+        };
+        assert!(no_spacing.get_points(&from, &long).is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, prelude::*, BufReader},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+use tracing::{info, trace, warn};
+
+use crate::{
+    ipc_handler::{RequestMessage, ResponseMessage},
+    router::rules::RouterRules,
+    router_runner::StartFinishPoint,
+};
+
+/// Requests with larger bodies are rejected before the body is read
+const MAX_BODY_LEN: usize = 1024 * 1024;
+/// Reads and writes of a connection fail after this, a client that stops sending or reading
+/// does not hold a thread of the pool
+const CONNECTION_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum HttpHandlerError {
+    Bind { error: io::Error },
+    ReadRequest { error: io::Error },
+    WriteResponse { error: io::Error },
+    SerializeResponse { error: serde_json::Error },
+    MalformedRequest { cause: String },
+    BodyTooLarge { len: usize },
+}
+
+/// Body of `POST /route`, `rules` and `reversed` can be left out
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HttpRouteRequest {
+    pub start: StartFinishPoint,
+    pub finish: StartFinishPoint,
+    #[serde(default)]
+    pub rules: RouterRules,
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+pub struct HttpHandler {
+    listener: TcpListener,
+}
+
+impl HttpHandler {
+    pub fn init(address: &str) -> Result<Self, HttpHandlerError> {
+        let listener =
+            TcpListener::bind(address).map_err(|error| HttpHandlerError::Bind { error })?;
+        info!("HTTP server running at http://{address}");
+        Ok(Self { listener })
+    }
+
+    /// Answer `POST /route` requests with the JSON of the `RouterResult`, each connection is
+    /// handled on the rayon pool so requests are served concurrently against the one graph
+    pub fn listen<T>(&self, message_handler: T) -> Result<(), HttpHandlerError>
+    where
+        T: Fn(RequestMessage) -> ResponseMessage + Sync + Send + Copy + 'static,
+    {
+        for (request_idx, conn) in self.listener.incoming().enumerate() {
+            let conn = match conn {
+                Err(e) => {
+                    warn!("Incoming connection failed {}", e);
+                    continue;
+                }
+                Ok(conn) => conn,
+            };
+            if let Err(error) = conn
+                .set_read_timeout(Some(CONNECTION_IO_TIMEOUT))
+                .and_then(|_| conn.set_write_timeout(Some(CONNECTION_IO_TIMEOUT)))
+            {
+                warn!("Could not set the connection timeouts {}", error);
+                continue;
+            }
+            rayon::spawn(move || {
+                trace!("received connection");
+                if let Err(error) =
+                    HttpHandler::handle_connection(conn, request_idx, message_handler)
+                {
+                    warn!("error from connection {:?}", error);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn handle_connection<T>(
+        mut conn: TcpStream,
+        request_idx: usize,
+        message_handler: T,
+    ) -> Result<(), HttpHandlerError>
+    where
+        T: Fn(RequestMessage) -> ResponseMessage,
+    {
+        let request = match HttpHandler::read_request(&mut BufReader::new(&conn)) {
+            Ok(request) => request,
+            Err(error) => {
+                let status = match error {
+                    HttpHandlerError::BodyTooLarge { .. } => "413 Payload Too Large",
+                    _ => "400 Bad Request",
+                };
+                HttpHandler::write_response(&mut conn, status, &format!("{:?}", error))?;
+                return Err(error);
+            }
+        };
+        info!("{} {}", request.method, request.path);
+
+        if request.path != "/route" {
+            return HttpHandler::write_response(&mut conn, "404 Not Found", "Not found");
+        }
+        if request.method != "POST" {
+            return HttpHandler::write_response(&mut conn, "405 Method Not Allowed", "Use POST");
+        }
+        let route_request: HttpRouteRequest = match serde_json::from_slice(&request.body) {
+            Ok(route_request) => route_request,
+            Err(error) => {
+                return HttpHandler::write_response(
+                    &mut conn,
+                    "400 Bad Request",
+                    &format!("Could not read the request {error}"),
+                );
+            }
+        };
+
+        let response = message_handler(RequestMessage {
+            id: format!("http-{request_idx}"),
+            start: route_request.start,
+            finish: route_request.finish,
+            rules: route_request.rules,
+            reversed: route_request.reversed,
+        });
+        let body = serde_json::to_string(&response.result)
+            .map_err(|error| HttpHandlerError::SerializeResponse { error })?;
+        HttpHandler::write_response(&mut conn, "200 OK", &body)
+    }
+
+    fn read_request<R: BufRead>(reader: &mut R) -> Result<HttpRequest, HttpHandlerError> {
+        let mut request_line = String::new();
+        reader
+            .read_line(&mut request_line)
+            .map_err(|error| HttpHandlerError::ReadRequest { error })?;
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next()) {
+            (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+            _ => {
+                return Err(HttpHandlerError::MalformedRequest {
+                    cause: format!("invalid request line {request_line:?}"),
+                })
+            }
+        };
+
+        let mut content_len = 0;
+        loop {
+            let mut header = String::new();
+            let len = reader
+                .read_line(&mut header)
+                .map_err(|error| HttpHandlerError::ReadRequest { error })?;
+            let header = header.trim();
+            if len == 0 || header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_len = value.trim().parse::<usize>().map_err(|_| {
+                        HttpHandlerError::MalformedRequest {
+                            cause: format!("invalid content length {value:?}"),
+                        }
+                    })?;
+                }
+            }
+        }
+        if content_len > MAX_BODY_LEN {
+            return Err(HttpHandlerError::BodyTooLarge { len: content_len });
+        }
+
+        let mut body = vec![0; content_len];
+        reader
+            .read_exact(&mut body)
+            .map_err(|error| HttpHandlerError::ReadRequest { error })?;
+
+        Ok(HttpRequest { method, path, body })
+    }
+
+    fn write_response(
+        conn: &mut TcpStream,
+        status: &str,
+        body: &str,
+    ) -> Result<(), HttpHandlerError> {
+        let content_type = if status.starts_with("200") {
+            "application/json"
+        } else {
+            "text/plain"
+        };
+        write!(
+            conn,
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .map_err(|error| HttpHandlerError::WriteResponse { error })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HttpHandler, HttpHandlerError, HttpRequest, MAX_BODY_LEN};
+
+    #[test]
+    fn read_http_request() {
+        let input =
+            b"POST /route HTTP/1.1\r\nHost: localhost\r\nContent-Length: 7\r\n\r\n{\"a\":1}";
+        assert_eq!(
+            HttpHandler::read_request(&mut &input[..]).unwrap(),
+            HttpRequest {
+                method: "POST".to_string(),
+                path: "/route".to_string(),
+                body: b"{\"a\":1}".to_vec(),
+            }
+        );
+
+        let input = b"GET / HTTP/1.1\r\n\r\n";
+        let request = HttpHandler::read_request(&mut &input[..]).unwrap();
+        assert_eq!(request.method, "GET");
+        assert!(request.body.is_empty());
+
+        assert!(matches!(
+            HttpHandler::read_request(&mut &b"\r\n"[..]),
+            Err(HttpHandlerError::MalformedRequest { .. })
+        ));
+        let input = format!(
+            "POST /route HTTP/1.1\r\ncontent-length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        assert!(matches!(
+            HttpHandler::read_request(&mut input.as_bytes()),
+            Err(HttpHandlerError::BodyTooLarge { .. })
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 use bincode::ErrorKind;
 use interprocess::local_socket::{prelude::*, GenericNamespaced, ListenerOptions, Name, Stream};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -8,8 +9,12 @@ use std::{
 use tracing::{info, trace, warn};
 
 use crate::{
-    router::{route::RouteStats, rules::RouterRules},
-    router_runner::StartFinish,
+    router::{
+        generator::ItineraryOutcome,
+        route::{instructions::Instruction, ProfilePoint, RouteStats},
+        rules::RouterRules,
+    },
+    router_runner::{StartFinish, StartFinishPoint},
 };
 
 #[derive(Debug)]
@@ -23,6 +28,7 @@ pub enum IpcHandlerError {
     Connect { error: io::Error },
     DeserializeMessage { error: Box<ErrorKind> },
     SerializeMessage { error: Box<ErrorKind> },
+    UnexpectedResponse,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -31,32 +37,92 @@ pub struct CoordsMessage {
     pub lon: f32,
 }
 
+/// Waypoint of the itinerary a route was generated for, `reached` when the route passed
+/// within the waypoint radius
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct WaypointMessage {
+    pub lat: f32,
+    pub lon: f32,
+    pub reached: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RequestMessage {
     pub id: String,
-    pub start: CoordsMessage,
-    pub finish: CoordsMessage,
+    pub start: StartFinishPoint,
+    pub finish: StartFinishPoint,
     pub rules: RouterRules,
+    pub reversed: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct RouteMessage {
     pub coords: Vec<CoordsMessage>,
     pub stats: RouteStats,
+    /// Return leg of the previous route in the list
+    pub reversed: bool,
+    pub instructions: Vec<Instruction>,
+    /// OSM way id of each segment, `coords[idx + 1]` is reached on `way_ids[idx]`
+    pub way_ids: Vec<u64>,
+    pub profile: Vec<ProfilePoint>,
+    /// Empty unless `refuel_range_km` is set in the rules
+    pub refuel_points: Vec<ProfilePoint>,
+    /// Empty unless `simplify_tolerance_m` is set in the rules, `coords` stays complete
+    pub simplified_coords: Vec<CoordsMessage>,
+    /// Waypoints the generator steered the route through
+    pub itinerary_waypoints: Vec<WaypointMessage>,
+    /// `coords` as an encoded polyline with `POLYLINE_PRECISION` decimals
+    pub polyline: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum RouterResult {
-    Error { message: String },
-    Ok { routes: Vec<RouteMessage> },
+    Error {
+        message: String,
+    },
+    Ok {
+        routes: Vec<RouteMessage>,
+        /// One per itinerary, including the ones that did not produce a route
+        outcomes: Vec<ItineraryOutcome>,
+    },
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ResponseMessage {
     pub id: String,
     pub result: RouterResult,
 }
 
+/// What a client sends to the server, a batch is answered in one round trip with a
+/// response for each request in the same order
+#[derive(Serialize, Deserialize, Debug)]
+pub enum IpcRequest {
+    Single(Box<RequestMessage>),
+    Batch(Vec<RequestMessage>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum IpcResponse {
+    Single(ResponseMessage),
+    Batch(Vec<ResponseMessage>),
+}
+
+impl IpcRequest {
+    /// Batched requests are handled in parallel, they only share the loaded map data
+    fn handle<T>(self, message_handler: T) -> IpcResponse
+    where
+        T: Fn(RequestMessage) -> ResponseMessage + Sync + Send,
+    {
+        match self {
+            IpcRequest::Single(request) => IpcResponse::Single(message_handler(*request)),
+            IpcRequest::Batch(requests) => {
+                info!("handling batch of {} requests", requests.len());
+                IpcResponse::Batch(requests.into_par_iter().map(&message_handler).collect())
+            }
+        }
+    }
+}
+
 pub struct IpcHandler<'a> {
     socket_print_name: String,
     socket_name: Name<'a>,
@@ -119,7 +185,7 @@ impl<'a> IpcHandler<'a> {
                         Ok(req) => req,
                     };
                     dbg!("calling msg handler");
-                    let resp = message_handler(req);
+                    let resp = req.handle(message_handler);
                     if let Err(error) = IpcHandler::process_response(&conn, &resp) {
                         warn!("error from connection {:?}", error);
                     }
@@ -130,7 +196,7 @@ impl<'a> IpcHandler<'a> {
         Ok(())
     }
 
-    fn process_request(conn: &Stream) -> Result<RequestMessage, IpcHandlerError> {
+    fn process_request(conn: &Stream) -> Result<IpcRequest, IpcHandlerError> {
         let mut conn = BufReader::new(conn);
         info!("Incoming connection!");
 
@@ -155,7 +221,7 @@ impl<'a> IpcHandler<'a> {
     }
     fn process_response(
         conn: &Stream,
-        response_message: &ResponseMessage,
+        response_message: &IpcResponse,
     ) -> Result<(), IpcHandlerError> {
         let mut conn = BufReader::new(conn);
 
@@ -179,25 +245,40 @@ impl<'a> IpcHandler<'a> {
         &self,
         start_finish: &StartFinish,
         rules: RouterRules,
+        reversed: bool,
     ) -> Result<ResponseMessage, IpcHandlerError> {
+        let req_msg = IpcRequest::Single(Box::new(RequestMessage {
+            id: "ooo".to_string(),
+            start: start_finish.start.clone(),
+            finish: start_finish.finish.clone(),
+            rules,
+            reversed,
+        }));
+        match self.send(&req_msg)? {
+            IpcResponse::Single(resp_msg) => Ok(resp_msg),
+            IpcResponse::Batch(_) => Err(IpcHandlerError::UnexpectedResponse),
+        }
+    }
+
+    /// Send all requests in one round trip, the responses are in the same order
+    pub fn connect_batch(
+        &self,
+        requests: Vec<RequestMessage>,
+    ) -> Result<Vec<ResponseMessage>, IpcHandlerError> {
+        let request_count = requests.len();
+        match self.send(&IpcRequest::Batch(requests))? {
+            IpcResponse::Batch(resp_msgs) if resp_msgs.len() == request_count => Ok(resp_msgs),
+            _ => Err(IpcHandlerError::UnexpectedResponse),
+        }
+    }
+
+    fn send(&self, req_msg: &IpcRequest) -> Result<IpcResponse, IpcHandlerError> {
         let conn = Stream::connect(self.socket_name.clone())
             .map_err(|error| IpcHandlerError::Connect { error })?;
 
         let mut conn = BufReader::new(conn);
 
-        let req_msg = RequestMessage {
-            id: "ooo".to_string(),
-            start: CoordsMessage {
-                lat: start_finish.start_lat,
-                lon: start_finish.start_lon,
-            },
-            finish: CoordsMessage {
-                lat: start_finish.finish_lat,
-                lon: start_finish.finish_lon,
-            },
-            rules,
-        };
-        let req_buf = bincode::serialize(&req_msg)
+        let req_buf = bincode::serialize(req_msg)
             .map_err(|error| IpcHandlerError::SerializeMessage { error })?;
 
         let mes_len_bytes: u64 = req_buf.len() as u64;
@@ -222,9 +303,93 @@ impl<'a> IpcHandler<'a> {
 
         info!("message received {}", resp_buf.len());
 
-        let resp_msg: ResponseMessage = bincode::deserialize(&resp_buf[..])
+        let resp_msg: IpcResponse = bincode::deserialize(&resp_buf[..])
             .map_err(|error| IpcHandlerError::DeserializeMessage { error })?;
 
         Ok(resp_msg)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{router::rules::RouterRules, router_runner::StartFinishPoint};
+
+    use super::{
+        IpcHandler, IpcRequest, IpcResponse, RequestMessage, ResponseMessage, RouterResult,
+    };
+
+    fn request(id: &str) -> RequestMessage {
+        RequestMessage {
+            id: id.to_string(),
+            start: StartFinishPoint::NodeId { id: 1 },
+            finish: StartFinishPoint::NodeId { id: 2 },
+            rules: RouterRules::default(),
+            reversed: false,
+        }
+    }
+
+    fn echo(request: RequestMessage) -> ResponseMessage {
+        ResponseMessage {
+            result: RouterResult::Error {
+                message: format!("handled {}", request.id),
+            },
+            id: request.id,
+        }
+    }
+
+    #[test]
+    fn batch_requests_are_answered_in_order() {
+        match IpcRequest::Single(Box::new(request("a"))).handle(echo) {
+            IpcResponse::Single(response) => assert_eq!(response.id, "a"),
+            IpcResponse::Batch(_) => panic!("expected a single response"),
+        }
+
+        let ids = (0..20).map(|idx| idx.to_string()).collect::<Vec<_>>();
+        let batch = IpcRequest::Batch(ids.iter().map(|id| request(id)).collect());
+        match batch.handle(echo) {
+            IpcResponse::Batch(responses) => {
+                assert_eq!(
+                    responses
+                        .iter()
+                        .map(|response| response.id.clone())
+                        .collect::<Vec<_>>(),
+                    ids
+                );
+                assert!(matches!(
+                    &responses[3].result,
+                    RouterResult::Error { message } if message == "handled 3"
+                ));
+            }
+            IpcResponse::Single(_) => panic!("expected a batch response"),
+        }
+    }
+
+    #[test]
+    fn connect_batch_round_trip() {
+        let socket_name = Some(format!("test-batch-{}", std::process::id()));
+        let server_socket_name = socket_name.clone();
+        std::thread::spawn(move || IpcHandler::init(server_socket_name).unwrap().listen(echo));
+
+        let client = IpcHandler::init(socket_name).unwrap();
+        let responses = (0..50)
+            .find_map(|_| {
+                let requests = vec![request("a"), request("b"), request("c")];
+                match client.connect_batch(requests) {
+                    Ok(responses) => Some(responses),
+                    Err(_) => {
+                        // the listener may not be created yet
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        None
+                    }
+                }
+            })
+            .expect("to get the batch responses");
+        assert_eq!(
+            responses
+                .iter()
+                .map(|response| response.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+    }
+}
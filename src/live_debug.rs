@@ -0,0 +1,185 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Sender},
+        Mutex, OnceLock,
+    },
+    thread,
+};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::router::route::Route;
+
+/// Navigator steps between route updates
+pub const PUBLISH_EVERY_STEPS: usize = 200;
+
+static SUBSCRIBERS: OnceLock<Mutex<Vec<Sender<String>>>> = OnceLock::new();
+
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>ridi-router live</title></head>
+<body style="margin:0">
+<canvas id="map" style="width:100vw;height:100vh"></canvas>
+<script>
+const canvas = document.getElementById("map");
+const routes = {};
+function draw() {
+  canvas.width = canvas.clientWidth;
+  canvas.height = canvas.clientHeight;
+  const all = Object.values(routes).flatMap(r => r.coords);
+  if (all.length === 0) return;
+  const lats = all.map(c => c[0]), lons = all.map(c => c[1]);
+  const minLat = Math.min(...lats), maxLat = Math.max(...lats);
+  const minLon = Math.min(...lons), maxLon = Math.max(...lons);
+  const scale = Math.min(canvas.width / (maxLon - minLon || 1), canvas.height / (maxLat - minLat || 1)) * 0.9;
+  const ctx = canvas.getContext("2d");
+  for (const route of Object.values(routes)) {
+    ctx.strokeStyle = route.finished ? "green" : "red";
+    ctx.beginPath();
+    route.coords.forEach((c, idx) => {
+      const x = (c[1] - minLon) * scale + canvas.width * 0.05;
+      const y = canvas.height - ((c[0] - minLat) * scale + canvas.height * 0.05);
+      idx === 0 ? ctx.moveTo(x, y) : ctx.lineTo(x, y);
+    });
+    ctx.stroke();
+  }
+}
+new EventSource("/events").onmessage = event => {
+  const route = JSON.parse(event.data);
+  routes[route.itinerary_id] = route;
+  draw();
+};
+</script>
+</body>
+</html>
+"#;
+
+#[derive(Serialize)]
+struct LiveRouteMessage<'a> {
+    itinerary_id: &'a str,
+    finished: bool,
+    coords: Vec<(f32, f32)>,
+}
+
+impl<'a> LiveRouteMessage<'a> {
+    fn new(itinerary_id: &'a str, route: &Route, finished: bool) -> Self {
+        Self {
+            itinerary_id,
+            finished,
+            coords: route
+                .get_points()
+                .iter()
+                .map(|point| (point.borrow().lat, point.borrow().lon))
+                .collect(),
+        }
+    }
+}
+
+/// Serve a page on `addr` that draws the routes the navigators are walking, updates are
+/// streamed to the page as server-sent events
+pub fn start(addr: &str) -> Result<(), io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    SUBSCRIBERS.get_or_init(|| Mutex::new(Vec::new()));
+    info!("live debug listening on http://{addr}");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(error) = handle_connection(stream) {
+                            info!(error = ?error, "live debug connection closed");
+                        }
+                    });
+                }
+                Err(error) => warn!(error = ?error, "live debug connection failed"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream) -> Result<(), io::Error> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    if !request_line.starts_with("GET /events ") {
+        return write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+            INDEX_HTML.len(),
+            INDEX_HTML
+        );
+    }
+
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n",
+    )?;
+    let (sender, receiver) = mpsc::channel();
+    match SUBSCRIBERS.get().map(|subscribers| subscribers.lock()) {
+        Some(Ok(mut subscribers)) => subscribers.push(sender),
+        _ => return Ok(()),
+    }
+    for message in receiver {
+        write!(stream, "data: {message}\n\n")?;
+    }
+    Ok(())
+}
+
+/// Send the route to all connected pages, does nothing when the server was not started or
+/// no page is connected
+pub fn publish_route(itinerary_id: &str, route: &Route, finished: bool) {
+    let mut subscribers = match SUBSCRIBERS.get().map(|subscribers| subscribers.lock()) {
+        Some(Ok(subscribers)) => subscribers,
+        _ => return,
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+    let message = match serde_json::to_string(&LiveRouteMessage::new(itinerary_id, route, finished))
+    {
+        Ok(message) => message,
+        Err(error) => {
+            warn!(error = ?error, "could not serialize live debug route");
+            return;
+        }
+    };
+    subscribers.retain(|subscriber| subscriber.send(message.clone()).is_ok());
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::graph::MapDataGraph,
+        router::route::{segment::Segment, Route},
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    use super::LiveRouteMessage;
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn live_route_message_json() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let (line, next_point) = MapDataGraph::get()
+                .get_adjacent(point)
+                .into_iter()
+                .find(|(_, point)| point.borrow().id == 2)
+                .unwrap();
+            let mut route = Route::new();
+            route.add_segment(Segment::new(line, next_point));
+
+            // the page reads `coords` as `[lat, lon]` pairs
+            assert_eq!(
+                serde_json::to_string(&LiveRouteMessage::new("1-2", &route, true)).unwrap(),
+                r#"{"itinerary_id":"1-2","finished":true,"coords":[[1.0,1.0],[2.0,2.0]]}"#
+            );
+        }
+    }
+}
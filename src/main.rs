@@ -4,12 +4,18 @@ use router_runner::RouterRunner;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
+mod geo_utils;
 mod gpx_writer;
+mod http_handler;
 mod ipc_handler;
+#[cfg(feature = "live-debug")]
+mod live_debug;
 mod map_data;
 mod map_data_cache;
 mod osm_data_reader;
 mod osm_json_parser;
+mod osm_xml_parser;
+mod polyline_writer;
 mod result_writer;
 mod router;
 mod router_runner;
@@ -0,0 +1,119 @@
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+/// Values that OSM uses for "no restriction" on the `max*` dimension tags
+const UNRESTRICTED_VALUES: [&str; 4] = ["none", "default", "no_sign", "no_indications"];
+
+/// Parsed `maxheight`, `maxwidth`, `maxlength` and `maxweight`, `None` when a tag is not set
+/// or can not be parsed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DimensionLimits {
+    pub height_m: Option<f32>,
+    pub width_m: Option<f32>,
+    pub length_m: Option<f32>,
+    pub weight_t: Option<f32>,
+}
+
+// parsed values are always finite so the bits compare and hash like the values
+impl Eq for DimensionLimits {}
+
+impl Hash for DimensionLimits {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in [self.height_m, self.width_m, self.length_m, self.weight_t] {
+            value.map(f32::to_bits).hash(state);
+        }
+    }
+}
+
+impl DimensionLimits {
+    pub fn parse<'a, F>(get_tag: F) -> Self
+    where
+        F: Fn(&str) -> Option<&'a String>,
+    {
+        Self {
+            height_m: get_tag("maxheight").and_then(|value| parse_length_m(value)),
+            width_m: get_tag("maxwidth").and_then(|value| parse_length_m(value)),
+            length_m: get_tag("maxlength").and_then(|value| parse_length_m(value)),
+            weight_t: get_tag("maxweight").and_then(|value| parse_weight_t(value)),
+        }
+    }
+}
+
+pub fn is_unrestricted(value: &str) -> bool {
+    UNRESTRICTED_VALUES.contains(&value.trim())
+}
+
+fn parse_number(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .replace(',', ".")
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.)
+}
+
+/// Parse a `maxheight`, `maxwidth` or `maxlength` value to metres. Plain numbers are metres,
+/// `m` and `ft` suffixes and feet and inches like `11'6"` are accepted.
+pub fn parse_length_m(value: &str) -> Option<f32> {
+    let value = value.trim();
+    if let Some((feet, inches)) = value.split_once('\'') {
+        let inches = inches.trim().trim_end_matches('"');
+        let inches = if inches.is_empty() {
+            0.
+        } else {
+            parse_number(inches)?
+        };
+        return Some((parse_number(feet)? * 12. + inches) * 0.0254);
+    }
+    if let Some(feet) = value.strip_suffix("ft") {
+        return Some(parse_number(feet)? * 0.3048);
+    }
+    parse_number(value.strip_suffix('m').unwrap_or(value))
+}
+
+/// Parse a `maxweight` value to tonnes. Plain numbers are tonnes, `t` and `kg` suffixes are
+/// accepted.
+pub fn parse_weight_t(value: &str) -> Option<f32> {
+    let value = value.trim();
+    if let Some(kg) = value.strip_suffix("kg") {
+        return Some(parse_number(kg)? / 1000.);
+    }
+    parse_number(value.strip_suffix('t').unwrap_or(value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_unrestricted, parse_length_m, parse_weight_t};
+
+    fn assert_close(value: Option<f32>, expected: f32) -> () {
+        let value = value.expect("value to be parsed");
+        assert!(
+            (value - expected).abs() < 0.01,
+            "{value} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn length_values() {
+        assert_close(parse_length_m("3.5"), 3.5);
+        assert_close(parse_length_m("3,5"), 3.5);
+        assert_close(parse_length_m("3.5 m"), 3.5);
+        assert_close(parse_length_m("3.5m"), 3.5);
+        assert_close(parse_length_m("12 ft"), 3.66);
+        assert_close(parse_length_m("11'6\""), 3.51);
+        assert_close(parse_length_m("11'"), 3.35);
+        assert_eq!(parse_length_m("low"), None);
+        assert_eq!(parse_length_m("-1"), None);
+        assert!(is_unrestricted("none"));
+        assert!(!is_unrestricted("3"));
+    }
+
+    #[test]
+    fn weight_values() {
+        assert_close(parse_weight_t("7.5"), 7.5);
+        assert_close(parse_weight_t("7.5 t"), 7.5);
+        assert_close(parse_weight_t("3500 kg"), 3.5);
+        assert_eq!(parse_weight_t("heavy"), None);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     cmp::{Eq, Ordering},
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt::{Debug, Display},
     hash::Hash,
     marker::PhantomData,
@@ -8,24 +8,26 @@ use std::{
     time::Instant,
 };
 
-use geo::HaversineDistance;
-use geo::Point;
-use serde::{Deserialize, Serialize};
-use tracing::info;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{info, warn};
 
 use crate::{
+    geo_utils,
     map_data::{
         osm::{OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType},
         rule::MapDataRule,
     },
-    osm_data_reader::{DataSource, OsmDataReader, ALLOWED_HIGHWAY_VALUES},
+    osm_data_reader::{get_motorcycle_access, way_tags_are_ok, OsmDataReader, OsmDataReaderError},
 };
 
 use super::{
+    dimension::{is_unrestricted, parse_length_m, parse_weight_t, DimensionLimits},
     line::{LineDirection, MapDataLine},
     osm::{OsmNode, OsmRelation, OsmWay},
+    poi::{Poi, PoiIndex},
     point::MapDataPoint,
-    proximity::PointGrid,
+    proximity::{PointGrid, PointSearchError, DEFAULT_SEARCH_RINGS},
     rule::MapDataRuleType,
     MapDataError,
 };
@@ -69,6 +71,29 @@ impl ElementTagSetRef {
     }
 }
 
+/// OSM keys that `ElementTagSet::get_tag` returns values for
+pub const ELEMENT_TAG_KEYS: [&str; 19] = [
+    "name",
+    "ref",
+    "highway",
+    "surface",
+    "smoothness",
+    "bridge",
+    "tunnel",
+    "ford",
+    "lit",
+    "maxheight",
+    "maxwidth",
+    "maxlength",
+    "maxweight",
+    "destination",
+    "destination:ref",
+    "destination:forward",
+    "destination:backward",
+    "destination:ref:forward",
+    "destination:ref:backward",
+];
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub struct ElementTagSet {
     name: ElementTagValueRef,
@@ -76,9 +101,80 @@ pub struct ElementTagSet {
     highway: ElementTagValueRef,
     surface: ElementTagValueRef,
     smoothness: ElementTagValueRef,
+    bridge: ElementTagValueRef,
+    tunnel: ElementTagValueRef,
+    ford: ElementTagValueRef,
+    lit: ElementTagValueRef,
+    /// The most specific of `MOTORCYCLE_ACCESS_TAGS` that is set
+    access: ElementTagValueRef,
+    maxheight: ElementTagValueRef,
+    maxwidth: ElementTagValueRef,
+    maxlength: ElementTagValueRef,
+    maxweight: ElementTagValueRef,
+    destination: ElementTagValueRef,
+    destination_ref: ElementTagValueRef,
+    destination_forward: ElementTagValueRef,
+    destination_backward: ElementTagValueRef,
+    destination_ref_forward: ElementTagValueRef,
+    destination_ref_backward: ElementTagValueRef,
+    /// The `max*` values parsed once when the tag set is created
+    limits: DimensionLimits,
 }
 
 impl ElementTagSet {
+    /// Number of the stored tags that have a value
+    fn known_tag_count(&self) -> usize {
+        [
+            &self.name,
+            &self.hw_ref,
+            &self.highway,
+            &self.surface,
+            &self.smoothness,
+            &self.bridge,
+            &self.tunnel,
+            &self.ford,
+            &self.lit,
+            &self.access,
+            &self.maxheight,
+            &self.maxwidth,
+            &self.maxlength,
+            &self.maxweight,
+            &self.destination,
+            &self.destination_ref,
+            &self.destination_forward,
+            &self.destination_backward,
+            &self.destination_ref_forward,
+            &self.destination_ref_backward,
+        ]
+        .iter()
+        .filter(|value| value.tag_value_pos != 0)
+        .count()
+    }
+    /// Stored value by OSM key, `None` for keys that are not in `ELEMENT_TAG_KEYS`
+    pub fn get_tag(&self, key: &str) -> Option<&smartstring::alias::String> {
+        match key {
+            "name" => self.name.borrow(),
+            "ref" => self.hw_ref.borrow(),
+            "highway" => self.highway.borrow(),
+            "surface" => self.surface.borrow(),
+            "smoothness" => self.smoothness.borrow(),
+            "bridge" => self.bridge.borrow(),
+            "tunnel" => self.tunnel.borrow(),
+            "ford" => self.ford.borrow(),
+            "lit" => self.lit.borrow(),
+            "maxheight" => self.maxheight.borrow(),
+            "maxwidth" => self.maxwidth.borrow(),
+            "maxlength" => self.maxlength.borrow(),
+            "maxweight" => self.maxweight.borrow(),
+            "destination" => self.destination.borrow(),
+            "destination:ref" => self.destination_ref.borrow(),
+            "destination:forward" => self.destination_forward.borrow(),
+            "destination:backward" => self.destination_backward.borrow(),
+            "destination:ref:forward" => self.destination_ref_forward.borrow(),
+            "destination:ref:backward" => self.destination_ref_backward.borrow(),
+            _ => None,
+        }
+    }
     pub fn name(&self) -> Option<&smartstring::alias::String> {
         self.name.borrow()
     }
@@ -88,12 +184,82 @@ impl ElementTagSet {
     pub fn highway(&self) -> Option<&smartstring::alias::String> {
         self.highway.borrow()
     }
+    /// Highway value without the `_link` suffix, `motorway_link` gives `motorway`
+    pub fn highway_class(&self) -> Option<&str> {
+        self.highway()
+            .map(|highway| highway.strip_suffix("_link").unwrap_or(highway))
+    }
+    pub fn is_link(&self) -> bool {
+        self.highway()
+            .is_some_and(|highway| highway.ends_with("_link"))
+    }
     pub fn surface(&self) -> Option<&smartstring::alias::String> {
         self.surface.borrow()
     }
     pub fn smoothness(&self) -> Option<&smartstring::alias::String> {
         self.smoothness.borrow()
     }
+    pub fn bridge(&self) -> Option<&smartstring::alias::String> {
+        self.bridge.borrow()
+    }
+    pub fn tunnel(&self) -> Option<&smartstring::alias::String> {
+        self.tunnel.borrow()
+    }
+    pub fn ford(&self) -> Option<&smartstring::alias::String> {
+        self.ford.borrow()
+    }
+    pub fn lit(&self) -> Option<&smartstring::alias::String> {
+        self.lit.borrow()
+    }
+    /// Access for a motorcycle, more specific tags like `motorcycle` override `access`
+    pub fn access(&self) -> Option<&smartstring::alias::String> {
+        self.access.borrow()
+    }
+    /// Only open to traffic going to a destination on the way
+    pub fn is_destination_access(&self) -> bool {
+        self.access()
+            .map_or(false, |access| access == "destination")
+    }
+    /// `maxheight` in metres, `None` when not restricted or the value can not be parsed
+    pub fn max_height_m(&self) -> Option<f32> {
+        self.limits.height_m
+    }
+    /// `maxwidth` in metres, `None` when not restricted or the value can not be parsed
+    pub fn max_width_m(&self) -> Option<f32> {
+        self.limits.width_m
+    }
+    /// `maxlength` in metres, `None` when not restricted or the value can not be parsed
+    pub fn max_length_m(&self) -> Option<f32> {
+        self.limits.length_m
+    }
+    /// `maxweight` in tonnes, `None` when not restricted or the value can not be parsed
+    pub fn max_weight_t(&self) -> Option<f32> {
+        self.limits.weight_t
+    }
+    /// Places the way is signed towards, `;` separated like in OSM
+    pub fn destination(&self) -> Option<&smartstring::alias::String> {
+        self.destination.borrow()
+    }
+    /// Road refs the way is signed towards from `destination:ref`
+    pub fn destination_ref(&self) -> Option<&smartstring::alias::String> {
+        self.destination_ref.borrow()
+    }
+    /// `destination:forward`, places signed when travelling in the direction of the way
+    pub fn destination_forward(&self) -> Option<&smartstring::alias::String> {
+        self.destination_forward.borrow()
+    }
+    /// `destination:backward`, places signed when travelling against the direction of the way
+    pub fn destination_backward(&self) -> Option<&smartstring::alias::String> {
+        self.destination_backward.borrow()
+    }
+    /// `destination:ref:forward`, refs signed when travelling in the direction of the way
+    pub fn destination_ref_forward(&self) -> Option<&smartstring::alias::String> {
+        self.destination_ref_forward.borrow()
+    }
+    /// `destination:ref:backward`, refs signed when travelling against the direction of the way
+    pub fn destination_ref_backward(&self) -> Option<&smartstring::alias::String> {
+        self.destination_ref_backward.borrow()
+    }
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -115,19 +281,32 @@ impl ElementTags {
         self.tag_set_map = HashMap::new();
         self.tag_map = HashMap::new();
     }
-    pub fn get_or_create(
-        &mut self,
-        name: Option<&String>,
-        hw_ref: Option<&String>,
-        highway: Option<&String>,
-        surface: Option<&String>,
-        smoothness: Option<&String>,
-    ) -> ElementTagSetRef {
-        let name_ref = self.get_tag_value_ref(name);
-        let hw_ref_ref = self.get_tag_value_ref(hw_ref);
-        let highway_ref = self.get_tag_value_ref(highway);
-        let surface_ref = self.get_tag_value_ref(surface);
-        let smoothness_ref = self.get_tag_value_ref(smoothness);
+    pub fn get_or_create(&mut self, tags: Option<&HashMap<String, String>>) -> ElementTagSetRef {
+        let get_tag = |key: &str| tags.map_or(None, |t| t.get(key));
+        let name_ref = self.get_tag_value_ref(get_tag("name"));
+        let hw_ref_ref = self.get_tag_value_ref(get_tag("ref"));
+        let highway_ref = self.get_tag_value_ref(get_tag("highway"));
+        let surface_ref = self.get_tag_value_ref(get_tag("surface"));
+        let smoothness_ref = self.get_tag_value_ref(get_tag("smoothness"));
+        let bridge_ref = self.get_tag_value_ref(get_tag("bridge"));
+        let tunnel_ref = self.get_tag_value_ref(get_tag("tunnel"));
+        let ford_ref = self.get_tag_value_ref(get_tag("ford"));
+        let lit_ref = self.get_tag_value_ref(get_tag("lit"));
+        let access =
+            get_motorcycle_access(|key| get_tag(key).map(|v| v.as_str())).map(String::from);
+        let access_ref = self.get_tag_value_ref(access.as_ref());
+        let maxheight_ref = self.get_tag_value_ref(get_tag("maxheight"));
+        let maxwidth_ref = self.get_tag_value_ref(get_tag("maxwidth"));
+        let maxlength_ref = self.get_tag_value_ref(get_tag("maxlength"));
+        let maxweight_ref = self.get_tag_value_ref(get_tag("maxweight"));
+        let destination_ref = self.get_tag_value_ref(get_tag("destination"));
+        let destination_ref_ref = self.get_tag_value_ref(get_tag("destination:ref"));
+        let destination_forward_ref = self.get_tag_value_ref(get_tag("destination:forward"));
+        let destination_backward_ref = self.get_tag_value_ref(get_tag("destination:backward"));
+        let destination_ref_forward_ref =
+            self.get_tag_value_ref(get_tag("destination:ref:forward"));
+        let destination_ref_backward_ref =
+            self.get_tag_value_ref(get_tag("destination:ref:backward"));
 
         let tag_set = ElementTagSet {
             name: name_ref,
@@ -135,10 +314,27 @@ impl ElementTags {
             highway: highway_ref,
             surface: surface_ref,
             smoothness: smoothness_ref,
+            bridge: bridge_ref,
+            tunnel: tunnel_ref,
+            ford: ford_ref,
+            lit: lit_ref,
+            access: access_ref,
+            maxheight: maxheight_ref,
+            maxwidth: maxwidth_ref,
+            maxlength: maxlength_ref,
+            maxweight: maxweight_ref,
+            destination: destination_ref,
+            destination_ref: destination_ref_ref,
+            destination_forward: destination_forward_ref,
+            destination_backward: destination_backward_ref,
+            destination_ref_forward: destination_ref_forward_ref,
+            destination_ref_backward: destination_ref_backward_ref,
+            limits: DimensionLimits::parse(get_tag),
         };
         let idx = match self.tag_set_map.get(&tag_set) {
             Some(i) => *i,
             None => {
+                ElementTags::warn_unparsed_dimensions(get_tag);
                 let new_idx = self.tag_sets.len() as u32;
                 self.tag_set_map.insert(tag_set.clone(), new_idx);
                 self.tag_sets.push(tag_set);
@@ -147,22 +343,40 @@ impl ElementTags {
         };
         ElementTagSetRef::new(idx)
     }
+    /// Unparseable dimension values are treated as unrestricted, warn about them once per
+    /// tag set
+    fn warn_unparsed_dimensions<'a, F>(get_tag: F) -> ()
+    where
+        F: Fn(&str) -> Option<&'a String>,
+    {
+        let dimensions: [(&str, fn(&str) -> Option<f32>); 4] = [
+            ("maxheight", parse_length_m),
+            ("maxwidth", parse_length_m),
+            ("maxlength", parse_length_m),
+            ("maxweight", parse_weight_t),
+        ];
+        for (tag, parse) in dimensions {
+            if let Some(value) = get_tag(tag) {
+                if !is_unrestricted(value) && parse(value).is_none() {
+                    warn!(
+                        tag,
+                        value, "could not parse value, treating it as unrestricted"
+                    );
+                }
+            }
+        }
+    }
     fn get_tag_value_ref(&mut self, value: Option<&String>) -> ElementTagValueRef {
         match value {
             None => ElementTagValueRef::none(),
             Some(v) => {
-                let v = if v.ends_with("_link") {
-                    v.replace("_link", "")
-                } else {
-                    v.to_string()
-                };
-                let idx = match self.tag_map.get(&smartstring::alias::String::from(&v)) {
+                let idx = match self.tag_map.get(&smartstring::alias::String::from(v)) {
                     Some(i) => *i,
                     None => {
                         let new_idx = self.tag_values.len() as u32;
-                        self.tag_values.push(smartstring::alias::String::from(&v));
+                        self.tag_values.push(smartstring::alias::String::from(v));
                         self.tag_map
-                            .insert(smartstring::alias::String::from(&v), new_idx);
+                            .insert(smartstring::alias::String::from(v), new_idx);
                         new_idx
                     }
                 };
@@ -186,9 +400,11 @@ impl MapDataElement for MapDataLine {
     }
 }
 
+/// Index into the graph's points or lines, stored as `u32` as these refs are held by every
+/// point, line and rule and a graph does not get close to 4 billion elements
 #[derive(Serialize, Deserialize)]
 pub struct MapDataElementRef<T: MapDataElement> {
-    idx: usize,
+    idx: u32,
     _marker: PhantomData<T>,
 }
 
@@ -201,13 +417,17 @@ impl<T: MapDataElement + 'static> Display for MapDataElementRef<T> {
 impl<T: MapDataElement> MapDataElementRef<T> {
     fn new(idx: usize) -> Self {
         Self {
-            idx,
+            idx: u32::try_from(idx).expect("element index to fit in u32"),
             _marker: PhantomData,
         }
     }
 
+    fn idx(&self) -> usize {
+        self.idx as usize
+    }
+
     pub fn borrow(&self) -> &'static T {
-        T::get(self.idx)
+        T::get(self.idx())
     }
 }
 
@@ -251,6 +471,9 @@ pub struct MapDataGraph {
     ways_lines: HashMap<u64, Vec<MapDataLineRef>>,
     lines: Vec<MapDataLine>,
     tags: ElementTags,
+    pois: PoiIndex,
+    #[serde(skip)]
+    keep_maps: bool,
 }
 
 #[derive(Default)]
@@ -259,6 +482,81 @@ pub struct MapDataGraphPacked {
     pub lines: Vec<u8>,
     pub tags: Vec<u8>,
     pub point_grid: Vec<u8>,
+    pub pois: Vec<u8>,
+}
+
+/// Summary of a loaded graph for checking that an extract was read as expected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphStats {
+    pub point_count: usize,
+    pub line_count: usize,
+    pub junction_count: usize,
+    /// Turn restrictions stored on the points
+    pub rule_count: usize,
+    /// `(min_lat, min_lon, max_lat, max_lon)` of the points with lines, `None` for an empty
+    /// graph
+    pub bounding_box: Option<(f32, f32, f32, f32)>,
+    pub highway_values: BTreeSet<String>,
+}
+
+impl Display for GraphStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "points {}, lines {}, junctions {}, rules {}, bounding box {}, highway {}",
+            self.point_count,
+            self.line_count,
+            self.junction_count,
+            self.rule_count,
+            self.bounding_box.map_or(
+                "none".to_string(),
+                |(min_lat, min_lon, max_lat, max_lon)| {
+                    format!("{min_lat},{min_lon} - {max_lat},{max_lon}")
+                }
+            ),
+            self.highway_values
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+/// Serialize `elements` in a chunk per thread, `points` and `lines` are most of the data so
+/// one thread each would leave the rest idle. Every chunk is prefixed with its length as a
+/// little endian u64 so that `unpack_chunks` can split them again
+fn pack_chunks<T: Serialize + Sync>(elements: &[T]) -> Vec<u8> {
+    let chunk_len = elements.len().div_ceil(rayon::current_num_threads()).max(1);
+    let chunks = elements
+        .par_chunks(chunk_len)
+        .map(|chunk| bincode::serialize(chunk).expect("could not serialize chunk"))
+        .collect::<Vec<_>>();
+    let mut packed = Vec::with_capacity(chunks.iter().map(|chunk| chunk.len() + 8).sum());
+    for chunk in chunks {
+        packed.extend_from_slice(&(chunk.len() as u64).to_le_bytes());
+        packed.extend(chunk);
+    }
+    packed
+}
+
+fn unpack_chunks<T: DeserializeOwned + Send>(packed: &[u8]) -> Vec<T> {
+    let mut chunks = Vec::new();
+    let mut rest = packed;
+    while !rest.is_empty() {
+        let (len, tail) = rest.split_at(8);
+        let len = u64::from_le_bytes(len.try_into().expect("chunk length must be 8 bytes"));
+        let (chunk, tail) = tail.split_at(len as usize);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+        .into_par_iter()
+        .map(|chunk| bincode::deserialize::<Vec<T>>(chunk).expect("could not deserialize chunk"))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect()
 }
 
 impl MapDataGraph {
@@ -270,9 +568,23 @@ impl MapDataGraph {
             ways_lines: HashMap::new(),
             lines: Vec::new(),
             tags: ElementTags::new(),
+            pois: PoiIndex::new(),
+            keep_maps: false,
         }
     }
 
+    /// Keep `points_map`, `ways_lines` and the tag lookup maps after `generate_point_hashes`
+    /// so that more nodes, ways and relations can be inserted into an already built graph,
+    /// e.g. when stitching two extracts together.
+    ///
+    /// The maps are normally dropped once the graph is built as they are only needed for
+    /// inserting data. Keeping them costs a map entry for every point, way, tag value and
+    /// tag set, which for a country sized extract is in the range of several hundred MB.
+    pub fn keep_maps(mut self, keep_maps: bool) -> Self {
+        self.keep_maps = keep_maps;
+        self
+    }
+
     pub fn pack(&self) -> MapDataGraphPacked {
         let pack_start = Instant::now();
 
@@ -282,22 +594,25 @@ impl MapDataGraph {
         info!("proximity_lookup len {}", self.point_grid.len(),);
         info!("lines len {}", self.lines.len());
         info!("tags len {:?}", self.tags.len());
+        info!("pois len {}", self.pois.len());
 
         rayon::scope(|scope| {
             scope.spawn(|_| {
-                packed.points =
-                    bincode::serialize(&self.points).expect("could not serialize points");
+                packed.points = pack_chunks(&self.points);
             });
             scope.spawn(|_| {
                 packed.point_grid =
                     bincode::serialize(&self.point_grid).expect("could not serialize");
             });
             scope.spawn(|_| {
-                packed.lines = bincode::serialize(&self.lines).expect("could not serialize lines");
+                packed.lines = pack_chunks(&self.lines);
             });
             scope.spawn(|_| {
                 packed.tags = bincode::serialize(&self.tags).expect("could not serialize tags");
             });
+            scope.spawn(|_| {
+                packed.pois = bincode::serialize(&self.pois).expect("could not serialize pois");
+            });
         });
 
         info!("points len {}, {}", self.points.len(), packed.points.len());
@@ -308,6 +623,7 @@ impl MapDataGraph {
         );
         info!("lines len {} {}", self.lines.len(), packed.lines.len());
         info!("tags len {:?} {}", self.tags.len(), packed.tags.len());
+        info!("pois len {} {}", self.pois.len(), packed.pois.len());
 
         let pack_end = pack_start.elapsed();
         info!("pack took {}s", pack_end.as_secs());
@@ -328,6 +644,9 @@ impl MapDataGraph {
     }
 
     pub fn insert_node(&mut self, value: OsmNode) -> () {
+        if self.points_map.contains_key(&value.id) {
+            return;
+        }
         let point = MapDataPoint {
             id: value.id,
             lat: value.lat as f32,
@@ -338,7 +657,52 @@ impl MapDataGraph {
         self.add_point(point.clone());
     }
 
+    pub fn stats(&self) -> GraphStats {
+        let connected_points = self.points.iter().filter(|point| !point.lines.is_empty());
+        let bounding_box = connected_points.clone().fold(None, |bbox, point| {
+            Some(bbox.map_or(
+                (point.lat, point.lon, point.lat, point.lon),
+                |(min_lat, min_lon, max_lat, max_lon): (f32, f32, f32, f32)| {
+                    (
+                        min_lat.min(point.lat),
+                        min_lon.min(point.lon),
+                        max_lat.max(point.lat),
+                        max_lon.max(point.lon),
+                    )
+                },
+            ))
+        });
+        GraphStats {
+            point_count: self.points.len(),
+            line_count: self.lines.len(),
+            junction_count: connected_points.filter(|point| point.is_junction()).count(),
+            rule_count: self.points.iter().map(|point| point.rules.len()).sum(),
+            bounding_box,
+            highway_values: self
+                .lines
+                .iter()
+                .filter_map(|line| {
+                    self.tags.tag_sets[line.tags.tag_set_idx as usize]
+                        .highway()
+                        .map(|v| v.to_string())
+                })
+                .collect(),
+        }
+    }
+
+    /// Add a POI to the POI index, POIs are not connected to the routing graph
+    pub fn insert_poi(&mut self, poi: Poi) -> () {
+        self.pois.insert(poi);
+    }
+
+    /// Only used by tests for now, the router does not query POIs yet
+    #[cfg(test)]
+    pub fn get_pois(&self) -> &PoiIndex {
+        &self.pois
+    }
+
     pub fn generate_point_hashes(&mut self) -> () {
+        self.point_grid = PointGrid::new();
         for point in self.points.iter().filter(|p| !p.lines.is_empty()) {
             let point_idx = self
                 .points_map
@@ -347,7 +711,7 @@ impl MapDataGraph {
             let point_ref = MapDataElementRef::new(*point_idx);
             self.point_grid.insert(point.lat, point.lon, point_ref);
         }
-        if !cfg!(test) {
+        if !cfg!(test) && !self.keep_maps {
             self.points_map = HashMap::new();
             self.ways_lines = HashMap::new();
             self.tags.clear_maps();
@@ -369,75 +733,91 @@ impl MapDataGraph {
     }
 
     fn way_is_ok(&self, osm_way: &OsmWay) -> bool {
-        if let Some(tags) = &osm_way.tags {
-            if tags.get("service").is_some() {
-                return false;
-            }
-            if let Some(access) = tags.get("access") {
-                if access == "no" || access == "private" {
-                    return false;
-                }
-            }
-            if let Some(motor_vehicle) = tags.get("motor_vehicle") {
-                if motor_vehicle == "private" || motor_vehicle == "no" {
-                    return false;
-                }
-            }
-            let motorcycle = match tags.get("motorcycle") {
-                Some(v) => v == "yes",
-                None => false,
-            };
+        osm_way.tags.as_ref().map_or(false, |tags| {
+            way_tags_are_ok(|key| tags.get(key).map(|v| v.as_str()))
+        })
+    }
 
-            if let Some(highway) = tags.get("highway") {
-                return ALLOWED_HIGHWAY_VALUES.contains(&highway.as_str())
-                    && (highway != "path" || (highway == "path" && motorcycle));
-            }
+    pub fn insert_way(&mut self, osm_way: OsmWay) -> Result<(), MapDataError> {
+        if !self.way_is_ok(&osm_way) || self.ways_lines.contains_key(&osm_way.id) {
+            return Ok(());
         }
-        false
+        let way_line_refs = self.insert_way_lines(&osm_way, &osm_way.point_ids)?;
+        self.ways_lines.insert(osm_way.id, way_line_refs);
+
+        Ok(())
     }
 
-    pub fn insert_way(&mut self, osm_way: OsmWay) -> Result<(), MapDataError> {
-        if !self.way_is_ok(&osm_way) {
+    /// Like `insert_way` but nodes that were not inserted, e.g. outside of the read bounding
+    /// box, cut the way instead of failing it. Only the parts with at least 2 known nodes are
+    /// inserted
+    pub fn insert_way_clipped(&mut self, osm_way: OsmWay) -> Result<(), MapDataError> {
+        if !self.way_is_ok(&osm_way) || self.ways_lines.contains_key(&osm_way.id) {
+            return Ok(());
+        }
+        let parts = osm_way
+            .point_ids
+            .split(|point_id| !self.points_map.contains_key(point_id))
+            .filter(|part| part.len() > 1)
+            .map(|part| part.to_vec())
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
             return Ok(());
         }
+        let mut way_line_refs = Vec::new();
+        for part in parts {
+            way_line_refs.extend(self.insert_way_lines(&osm_way, &part)?);
+        }
+        self.ways_lines.insert(osm_way.id, way_line_refs);
+
+        Ok(())
+    }
+
+    fn insert_way_lines(
+        &mut self,
+        osm_way: &OsmWay,
+        point_ids: &[u64],
+    ) -> Result<Vec<MapDataLineRef>, MapDataError> {
         let mut prev_point_ref: Option<MapDataPointRef> = None;
 
         let mut way_line_refs = Vec::new();
-        for point_id in &osm_way.point_ids {
+        for point_id in point_ids {
             if let Some(point_ref) = self.get_point_ref_by_id(&point_id) {
                 if let Some(prev_point_ref) = prev_point_ref {
-                    let tag_name = osm_way.tags.as_ref().map_or(None, |t| t.get("name"));
-                    let tag_ref = osm_way.tags.as_ref().map_or(None, |t| t.get("ref"));
-                    let tag_surface = osm_way.tags.as_ref().map_or(None, |t| t.get("surface"));
-                    let tag_smoothness =
-                        osm_way.tags.as_ref().map_or(None, |t| t.get("smoothness"));
-                    let tag_highway = osm_way.tags.as_ref().map_or(None, |t| t.get("highway"));
-                    let line = MapDataLine {
-                        points: (prev_point_ref.clone(), point_ref.clone()),
-                        direction: if osm_way.is_roundabout() {
-                            LineDirection::Roundabout
-                        } else if osm_way.is_one_way() {
-                            LineDirection::OneWay
-                        } else {
-                            LineDirection::BothWays
-                        },
-                        tags: self.tags.get_or_create(
-                            tag_name,
-                            tag_ref,
-                            tag_highway,
-                            tag_surface,
-                            tag_smoothness,
-                        ),
+                    let direction = if osm_way.is_roundabout() {
+                        LineDirection::Roundabout
+                    } else if osm_way.is_one_way() {
+                        LineDirection::OneWay
+                    } else {
+                        LineDirection::BothWays
                     };
-                    let line_idx = self.add_line(line);
-                    let line_ref = MapDataLineRef::new(line_idx);
-                    way_line_refs.push(line_ref.clone());
-
-                    let point_mut = self.get_mut_point_by_idx(point_ref.idx);
-                    point_mut.lines.push(line_ref.clone());
-
-                    let prev_point_mut = self.get_mut_point_by_idx(prev_point_ref.idx);
-                    prev_point_mut.lines.push(line_ref);
+                    let tags = self.tags.get_or_create(osm_way.tags.as_ref());
+                    if let Some(line_ref) =
+                        self.get_duplicate_line(&prev_point_ref, &point_ref, &direction)
+                    {
+                        self.merge_duplicate_line(&line_ref, &prev_point_ref, osm_way.id, tags);
+                        way_line_refs.push(line_ref);
+                    } else {
+                        let line = MapDataLine {
+                            way_id: osm_way.id,
+                            length_m: MapDataLine::calc_len_m(
+                                &self.points[prev_point_ref.idx()],
+                                &self.points[point_ref.idx()],
+                            ),
+                            points: (prev_point_ref.clone(), point_ref.clone()),
+                            direction,
+                            tags,
+                        };
+                        let line_idx = self.add_line(line);
+                        let line_ref = MapDataLineRef::new(line_idx);
+                        way_line_refs.push(line_ref.clone());
+
+                        let point_mut = self.get_mut_point_by_idx(point_ref.idx());
+                        point_mut.lines.push(line_ref.clone());
+
+                        let prev_point_mut = self.get_mut_point_by_idx(prev_point_ref.idx());
+                        prev_point_mut.lines.push(line_ref);
+                    }
                 }
                 prev_point_ref = Some(point_ref);
             } else {
@@ -446,9 +826,53 @@ impl MapDataGraph {
                 });
             }
         }
-        self.ways_lines.insert(osm_way.id, way_line_refs);
 
-        Ok(())
+        Ok(way_line_refs)
+    }
+
+    /// Line that was already inserted between the same points, e.g. from a way that was split
+    /// for tagging, that can be travelled the same way as a new line from `from` to `to`
+    fn get_duplicate_line(
+        &self,
+        from: &MapDataPointRef,
+        to: &MapDataPointRef,
+        direction: &LineDirection,
+    ) -> Option<MapDataLineRef> {
+        self.points[from.idx()]
+            .lines
+            .iter()
+            .find(|line_ref| {
+                let line = &self.lines[line_ref.idx()];
+                line.direction == *direction
+                    && ((line.points.0 == *from && line.points.1 == *to)
+                        || (*direction == LineDirection::BothWays
+                            && line.points.0 == *to
+                            && line.points.1 == *from))
+            })
+            .cloned()
+    }
+
+    /// Keep one line for duplicates, it takes the `way_id` and the tags of the same way, the one
+    /// with more known tags set or the first inserted one when they have the same count. The
+    /// points follow that way, so `:forward` and `:backward` tags keep their meaning
+    fn merge_duplicate_line(
+        &mut self,
+        line_ref: &MapDataLineRef,
+        from: &MapDataPointRef,
+        way_id: u64,
+        tags: ElementTagSetRef,
+    ) {
+        let existing = &self.lines[line_ref.idx()].tags;
+        if self.tags.tag_sets[tags.tag_set_idx as usize].known_tag_count()
+            > self.tags.tag_sets[existing.tag_set_idx as usize].known_tag_count()
+        {
+            let line = &mut self.lines[line_ref.idx()];
+            line.way_id = way_id;
+            line.tags = tags;
+            if line.points.0 != *from {
+                line.points = (line.points.1.clone(), line.points.0.clone());
+            }
+        }
     }
 
     fn relation_is_ok(&self, relation: &OsmRelation) -> bool {
@@ -552,7 +976,7 @@ impl MapDataGraph {
                 },
             )?;
 
-            let point = self.get_mut_point_by_idx(via_point.idx);
+            let point = self.get_mut_point_by_idx(via_point.idx());
             let rule = MapDataRule {
                 from_lines,
                 to_lines,
@@ -589,20 +1013,69 @@ impl MapDataGraph {
             .collect()
     }
 
+    /// `get_adjacent` without the lines that can not be entered from `center_point`, one-way
+    /// lines against their direction and, when arriving on `from_line`, the lines the turn
+    /// restrictions at the point do not allow. Going back on `from_line` is not dropped.
+    pub fn get_adjacent_directed(
+        &self,
+        center_point: MapDataPointRef,
+        from_line: Option<&MapDataLineRef>,
+    ) -> Vec<(MapDataLineRef, MapDataPointRef)> {
+        let rules = center_point
+            .borrow()
+            .rules
+            .iter()
+            .filter(|rule| from_line.map_or(false, |line| rule.from_lines.contains(line)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let only_allowed = rules
+            .iter()
+            .filter(|rule| rule.rule_type == MapDataRuleType::OnlyAllowed)
+            .collect::<Vec<_>>();
+
+        self.get_adjacent(center_point.clone())
+            .into_iter()
+            .filter(|(line, _)| {
+                if line.borrow().is_one_way() && line.borrow().points.1 == center_point {
+                    return false;
+                }
+                if rules.iter().any(|rule| {
+                    rule.rule_type == MapDataRuleType::NotAllowed && rule.to_lines.contains(line)
+                }) {
+                    return false;
+                }
+                only_allowed.is_empty()
+                    || only_allowed.iter().any(|rule| rule.to_lines.contains(line))
+            })
+            .collect()
+    }
+
     pub fn get_closest_to_coords(&self, lat: f32, lon: f32) -> Option<MapDataPointRef> {
-        let closest_points = self.point_grid.find_closest_point_refs(lat, lon);
-        let closest_points = match closest_points {
-            Some(p) => p,
-            None => return None,
-        };
+        self.get_closest_to_coords_filtered(lat, lon, DEFAULT_SEARCH_RINGS, |_| true)
+            .ok()
+    }
+
+    /// Find the closest point that passes `filter`, widening the search up to `max_rings`
+    /// cell rings when all the nearby points are filtered out.
+    pub fn get_closest_to_coords_filtered<F>(
+        &self,
+        lat: f32,
+        lon: f32,
+        max_rings: u16,
+        filter: F,
+    ) -> Result<MapDataPointRef, PointSearchError>
+    where
+        F: Fn(&MapDataPointRef) -> bool,
+    {
+        let closest_points = self
+            .point_grid
+            .find_closest_point_refs_filtered(lat, lon, max_rings, filter)?;
 
         let mut distances = closest_points
             .iter()
             .map(|p| {
-                let point = &self.points[p.idx];
-                let geo_point = Point::new(point.lon, point.lat);
-                let geo_lookup_point = Point::new(lon, lat);
-                (p, geo_point.haversine_distance(&geo_lookup_point))
+                let point = &self.points[p.idx()];
+                (p, geo_utils::distance((point.lat, point.lon), (lat, lon)))
             })
             .collect::<Vec<(&MapDataPointRef, f32)>>();
         distances.sort_by(|el1, el2| {
@@ -615,8 +1088,29 @@ impl MapDataGraph {
             }
         });
 
-        distances.get(0).map_or(None, |v| Some(v.0.clone()))
+        distances
+            .get(0)
+            .map(|v| v.0.clone())
+            .ok_or(PointSearchError::NoPointsNearby)
+    }
+
+    /// Find a routable point by its OSM node id.
+    ///
+    /// Uses `points_map` when it is still available and falls back to a linear scan over all
+    /// points when the maps have been dropped after `generate_point_hashes` or `unpack`.
+    /// Points that are not part of any line are not routable and are not returned.
+    pub fn get_point_ref_by_osm_id(&self, id: u64) -> Option<MapDataPointRef> {
+        let idx = if self.points_map.is_empty() {
+            self.points.iter().position(|point| point.id == id)
+        } else {
+            self.points_map.get(&id).copied()
+        }?;
+        if self.points[idx].lines.is_empty() {
+            return None;
+        }
+        Some(MapDataElementRef::new(idx))
     }
+
     #[tracing::instrument(skip(packed))]
     pub fn unpack(packed: MapDataGraphPacked) -> &'static MapDataGraph {
         let mut points = Vec::new();
@@ -625,13 +1119,13 @@ impl MapDataGraph {
         let ways_lines = HashMap::new();
         let mut lines = Vec::new();
         let mut tags = ElementTags::new();
+        let mut pois = PoiIndex::new();
 
         let unpack_start = Instant::now();
         rayon::scope(|scope| {
             scope.spawn(|_| {
                 let start = Instant::now();
-                points =
-                    bincode::deserialize(&packed.points[..]).expect("could not deserialize points");
+                points = unpack_chunks(&packed.points[..]);
                 let dur = start.elapsed();
                 info!("points {}s", dur.as_secs());
             });
@@ -644,8 +1138,7 @@ impl MapDataGraph {
             });
             scope.spawn(|_| {
                 let start = Instant::now();
-                lines =
-                    bincode::deserialize(&packed.lines[..]).expect("could not deserialize lines");
+                lines = unpack_chunks(&packed.lines[..]);
                 let dur = start.elapsed();
                 info!("lines {}s", dur.as_secs());
             });
@@ -655,6 +1148,12 @@ impl MapDataGraph {
                 let dur = start.elapsed();
                 info!("tags {}s", dur.as_secs());
             });
+            scope.spawn(|_| {
+                let start = Instant::now();
+                pois = bincode::deserialize(&packed.pois[..]).expect("could not deserialize pois");
+                let dur = start.elapsed();
+                info!("pois {}s", dur.as_secs());
+            });
         });
         let unpack_duration = unpack_start.elapsed();
         info!("unpack took {}s", unpack_duration.as_secs());
@@ -662,6 +1161,7 @@ impl MapDataGraph {
         info!("point_grid {}", point_grid.len());
         info!("lines {}", lines.len());
         info!("tags {:?}", tags.len());
+        info!("pois {}", pois.len());
 
         MAP_DATA_GRAPH.get_or_init(|| MapDataGraph {
             points,
@@ -670,24 +1170,22 @@ impl MapDataGraph {
             lines,
             ways_lines,
             tags,
+            pois,
+            keep_maps: false,
         })
     }
 
-    fn get_or_init(data_source: Option<&DataSource>) -> &'static MapDataGraph {
-        MAP_DATA_GRAPH.get_or_init(|| {
-            let data_source = data_source.expect("data source must passed in when calling init");
-            let data_reader = OsmDataReader::new(data_source.clone());
-            let map_data = data_reader.read_data().unwrap();
-
-            map_data
-        })
-    }
-    #[tracing::instrument]
-    pub fn init(data_source: &DataSource) -> () {
-        MapDataGraph::get_or_init(Some(data_source));
+    /// Read the data with the configured reader into the global graph
+    #[tracing::instrument(skip_all)]
+    pub fn init(data_reader: OsmDataReader) -> Result<(), OsmDataReaderError> {
+        let map_data = data_reader.read_data()?;
+        MAP_DATA_GRAPH.get_or_init(|| map_data);
+        Ok(())
     }
     pub fn get() -> &'static MapDataGraph {
-        MapDataGraph::get_or_init(None) // we've already initialized the graph
+        MAP_DATA_GRAPH
+            .get()
+            .expect("map data graph must be initialized before use")
     }
 }
 
@@ -696,12 +1194,96 @@ mod tests {
     use core::panic;
     use std::{collections::HashSet, u8};
 
+    use geo::{HaversineDistance, Point};
     use rusty_fork::rusty_fork_test;
 
-    use crate::test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1};
+    use crate::test_utils::{
+        graph_from_test_dataset, make_osm_way, set_graph_static, test_dataset_1, TestGraphBuilder,
+    };
 
     use super::*;
 
+    #[test]
+    fn pack_chunks_roundtrip() {
+        let elements = (0..1000u64).collect::<Vec<_>>();
+        assert_eq!(unpack_chunks::<u64>(&pack_chunks(&elements)), elements);
+        assert_eq!(unpack_chunks::<u64>(&pack_chunks(&elements[..1])), vec![0]);
+        assert!(unpack_chunks::<u64>(&pack_chunks::<u64>(&[])).is_empty());
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn adjacent_directed() {
+            //      1
+            //      |
+            // 5 -- 2 -- 4
+            //      ^
+            //      3
+            let restriction = |id, from, to, restriction: &str| OsmRelation {
+                id,
+                members: vec![
+                    OsmRelationMember {
+                        member_ref: from,
+                        role: OsmRelationMemberRole::From,
+                        member_type: OsmRelationMemberType::Way,
+                    },
+                    OsmRelationMember {
+                        member_ref: 2,
+                        role: OsmRelationMemberRole::Via,
+                        member_type: OsmRelationMemberType::Node,
+                    },
+                    OsmRelationMember {
+                        member_ref: to,
+                        role: OsmRelationMemberRole::To,
+                        member_type: OsmRelationMemberType::Way,
+                    },
+                ],
+                tags: HashMap::from([
+                    ("type".to_string(), "restriction".to_string()),
+                    ("restriction".to_string(), restriction.to_string()),
+                ]),
+            };
+            let primary = [("highway", "primary")];
+            let map_data = TestGraphBuilder::new()
+                .nodes_at(&[(1, 1., 0.), (2, 0., 0.), (3, -1., 0.), (4, 0., 1.), (5, 0., -1.)])
+                .way(12, &[1, 2], &primary)
+                .way(32, &[3, 2], &[("highway", "primary"), ("oneway", "yes")])
+                .way(24, &[2, 4], &primary)
+                .way(25, &[2, 5], &primary)
+                .relation(restriction(1, 12, 24, "no_left_turn"))
+                .relation(restriction(2, 25, 12, "only_right_turn"))
+                .build();
+            set_graph_static(map_data);
+            let map_data = MapDataGraph::get();
+            let center = map_data.test_get_point_ref_by_id(&2).unwrap();
+            let line_to = |id| {
+                map_data
+                    .get_adjacent(center.clone())
+                    .into_iter()
+                    .find(|(_, point)| point.borrow().id == id)
+                    .unwrap()
+                    .0
+            };
+            let adjacent_ids = |from_line: Option<&MapDataLineRef>| {
+                let mut ids = map_data
+                    .get_adjacent_directed(center.clone(), from_line)
+                    .iter()
+                    .map(|(_, point)| point.borrow().id)
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            };
+
+            assert_eq!(map_data.get_adjacent(center.clone()).len(), 4);
+            // 2 -> 3 is against the one-way
+            assert_eq!(adjacent_ids(None), vec![1, 4, 5]);
+            assert_eq!(adjacent_ids(Some(&line_to(3))), vec![1, 4, 5]);
+            assert_eq!(adjacent_ids(Some(&line_to(1))), vec![1, 5]);
+            assert_eq!(adjacent_ids(Some(&line_to(5))), vec![1]);
+        }
+    }
+
     #[test]
     fn check_way_ok() {
         let map_data = MapDataGraph::new();
@@ -779,6 +1361,17 @@ mod tests {
 
         assert_eq!(map_data.way_is_ok(&osm_way), false);
 
+        let osm_way = OsmWay {
+            id: 1,
+            point_ids: Vec::new(),
+            tags: Some(HashMap::from([
+                ("highway".to_string(), "path".to_string()),
+                ("motorcycle".to_string(), "yes".to_string()),
+            ])),
+        };
+
+        assert_eq!(map_data.way_is_ok(&osm_way), true);
+
         let osm_way = OsmWay {
             id: 1,
             point_ids: Vec::new(),
@@ -876,7 +1469,7 @@ mod tests {
             ])),
         };
 
-        assert_eq!(map_data.way_is_ok(&osm_way), false);
+        assert_eq!(map_data.way_is_ok(&osm_way), true);
 
         let osm_way = OsmWay {
             id: 1,
@@ -888,7 +1481,117 @@ mod tests {
             ])),
         };
 
-        assert_eq!(map_data.way_is_ok(&osm_way), false);
+        assert_eq!(map_data.way_is_ok(&osm_way), true);
+    }
+
+    #[test]
+    fn insert_into_built_graph() {
+        let (nodes, ways, _) = test_dataset_1();
+        let mut map_data = MapDataGraph::new().keep_maps(true);
+        map_data.insert_node(nodes[0].clone());
+        map_data.insert_node(nodes[1].clone());
+        map_data
+            .insert_way(OsmWay {
+                id: 1,
+                point_ids: vec![nodes[0].id, nodes[1].id],
+                tags: ways[0].tags.clone(),
+            })
+            .unwrap();
+        map_data.generate_point_hashes();
+        assert_eq!(map_data.points.len(), 2);
+        assert_eq!(map_data.lines.len(), 1);
+
+        // second batch overlaps with the first one
+        map_data.insert_node(nodes[1].clone());
+        map_data.insert_node(nodes[2].clone());
+        map_data
+            .insert_way(OsmWay {
+                id: 1,
+                point_ids: vec![nodes[0].id, nodes[1].id],
+                tags: ways[0].tags.clone(),
+            })
+            .unwrap();
+        map_data
+            .insert_way(OsmWay {
+                id: 2,
+                point_ids: vec![nodes[1].id, nodes[2].id],
+                tags: ways[0].tags.clone(),
+            })
+            .unwrap();
+        map_data.generate_point_hashes();
+        assert_eq!(map_data.points.len(), 3);
+        assert_eq!(map_data.lines.len(), 2);
+        let point_2 = map_data.get_point_ref_by_id(&nodes[1].id).unwrap();
+        assert_eq!(map_data.points[point_2.idx()].lines.len(), 2);
+        let closest = map_data
+            .point_grid
+            .find_closest_point_refs_filtered(
+                nodes[2].lat as f32,
+                nodes[2].lon as f32,
+                DEFAULT_SEARCH_RINGS,
+                |_| true,
+            )
+            .unwrap();
+        assert_eq!(closest.len(), 1);
+    }
+
+    #[test]
+    fn element_refs_are_u32() {
+        assert_eq!(std::mem::size_of::<MapDataPointRef>(), 4);
+        assert_eq!(std::mem::size_of::<MapDataLineRef>(), 4);
+        assert_eq!(MapDataPointRef::new(12).idx(), 12);
+    }
+
+    #[test]
+    fn line_length_stored() {
+        let map_data = graph_from_test_dataset(test_dataset_1());
+        for line in &map_data.lines {
+            let point_1 = &map_data.points[line.points.0.idx()];
+            let point_2 = &map_data.points[line.points.1.idx()];
+            let expected = Point::new(point_1.lon, point_1.lat)
+                .haversine_distance(&Point::new(point_2.lon, point_2.lat));
+            assert!(line.length_m > 0.);
+            assert_eq!(line.length_m, expected);
+        }
+    }
+
+    #[test]
+    fn line_way_id_stored() {
+        let (nodes, ways, relations) = test_dataset_1();
+        let map_data = graph_from_test_dataset((nodes, ways.clone(), relations));
+        for line in &map_data.lines {
+            let way = ways.iter().find(|way| way.id == line.way_id).unwrap();
+            let point_ids = (
+                map_data.points[line.points.0.idx()].id,
+                map_data.points[line.points.1.idx()].id,
+            );
+            assert!(way
+                .point_ids
+                .windows(2)
+                .any(|ids| (ids[0], ids[1]) == point_ids));
+        }
+    }
+
+    #[test]
+    fn get_point_ref_by_osm_id() {
+        let mut map_data = graph_from_test_dataset(test_dataset_1());
+        map_data.insert_node(OsmNode {
+            id: 999,
+            lat: 1.,
+            lon: 1.,
+        });
+        map_data.generate_point_hashes();
+
+        let point = map_data.get_point_ref_by_osm_id(2).unwrap();
+        assert_eq!(map_data.points[point.idx()].id, 2);
+        assert!(map_data.get_point_ref_by_osm_id(999).is_none());
+        assert!(map_data.get_point_ref_by_osm_id(123456).is_none());
+
+        map_data.points_map = HashMap::new();
+        let point = map_data.get_point_ref_by_osm_id(2).unwrap();
+        assert_eq!(map_data.points[point.idx()].id, 2);
+        assert!(map_data.get_point_ref_by_osm_id(999).is_none());
+        assert!(map_data.get_point_ref_by_osm_id(123456).is_none());
     }
 
     #[derive(Debug)]
@@ -1036,6 +1739,71 @@ mod tests {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn duplicate_lines_are_merged() {
+            let mut map_data = MapDataGraph::new();
+            for id in [1, 2] {
+                map_data.insert_node(OsmNode {
+                    id,
+                    lat: id as f64,
+                    lon: id as f64,
+                });
+            }
+            map_data
+                .insert_way(make_osm_way(1, &[1, 2], &[("highway", "primary")]))
+                .unwrap();
+            map_data
+                .insert_way(make_osm_way(
+                    2,
+                    &[2, 1],
+                    &[("highway", "primary"), ("surface", "asphalt")],
+                ))
+                .unwrap();
+            map_data
+                .insert_way(make_osm_way(
+                    3,
+                    &[1, 2],
+                    &[("highway", "primary"), ("oneway", "yes")],
+                ))
+                .unwrap();
+            assert_eq!(map_data.lines.len(), 2);
+            assert_eq!(map_data.ways_lines[&1], map_data.ways_lines[&2]);
+            assert_ne!(map_data.ways_lines[&1], map_data.ways_lines[&3]);
+
+            let map_data = set_graph_static(map_data);
+            let point = map_data.test_get_point_ref_by_id(&1).unwrap();
+            assert_eq!(point.borrow().lines.len(), 2);
+            assert_eq!(map_data.get_adjacent(point).len(), 2);
+            let merged = map_data.ways_lines[&1][0].borrow();
+            assert_eq!(merged.way_id, 2);
+            assert_eq!(merged.points.0.borrow().id, 2);
+            assert_eq!(
+                merged.tags.borrow().surface().map(|v| v.as_str()),
+                Some("asphalt")
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn graph_stats() {
+            let stats = set_graph_static(graph_from_test_dataset(test_dataset_1())).stats();
+            assert_eq!(stats.point_count, 11);
+            assert_eq!(stats.line_count, 10);
+            assert_eq!(stats.junction_count, 3);
+            assert_eq!(stats.rule_count, 0);
+            assert_eq!(stats.bounding_box, Some((1., 1., 12., 12.)));
+            assert_eq!(
+                stats.highway_values.into_iter().collect::<Vec<_>>(),
+                vec!["primary".to_string()]
+            );
+            assert_eq!(MapDataGraph::new().stats().bounding_box, None);
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -1319,4 +2087,50 @@ mod tests {
             run_closest_test(CLOSEST_TESTS[2].clone());
         }
     }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn closest_lookup_filtered() {
+            let (points, check_point, _) = CLOSEST_TESTS[2].clone();
+            let mut map_data = MapDataGraph::new();
+            for point in points.iter().flatten() {
+                map_data.insert_node(point.clone());
+                map_data
+                    .insert_way(OsmWay {
+                        id: point.id,
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            "primary".to_string(),
+                        )])),
+                        point_ids: vec![point.id, point.id],
+                    })
+                    .expect("failed to insert dummy way");
+            }
+            map_data.generate_point_hashes();
+            let map_data = set_graph_static(map_data);
+            let lat = check_point.lat as f32;
+            let lon = check_point.lon as f32;
+
+            let closest = map_data
+                .get_closest_to_coords_filtered(lat, lon, DEFAULT_SEARCH_RINGS, |point| {
+                    point.borrow().id != 2
+                })
+                .unwrap();
+            assert_eq!(closest.borrow().id, 1);
+
+            assert_eq!(
+                map_data
+                    .get_closest_to_coords_filtered(lat, lon, DEFAULT_SEARCH_RINGS, |_| false)
+                    .unwrap_err(),
+                PointSearchError::AllPointsFiltered { filtered: 2 }
+            );
+
+            assert_eq!(
+                map_data
+                    .get_closest_to_coords_filtered(lat + 1., lon, DEFAULT_SEARCH_RINGS, |_| true)
+                    .unwrap_err(),
+                PointSearchError::NoPointsNearby
+            );
+        }
+    }
 }
@@ -1,9 +1,14 @@
 use std::fmt::{Debug, Display};
 
-use geo::{HaversineDistance, Point};
+use geo::{GeodesicDistance, Point};
 use serde::{Deserialize, Serialize};
 
-use super::graph::{ElementTagSetRef, MapDataPointRef};
+use crate::geo_utils;
+
+use super::{
+    graph::{ElementTagSetRef, MapDataPointRef},
+    point::MapDataPoint,
+};
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum LineDirection {
@@ -15,9 +20,14 @@ pub enum LineDirection {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MapDataLine {
     // pub id: String,
+    /// OSM id of the way the line was created from
+    pub way_id: u64,
+    /// In the direction of the way `way_id`
     pub points: (MapDataPointRef, MapDataPointRef),
     pub direction: LineDirection,
     pub tags: ElementTagSetRef,
+    /// Haversine length, calculated once when the line is created
+    pub length_m: f32,
 }
 impl Display for MapDataLine {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -38,13 +48,34 @@ impl MapDataLine {
     pub fn is_roundabout(&self) -> bool {
         self.direction == LineDirection::Roundabout
     }
+    pub fn bridge(&self) -> Option<&smartstring::alias::String> {
+        self.tags.borrow().bridge()
+    }
+    pub fn tunnel(&self) -> Option<&smartstring::alias::String> {
+        self.tags.borrow().tunnel()
+    }
+    pub fn ford(&self) -> Option<&smartstring::alias::String> {
+        self.tags.borrow().ford()
+    }
+    pub fn lit(&self) -> Option<&smartstring::alias::String> {
+        self.tags.borrow().lit()
+    }
+    pub fn is_destination_access(&self) -> bool {
+        self.tags.borrow().is_destination_access()
+    }
+    pub fn calc_len_m(point_1: &MapDataPoint, point_2: &MapDataPoint) -> f32 {
+        geo_utils::distance((point_1.lat, point_1.lon), (point_2.lat, point_2.lon))
+    }
     pub fn get_len_m(&self) -> f32 {
+        self.length_m
+    }
+    pub fn get_geodesic_len_m(&self) -> f64 {
         let point_1 = self.points.0.borrow();
         let point_2 = self.points.1.borrow();
-        let geo_point_1 = Point::new(point_1.lon, point_1.lat);
-        let geo_point_2 = Point::new(point_2.lon, point_2.lat);
+        let geo_point_1 = Point::new(point_1.lon as f64, point_1.lat as f64);
+        let geo_point_2 = Point::new(point_2.lon as f64, point_2.lat as f64);
 
-        geo_point_1.haversine_distance(&geo_point_2)
+        geo_point_1.geodesic_distance(&geo_point_2)
     }
 }
 
@@ -60,10 +91,12 @@ impl Debug for MapDataLine {
             f,
             "MapDataLine
     id={}
+    way_id={}
     points=({},{})
     one_way={}
     roundabout={}",
             self.line_id(),
+            self.way_id,
             self.points.0.borrow().id,
             self.points.1.borrow().id,
             self.is_one_way(),
@@ -1,10 +1,12 @@
 use self::osm::OsmRelation;
 
+pub mod dimension;
 pub mod graph;
 pub mod line;
 pub mod osm;
+pub mod poi;
 pub mod point;
-mod proximity;
+pub mod proximity;
 pub mod rule;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::geo_utils;
+
+use super::proximity::PointGrid;
+
+/// Height of a `PointGrid` cell in metres, cells are 0.01 degrees on each side
+#[cfg(test)]
+const CELL_HEIGHT_M: f32 = 1113.;
+
+/// Point of interest that is not part of the routing graph, e.g. a fuel station
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Poi {
+    pub id: u64,
+    pub lat: f32,
+    pub lon: f32,
+    /// The capture the node matched, as `key=value`
+    pub kind: String,
+    pub name: Option<String>,
+}
+
+/// Node tag to capture as a POI, `key=value` or only `key` to capture any value
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoiCapture {
+    key: String,
+    value: Option<String>,
+}
+
+impl PoiCapture {
+    pub fn parse(capture: &str) -> Self {
+        match capture.split_once('=') {
+            Some((key, value)) => Self {
+                key: key.trim().to_string(),
+                value: Some(value.trim().to_string()),
+            },
+            None => Self {
+                key: capture.trim().to_string(),
+                value: None,
+            },
+        }
+    }
+
+    /// Returns the POI kind when the tags match the capture
+    pub fn get_kind<'a, F>(&self, get_tag: F) -> Option<String>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let tag_value = get_tag(&self.key)?;
+        match &self.value {
+            Some(value) if value != tag_value => None,
+            _ => Some(format!("{}={}", self.key, tag_value)),
+        }
+    }
+}
+
+/// Returns the kind of the first capture the tags match
+pub fn get_poi_kind<'a, F>(captures: &[PoiCapture], get_tag: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    captures
+        .iter()
+        .find_map(|capture| capture.get_kind(&get_tag))
+}
+
+/// POIs are only stored in the map data and the cache for now, nothing in the router queries
+/// them yet
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PoiIndex {
+    pois: Vec<Poi>,
+    grid: PointGrid<usize>,
+}
+
+impl PoiIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pois.len()
+    }
+
+    pub fn insert(&mut self, poi: Poi) -> () {
+        self.grid.insert(poi.lat, poi.lon, self.pois.len());
+        self.pois.push(poi);
+    }
+
+    /// POIs within `radius_m` of the coordinates, closest first
+    #[cfg(test)]
+    pub fn find_points_within_radius(&self, lat: f32, lon: f32, radius_m: f32) -> Vec<&Poi> {
+        let cell_width_m = CELL_HEIGHT_M * lat.to_radians().cos().max(0.01);
+        let rings = (radius_m / cell_width_m).ceil().min(u16::MAX as f32) as u16;
+        let mut pois = self
+            .grid
+            .get_points_within_rings(lat, lon, rings)
+            .into_iter()
+            .map(|idx| {
+                let poi = &self.pois[idx];
+                (poi, geo_utils::distance((lat, lon), (poi.lat, poi.lon)))
+            })
+            .filter(|(_, distance)| *distance <= radius_m)
+            .collect::<Vec<_>>();
+        pois.sort_by(|a, b| a.1.total_cmp(&b.1));
+        pois.into_iter().map(|(poi, _)| poi).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_poi_kind, Poi, PoiCapture, PoiIndex};
+
+    fn poi(id: u64, lat: f32, lon: f32) -> Poi {
+        Poi {
+            id,
+            lat,
+            lon,
+            kind: String::from("amenity=fuel"),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn poi_capture() {
+        let captures = vec![
+            PoiCapture::parse("amenity=fuel"),
+            PoiCapture::parse("tourism"),
+        ];
+        let kind = |tags: Vec<(&'static str, &'static str)>| {
+            get_poi_kind(&captures, |key| {
+                tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+            })
+        };
+        assert_eq!(
+            kind(vec![("amenity", "fuel")]),
+            Some(String::from("amenity=fuel"))
+        );
+        assert_eq!(kind(vec![("amenity", "cafe")]), None);
+        assert_eq!(
+            kind(vec![("tourism", "viewpoint")]),
+            Some(String::from("tourism=viewpoint"))
+        );
+        assert_eq!(kind(vec![("shop", "bakery")]), None);
+    }
+
+    #[test]
+    fn pois_within_radius() {
+        let mut index = PoiIndex::new();
+        index.insert(poi(1, 57.0, 24.0));
+        index.insert(poi(2, 57.0, 24.03));
+        index.insert(poi(3, 57.0, 24.005));
+        index.insert(poi(4, 57.5, 24.0));
+
+        let ids = |radius_m| {
+            index
+                .find_points_within_radius(57.0, 24.0, radius_m)
+                .iter()
+                .map(|poi| poi.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(100.), vec![1]);
+        assert_eq!(ids(500.), vec![1, 3]);
+        assert_eq!(ids(2000.), vec![1, 3, 2]);
+        assert!(index
+            .find_points_within_radius(50.0, 24.0, 2000.)
+            .is_empty());
+    }
+}
@@ -1,12 +1,11 @@
-use geo::HaversineBearing;
-use geo::HaversineDistance;
-use geo::Point;
 use serde::Deserialize;
 use serde::Serialize;
 
 use std::fmt::Debug;
 use std::fmt::Display;
 
+use crate::geo_utils;
+
 use super::graph::MapDataLineRef;
 use super::graph::MapDataPointRef;
 use super::rule::MapDataRule;
@@ -22,14 +21,16 @@ pub struct MapDataPoint {
 
 impl MapDataPoint {
     pub fn distance_between(&self, point: &MapDataPointRef) -> f32 {
-        let self_geo = Point::new(self.lon, self.lat);
-        let point_geo = Point::new(point.borrow().lon, point.borrow().lat);
-        self_geo.haversine_distance(&point_geo)
+        geo_utils::distance(
+            (self.lat, self.lon),
+            (point.borrow().lat, point.borrow().lon),
+        )
     }
     pub fn bearing_to(&self, point: &MapDataPointRef) -> f32 {
-        let self_geo = Point::new(self.lon, self.lat);
-        let point_geo = Point::new(point.borrow().lon, point.borrow().lat);
-        self_geo.haversine_bearing(point_geo)
+        geo_utils::bearing_between(
+            (self.lat, self.lon),
+            (point.borrow().lat, point.borrow().lon),
+        )
     }
     pub fn is_junction(&self) -> bool {
         self.lines.len() > 2
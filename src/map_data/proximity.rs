@@ -6,45 +6,43 @@ use super::graph::MapDataPointRef;
 
 type GpsCellId = (i16, i16);
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct PointGrid {
-    grid: HashMap<GpsCellId, Vec<MapDataPointRef>>,
+/// Default number of cell rings to search around the center cell, a ring is roughly 1km wide
+pub const DEFAULT_SEARCH_RINGS: u16 = 10;
+
+/// Longitude cells around the globe, cells are 0.01 degrees wide
+const LON_CELL_COUNT: i32 = 36000;
+
+#[derive(Debug, PartialEq)]
+pub enum PointSearchError {
+    NoPointsNearby,
+    AllPointsFiltered { filtered: usize },
 }
 
-impl PointGrid {
-    pub fn new() -> Self {
-        Self::default()
+/// Grid of items by rounded coordinates, the routing graph points by default
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PointGrid<T = MapDataPointRef> {
+    grid: HashMap<GpsCellId, Vec<T>>,
+}
+
+impl<T> Default for PointGrid<T> {
+    fn default() -> Self {
+        Self {
+            grid: HashMap::new(),
+        }
     }
+}
 
+impl PointGrid {
     pub fn get_cell_id(lat: f32, lon: f32) -> GpsCellId {
         let lat_rounded = (lat * 100.0).round() as i16;
-        let lon_rounded = (lon * 100.0).round() as i16;
+        let lon_rounded = PointGrid::wrap_lon_cell((lon * 100.0).round() as i32);
         (lat_rounded, lon_rounded)
     }
 
-    pub fn len(&self) -> usize {
-        self.grid.len()
-    }
-
-    pub fn insert(&mut self, lat: f32, lon: f32, point_ref: MapDataPointRef) -> () {
-        let cell_id = PointGrid::get_cell_id(lat, lon);
-        let maybe_points = self.grid.get_mut(&cell_id);
-        if let Some(points) = maybe_points {
-            points.push(point_ref.clone());
-        } else {
-            self.grid.insert(cell_id, vec![point_ref.clone()]);
-        }
-    }
-
-    fn get_points_in_cells(&self, cell_ids: Vec<GpsCellId>) -> Vec<MapDataPointRef> {
-        cell_ids
-            .iter()
-            .map(|cell_id| match self.grid.get(&cell_id) {
-                Some(points) => points.clone(),
-                None => Vec::new(),
-            })
-            .flatten()
-            .collect()
+    /// Longitude cell in -18000..18000 so the cells on both sides of the antimeridian are
+    /// neighbours and 180 and -180 are the same cell
+    fn wrap_lon_cell(lon_cell: i32) -> i16 {
+        ((lon_cell + LON_CELL_COUNT / 2).rem_euclid(LON_CELL_COUNT) - LON_CELL_COUNT / 2) as i16
     }
 
     fn get_outer_cell_ids(center: GpsCellId, offset: u16) -> Option<Vec<GpsCellId>> {
@@ -56,21 +54,12 @@ impl PointGrid {
                     .map(|lon_offset| {
                         if lat_offset.abs() as u16 == offset || lon_offset.abs() as u16 == offset {
                             let lat_new = lat_rounded - lat_offset;
-                            let lat_new = if lat_new > 9000 {
-                                lat_new - 9000
-                            } else if lat_new < -9000 {
-                                lat_new + 9000
-                            } else {
-                                lat_new
-                            };
-                            let lon_new = lon_rounded - lon_offset;
-                            let lon_new = if lon_new > 18000 {
-                                lon_new - 18000
-                            } else if lon_new < -18000 {
-                                lon_new + 18000
-                            } else {
-                                lon_new
-                            };
+                            // there are no cells past the poles
+                            if lat_new.abs() > 9000 {
+                                return None;
+                            }
+                            let lon_new =
+                                PointGrid::wrap_lon_cell(lon_rounded as i32 - lon_offset as i32);
                             Some((lat_new, lon_new))
                         } else {
                             None
@@ -84,23 +73,86 @@ impl PointGrid {
 
         Some(result)
     }
+}
+
+impl<T: Clone> PointGrid<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    pub fn find_closest_point_refs(&self, lat: f32, lon: f32) -> Option<Vec<MapDataPointRef>> {
+    pub fn len(&self) -> usize {
+        self.grid.len()
+    }
+
+    pub fn insert(&mut self, lat: f32, lon: f32, point_ref: T) -> () {
+        let cell_id = PointGrid::get_cell_id(lat, lon);
+        let maybe_points = self.grid.get_mut(&cell_id);
+        if let Some(points) = maybe_points {
+            points.push(point_ref.clone());
+        } else {
+            self.grid.insert(cell_id, vec![point_ref.clone()]);
+        }
+    }
+
+    fn get_points_in_cells(&self, cell_ids: Vec<GpsCellId>) -> Vec<T> {
+        cell_ids
+            .iter()
+            .map(|cell_id| match self.grid.get(&cell_id) {
+                Some(points) => points.clone(),
+                None => Vec::new(),
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// All points in the center cell and the `rings` cell rings around it
+    pub fn get_points_within_rings(&self, lat: f32, lon: f32, rings: u16) -> Vec<T> {
+        let center_cell_id = PointGrid::get_cell_id(lat, lon);
+        (0..=rings)
+            .filter_map(|step| PointGrid::get_outer_cell_ids(center_cell_id, step))
+            .flat_map(|cell_ids| self.get_points_in_cells(cell_ids))
+            .collect()
+    }
+
+    /// Searches cell rings around the coordinates outwards, up to `max_rings`, and returns
+    /// the points of the first ring that has points passing `filter`. Rings where all points
+    /// are filtered out are skipped so that the search widens instead of giving up.
+    pub fn find_closest_point_refs_filtered<F>(
+        &self,
+        lat: f32,
+        lon: f32,
+        max_rings: u16,
+        filter: F,
+    ) -> Result<Vec<T>, PointSearchError>
+    where
+        F: Fn(&T) -> bool,
+    {
         let center_cell_id = PointGrid::get_cell_id(lat, lon);
+        let mut filtered = 0;
 
-        for step in 0..=10 {
+        for step in 0..=max_rings {
             let cell_ids = PointGrid::get_outer_cell_ids(center_cell_id, step);
             let cell_ids = match cell_ids {
                 Some(ids) => ids,
-                None => return None,
+                None => break,
             };
             let points_in_cell = self.get_points_in_cells(cell_ids);
+            let points_total = points_in_cell.len();
+            let points_in_cell = points_in_cell
+                .into_iter()
+                .filter(|point| filter(point))
+                .collect::<Vec<_>>();
+            filtered += points_total - points_in_cell.len();
             if !points_in_cell.is_empty() {
-                return Some(points_in_cell);
+                return Ok(points_in_cell);
             }
         }
 
-        None
+        if filtered > 0 {
+            Err(PointSearchError::AllPointsFiltered { filtered })
+        } else {
+            Err(PointSearchError::NoPointsNearby)
+        }
     }
 }
 
@@ -109,7 +161,7 @@ mod test {
     use rusty_fork::rusty_fork_test;
     use tracing::info;
 
-    use super::PointGrid;
+    use super::{PointGrid, PointSearchError};
 
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
@@ -121,7 +173,9 @@ mod test {
                 (21.21, 54.11, (2121, 5411)),
                 (0.0, 0.0, (0, 0)),
                 (-90.0, -180.0, (-9000, -18000)),
-                (90.0, 180.0, (9000, 18000)),
+                (90.0, 180.0, (9000, -18000)),
+                (-17.0, 179.999, (-1700, -18000)),
+                (-17.0, 179.99, (-1700, 17999)),
             ];
             for (idx, test) in tests.iter().enumerate() {
                 let hash = PointGrid::get_cell_id(test.0, test.1);
@@ -178,4 +232,39 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn outer_cell_ids_wrap() {
+        let ids = PointGrid::get_outer_cell_ids((-1700, 17999), 1).unwrap();
+        assert!(ids.contains(&(-1700, -18000)));
+        assert!(ids.contains(&(-1699, -18000)));
+        assert!(!ids.iter().any(|id| id.1 > 17999 || id.1 < -18000));
+
+        let ids = PointGrid::get_outer_cell_ids((-1700, -18000), 2).unwrap();
+        assert!(ids.contains(&(-1700, 17998)));
+        assert!(ids.contains(&(-1700, -17998)));
+
+        let ids = PointGrid::get_outer_cell_ids((9000, 0), 1).unwrap();
+        assert_eq!(ids.len(), 5);
+        assert!(ids.iter().all(|id| id.0 <= 9000));
+    }
+
+    #[test]
+    fn points_across_antimeridian() {
+        let mut grid = PointGrid::<u64>::new();
+        grid.insert(-17.0, 179.99, 1);
+        grid.insert(-17.0, -179.99, 2);
+
+        assert_eq!(
+            grid.find_closest_point_refs_filtered(-17.0, 180.0, 0, |_| true),
+            Err(PointSearchError::NoPointsNearby)
+        );
+        let mut closest = grid
+            .find_closest_point_refs_filtered(-17.0, 180.0, 1, |_| true)
+            .unwrap();
+        closest.sort();
+        assert_eq!(closest, vec![1, 2]);
+        assert_eq!(grid.get_points_within_rings(-17.0, -179.99, 1), vec![2]);
+        assert!(grid.get_points_within_rings(-17.0, -179.99, 2).contains(&1));
+    }
 }
@@ -7,6 +7,9 @@ use tracing::info;
 
 use crate::map_data::graph::MapDataGraphPacked;
 
+/// Bump when the packed graph format changes, caches with another version are rebuilt
+const CACHE_VERSION: &str = "13";
+
 fn read_cache_file(file_folder: &PathBuf, file_name: &str) -> Result<Vec<u8>, MapDataCacheError> {
     let mut file = file_folder.clone();
     file.push(format!("{file_name}.cache"));
@@ -33,8 +36,38 @@ pub enum MapDataCacheError {
     MissingValue,
     UnexpectedValue,
 }
+/// How the cache dir is used, it is not used at all without a cache dir
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum CacheMode {
+    /// Do not read or write the cache
+    Off,
+    /// Use an existing cache but never write one
+    Read,
+    /// Never use an existing cache, write one when there is none or it is outdated
+    Write,
+    /// Use an existing cache, write one when there is none or it is outdated
+    #[default]
+    ReadWrite,
+    /// Ignore an existing cache and overwrite it
+    Rebuild,
+}
+
+impl CacheMode {
+    fn reads(&self) -> bool {
+        matches!(self, CacheMode::Read | CacheMode::ReadWrite)
+    }
+    fn writes(&self) -> bool {
+        matches!(
+            self,
+            CacheMode::Write | CacheMode::ReadWrite | CacheMode::Rebuild
+        )
+    }
+}
+
 pub struct MapDataCache {
     cache_dir: Option<PathBuf>,
+    mode: CacheMode,
+    identity: String,
     write_to_cache: bool,
 }
 
@@ -43,14 +76,29 @@ impl MapDataCache {
         Self {
             write_to_cache: cache_dir.is_some(),
             cache_dir,
+            mode: CacheMode::default(),
+            identity: String::new(),
         }
     }
 
+    /// Options the map data was read with that change its contents, a cache written with
+    /// another identity is rebuilt
+    pub fn identity(mut self, identity: String) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self.write_to_cache = self.cache_dir.is_some() && mode.writes();
+        self
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn read_cache(&mut self) -> Result<Option<MapDataGraphPacked>, MapDataCacheError> {
         let cache_dir = match &self.cache_dir {
-            None => return Ok(None),
-            Some(cd) => cd,
+            Some(cd) if self.mode.reads() || self.mode == CacheMode::Write => cd,
+            _ => return Ok(None),
         };
 
         let read_start = Instant::now();
@@ -59,12 +107,30 @@ impl MapDataCache {
             return Ok(None);
         }
 
-        self.write_to_cache = false;
+        let cache_version = read_cache_file(&cache_dir, "version").ok();
+        if cache_version.as_deref() != Some(CACHE_VERSION.as_bytes()) {
+            info!("cache version does not match {CACHE_VERSION}, ignoring cache");
+            return Ok(None);
+        }
+        let cache_identity = read_cache_file(cache_dir, "identity").ok();
+        if cache_identity.as_deref() != Some(self.identity.as_bytes()) {
+            info!(
+                "cache was built with other options than {:?}, ignoring cache",
+                self.identity
+            );
+            return Ok(None);
+        }
+        if !self.mode.reads() {
+            info!("cache is up to date, not writing it again");
+            self.write_to_cache = false;
+            return Ok(None);
+        }
 
         let mut points: Option<Result<Vec<u8>, MapDataCacheError>> = None;
         let mut point_grid: Option<Result<Vec<u8>, MapDataCacheError>> = None;
         let mut lines: Option<Result<Vec<u8>, MapDataCacheError>> = None;
         let mut tags: Option<Result<Vec<u8>, MapDataCacheError>> = None;
+        let mut pois: Option<Result<Vec<u8>, MapDataCacheError>> = None;
         rayon::scope(|scope| {
             scope.spawn(|_| {
                 points = Some(read_cache_file(&cache_dir, "points"));
@@ -78,6 +144,9 @@ impl MapDataCache {
             scope.spawn(|_| {
                 tags = Some(read_cache_file(&cache_dir, "tags"));
             });
+            scope.spawn(|_| {
+                pois = Some(read_cache_file(&cache_dir, "pois"));
+            });
         });
 
         let packed_data = MapDataGraphPacked {
@@ -85,7 +154,9 @@ impl MapDataCache {
             point_grid: point_grid.ok_or(MapDataCacheError::MissingValue)??,
             lines: lines.ok_or(MapDataCacheError::MissingValue)??,
             tags: tags.ok_or(MapDataCacheError::MissingValue)??,
+            pois: pois.ok_or(MapDataCacheError::MissingValue)??,
         };
+        self.write_to_cache = false;
 
         let read_duration = read_start.elapsed();
         info!("cache read took {} seconds", read_duration.as_secs());
@@ -111,7 +182,10 @@ impl MapDataCache {
             std::fs::create_dir_all(&cache_dir)
                 .map_err(|error| MapDataCacheError::FileError { error })?;
 
-            let tasks = [0u8; 4];
+            write_cache_file(&cache_dir, "version", &CACHE_VERSION.as_bytes().to_vec())?;
+            write_cache_file(cache_dir, "identity", &self.identity.as_bytes().to_vec())?;
+
+            let tasks = [0u8; 5];
             tasks
                 .par_iter()
                 .enumerate()
@@ -120,6 +194,7 @@ impl MapDataCache {
                     1 => write_cache_file(&cache_dir, "point_grid", &packed_data.point_grid),
                     2 => write_cache_file(&cache_dir, "lines", &packed_data.lines),
                     3 => write_cache_file(&cache_dir, "tags", &packed_data.tags),
+                    4 => write_cache_file(&cache_dir, "pois", &packed_data.pois),
                     _ => Err(MapDataCacheError::UnexpectedValue),
                 })
                 .collect::<Result<Vec<_>, MapDataCacheError>>()?;
@@ -129,3 +204,65 @@ impl MapDataCache {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::map_data::graph::MapDataGraphPacked;
+
+    use super::{CacheMode, MapDataCache};
+
+    #[test]
+    fn cache_modes() {
+        let dir = std::env::temp_dir().join(format!("ridi-router-cache-{}", std::process::id()));
+        let read = |mode| {
+            MapDataCache::init(Some(dir.clone()))
+                .mode(mode)
+                .read_cache()
+                .unwrap()
+                .is_some()
+        };
+
+        MapDataCache::init(Some(dir.clone()))
+            .mode(CacheMode::Read)
+            .write_cache(MapDataGraphPacked::default())
+            .unwrap();
+        assert!(!dir.exists());
+
+        MapDataCache::init(Some(dir.clone()))
+            .mode(CacheMode::Rebuild)
+            .write_cache(MapDataGraphPacked::default())
+            .unwrap();
+        assert!(read(CacheMode::ReadWrite));
+        assert!(read(CacheMode::Read));
+        assert!(!read(CacheMode::Off));
+        assert!(!read(CacheMode::Write));
+        assert!(!read(CacheMode::Rebuild));
+        assert!(MapDataCache::init(Some(dir.clone()))
+            .identity("capture_pois=amenity=fuel".to_string())
+            .read_cache()
+            .unwrap()
+            .is_none());
+
+        // a cache missing a file is rebuilt and written again
+        std::fs::remove_file(dir.join("pois.cache")).unwrap();
+        let mut cache = MapDataCache::init(Some(dir.clone()));
+        assert!(cache.read_cache().is_err());
+        cache.write_cache(MapDataGraphPacked::default()).unwrap();
+        assert!(read(CacheMode::Read));
+
+        // an up to date cache is left as it is, an outdated one is written again
+        std::fs::remove_file(dir.join("pois.cache")).unwrap();
+        let mut cache = MapDataCache::init(Some(dir.clone())).mode(CacheMode::Write);
+        assert!(cache.read_cache().unwrap().is_none());
+        cache.write_cache(MapDataGraphPacked::default()).unwrap();
+        assert!(!dir.join("pois.cache").exists());
+
+        std::fs::write(dir.join("version.cache"), "0").unwrap();
+        let mut cache = MapDataCache::init(Some(dir.clone())).mode(CacheMode::Write);
+        assert!(cache.read_cache().unwrap().is_none());
+        cache.write_cache(MapDataGraphPacked::default()).unwrap();
+        assert!(read(CacheMode::Read));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,16 +1,19 @@
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
+    geo_utils::BoundingBox,
     map_data::{
         graph::{MapDataGraph, MapDataGraphPacked},
         osm::{
             OsmNode, OsmRelation, OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType,
             OsmWay,
         },
+        poi::{get_poi_kind, Poi, PoiCapture},
         MapDataError,
     },
     osm_json_parser::{OsmElement, OsmElementType, OsmJsonParser, OsmJsonParserError},
+    osm_xml_parser::{OsmXmlElement, OsmXmlParser, OsmXmlParserError},
 };
 use std::{
     fs::File,
@@ -39,36 +42,162 @@ pub const ALLOWED_HIGHWAY_VALUES: [&str; 17] = [
     "road",
 ];
 
+/// Characters of the offending line kept in `OsmDataReaderError::JsonLineError`
+const JSON_ERROR_SNIPPET_LEN: usize = 80;
+
+/// Access tags that apply to a motorcycle from the most general to the most specific, a more
+/// specific tag overrides the ones before it. `hgv`, `psv` etc. are for other vehicles and are
+/// not read
+pub const MOTORCYCLE_ACCESS_TAGS: [&str; 4] = ["access", "vehicle", "motor_vehicle", "motorcycle"];
+
+/// Access values that keep a motorcycle off the way
+const NO_ACCESS_VALUES: [&str; 5] = ["no", "private", "agricultural", "forestry", "delivery"];
+
+/// Value of the most specific access tag that is set for a motorcycle
+pub fn get_motorcycle_access<'a, F>(get_tag: F) -> Option<&'a str>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    MOTORCYCLE_ACCESS_TAGS
+        .iter()
+        .rev()
+        .find_map(|key| get_tag(key))
+}
+
+/// Single acceptance check for ways, used by the PBF prefilter so nodes of ways
+/// that would be dropped by `MapDataGraph::insert_way` are not read at all
+pub fn way_tags_are_ok<'a, F>(get_tag: F) -> bool
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    if get_tag("service").is_some() {
+        return false;
+    }
+    // `destination` is kept and penalized by `weight_penalize_destination_access`
+    if let Some(access) = get_motorcycle_access(&get_tag) {
+        if NO_ACCESS_VALUES.contains(&access) {
+            return false;
+        }
+    }
+    let motorcycle = get_tag("motorcycle").map_or(false, |v| {
+        v == "yes" || v == "permissive" || v == "designated"
+    });
+
+    match get_tag("highway") {
+        None => false,
+        Some(highway) => {
+            ALLOWED_HIGHWAY_VALUES.contains(&highway) || (highway == "path" && motorcycle)
+        }
+    }
+}
+
+fn pbf_way_tags_are_ok(tags: &osmpbfreader::Tags) -> bool {
+    way_tags_are_ok(|key| tags.get(key).map(|v| v.as_str()))
+}
+
+/// PBF prefilter, the ways that can end up in the graph and the nodes that are POIs, the
+/// nodes of the ways are read as their dependencies
+fn pbf_obj_is_wanted(obj: &osmpbfreader::OsmObj, poi_captures: &[PoiCapture]) -> bool {
+    (obj.is_way() && pbf_way_tags_are_ok(obj.tags()))
+        || (obj.is_node()
+            && get_poi_kind(poi_captures, |key| obj.tags().get(key).map(|v| v.as_str())).is_some())
+}
+
 #[derive(Debug)]
 pub enum OsmDataReaderError {
-    StdioError { error: io::Error },
-    ParserError { error: OsmJsonParserError },
-    MapDataError { error: MapDataError },
-    FileError { error: io::Error },
-    PbfFileOpenError { error: io::Error },
-    PbfFileReadError { error: osmpbfreader::Error },
-    PbfFileError { error: String },
+    StdioError {
+        error: io::Error,
+    },
+    ParserError {
+        error: OsmJsonParserError,
+    },
+    /// `line_number` starts at 1, `snippet` is the start of the offending line
+    JsonLineError {
+        error: OsmJsonParserError,
+        line_number: usize,
+        snippet: String,
+    },
+    XmlParserError {
+        error: OsmXmlParserError,
+    },
+    XmlUtf8Error {
+        error: std::str::Utf8Error,
+    },
+    MapDataError {
+        error: MapDataError,
+    },
+    FileError {
+        error: io::Error,
+    },
+    PbfFileOpenError {
+        error: io::Error,
+    },
+    PbfFileReadError {
+        error: osmpbfreader::Error,
+    },
+    PbfFileError {
+        error: String,
+    },
+    TooManySkippedWays {
+        skipped: usize,
+        max: usize,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum DataSource {
     JsonFile { file: PathBuf },
     PbfFile { file: PathBuf },
+    OsmXmlFile { file: PathBuf },
 }
 
 pub struct OsmDataReader {
     source: DataSource,
     map_data: MapDataGraph,
+    skipped_ways: usize,
+    max_skipped_ways: Option<usize>,
+    poi_captures: Vec<PoiCapture>,
+    bbox: Option<BoundingBox>,
 }
 
 impl OsmDataReader {
     pub fn new(data_source: DataSource) -> Self {
+        Self::new_with_graph(data_source, MapDataGraph::new())
+    }
+
+    /// Reads the data into an existing graph, the graph must have been created with
+    /// `MapDataGraph::keep_maps` for the new data to be linked with the existing data
+    pub fn new_with_graph(data_source: DataSource, map_data: MapDataGraph) -> Self {
         Self {
-            map_data: MapDataGraph::new(),
+            map_data,
             source: data_source,
+            skipped_ways: 0,
+            max_skipped_ways: None,
+            poi_captures: Vec::new(),
+            bbox: None,
         }
     }
 
+    /// Fail the read when more than `max` ways reference nodes that are missing from the
+    /// input, a sign of an incomplete extract
+    pub fn max_skipped_ways(mut self, max: Option<usize>) -> Self {
+        self.max_skipped_ways = max;
+        self
+    }
+
+    /// Store nodes matching any of the captures in the POI index of the graph, only the JSON
+    /// and PBF readers capture POIs
+    pub fn capture_pois(mut self, captures: Vec<PoiCapture>) -> Self {
+        self.poi_captures = captures;
+        self
+    }
+
+    /// Only read nodes inside the bounding box, ways leaving the box are cut at its edge
+    pub fn bbox(mut self, bbox: Option<BoundingBox>) -> Self {
+        self.bbox = bbox;
+        self
+    }
+
     pub fn read_data(mut self) -> Result<MapDataGraph, OsmDataReaderError> {
         match self.source {
             DataSource::JsonFile { ref file } => {
@@ -77,10 +206,73 @@ impl OsmDataReader {
             DataSource::PbfFile { ref file } => {
                 self.read_pbf(file.clone())?;
             }
+            DataSource::OsmXmlFile { ref file } => {
+                self.read_xml(file.clone())?;
+            }
         };
+        self.check_skipped_ways()?;
         Ok(self.map_data)
     }
 
+    fn check_skipped_ways(&self) -> Result<(), OsmDataReaderError> {
+        if self.skipped_ways > 0 {
+            warn!(
+                "skipped {} ways that reference nodes missing from the input",
+                self.skipped_ways
+            );
+        }
+        match self.max_skipped_ways {
+            Some(max) if self.skipped_ways > max => Err(OsmDataReaderError::TooManySkippedWays {
+                skipped: self.skipped_ways,
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_node(&mut self, node: OsmNode) {
+        if self.node_is_in_bbox(&node) {
+            self.map_data.insert_node(node);
+        }
+    }
+
+    fn node_is_in_bbox(&self, node: &OsmNode) -> bool {
+        self.bbox
+            .map_or(true, |bbox| bbox.contains(node.lat as f32, node.lon as f32))
+    }
+
+    fn insert_way(&mut self, way: OsmWay) -> Result<(), OsmDataReaderError> {
+        let res = if self.bbox.is_some() {
+            self.map_data.insert_way_clipped(way)
+        } else {
+            self.map_data.insert_way(way)
+        };
+        res.map_err(|error| {
+            if let MapDataError::MissingPoint { .. } = error {
+                self.skipped_ways += 1;
+            }
+            OsmDataReaderError::MapDataError { error }
+        })
+    }
+
+    fn insert_poi<'a, F>(&mut self, node: &OsmNode, get_tag: F) -> ()
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        if !self.node_is_in_bbox(node) {
+            return;
+        }
+        if let Some(kind) = get_poi_kind(&self.poi_captures, &get_tag) {
+            self.map_data.insert_poi(Poi {
+                id: node.id,
+                lat: node.lat as f32,
+                lon: node.lon as f32,
+                kind,
+                name: get_tag("name").map(|name| name.to_string()),
+            });
+        }
+    }
+
     fn process_elements(&mut self, elements: Vec<OsmElement>) -> Result<(), OsmDataReaderError> {
         for element in elements {
             match element
@@ -91,17 +283,14 @@ impl OsmDataReader {
                     let node = element
                         .get_node_element()
                         .map_err(|error| OsmDataReaderError::ParserError { error })?;
-                    self.map_data.insert_node(node);
+                    self.insert_poi(&node, |key| element.get_tag(key));
+                    self.insert_node(node);
                 }
                 OsmElementType::Way => {
                     let way = element
                         .get_way_element()
                         .map_err(|error| OsmDataReaderError::ParserError { error })?;
-                    let res = self
-                        .map_data
-                        .insert_way(way)
-                        .map_err(|error| OsmDataReaderError::MapDataError { error });
-                    if let Err(error) = res {
+                    if let Err(error) = self.insert_way(way) {
                         error!(error=?error, "Error, skipping way");
                     }
                 }
@@ -122,6 +311,88 @@ impl OsmDataReader {
         Ok(())
     }
 
+    fn insert_pbf_element(
+        &mut self,
+        element: osmpbfreader::OsmObj,
+    ) -> Result<(), OsmDataReaderError> {
+        if element.is_node() {
+            let node = element.node().map_or(
+                Err(OsmDataReaderError::PbfFileError {
+                    error: String::from("expected node, did not get it"),
+                }),
+                |v| Ok(v),
+            )?;
+            let osm_node = OsmNode {
+                id: node.id.0 as u64,
+                lat: node.lat(),
+                lon: node.lon(),
+            };
+            self.insert_poi(&osm_node, |key| node.tags.get(key).map(|v| v.as_str()));
+            self.insert_node(osm_node);
+        } else if element.is_way() {
+            let way = element.way().map_or(
+                Err(OsmDataReaderError::PbfFileError {
+                    error: String::from("expected way, did not get it"),
+                }),
+                |v| Ok(v),
+            )?;
+            self.insert_way(OsmWay {
+                id: way.id.0 as u64,
+                point_ids: way.nodes.iter().map(|v| v.0 as u64).collect(),
+                tags: Some(
+                    way.tags
+                        .iter()
+                        .map(|v| (v.0.to_string(), v.1.to_string()))
+                        .collect(),
+                ),
+            })?;
+        } else if element.is_relation() {
+            let relation = element.relation().map_or(
+                Err(OsmDataReaderError::PbfFileError {
+                    error: String::from("expected relation, did not get it"),
+                }),
+                |v| Ok(v),
+            )?;
+            self.map_data
+                .insert_relation(OsmRelation {
+                    id: relation.id.0 as u64,
+                    members: relation
+                        .refs
+                        .iter()
+                        .map(|v| -> Result<OsmRelationMember, OsmDataReaderError> {
+                            Ok(OsmRelationMember {
+                                member_ref: match v.member {
+                                    osmpbfreader::OsmId::Way(id) => id.0 as u64,
+                                    osmpbfreader::OsmId::Node(id) => id.0 as u64,
+                                    osmpbfreader::OsmId::Relation(id) => id.0 as u64,
+                                },
+                                role: match v.role.as_str() {
+                                    "from" => OsmRelationMemberRole::From,
+                                    "to" => OsmRelationMemberRole::To,
+                                    "via" => OsmRelationMemberRole::Via,
+                                    other => OsmRelationMemberRole::Other(other.to_string()),
+                                },
+                                member_type: match v.member {
+                                    osmpbfreader::OsmId::Way(_) => OsmRelationMemberType::Way,
+                                    osmpbfreader::OsmId::Node(_) => OsmRelationMemberType::Node,
+                                    _ => Err(OsmDataReaderError::PbfFileError {
+                                        error: String::from("unexpected member type"),
+                                    })?,
+                                },
+                            })
+                        })
+                        .collect::<Result<Vec<OsmRelationMember>, OsmDataReaderError>>()?,
+                    tags: relation
+                        .tags
+                        .iter()
+                        .map(|v| (v.0.to_string(), v.1.to_string()))
+                        .collect(),
+                })
+                .map_err(|error| OsmDataReaderError::MapDataError { error })?;
+        }
+        Ok(())
+    }
+
     fn read_pbf(&mut self, file: PathBuf) -> Result<(), OsmDataReaderError> {
         let read_start = Instant::now();
 
@@ -130,101 +401,21 @@ impl OsmDataReader {
             .map_err(|error| OsmDataReaderError::PbfFileOpenError { error })?;
         let mut pbf = osmpbfreader::OsmPbfReader::new(r);
 
+        let poi_captures = self.poi_captures.clone();
         let elements = pbf
-            .get_objs_and_deps(|obj| {
-                obj.is_way()
-                    && obj.tags().iter().any(|t| {
-                        t.0 == "highway"
-                            && (ALLOWED_HIGHWAY_VALUES.contains(&t.1.as_str())
-                                || (t.1 == "path"
-                                    && obj
-                                        .tags()
-                                        .iter()
-                                        .find(|t2| t2.0 == "motorcycle" && t2.1 == "yes")
-                                        .is_some()))
-                    })
-            })
+            .get_objs_and_deps(|obj| pbf_obj_is_wanted(obj, &poi_captures))
             .map_err(|error| OsmDataReaderError::PbfFileReadError { error })?;
 
-        for (_id, element) in elements {
-            if element.is_node() {
-                let node = element.node().map_or(
-                    Err(OsmDataReaderError::PbfFileError {
-                        error: String::from("expected node, did not get it"),
-                    }),
-                    |v| Ok(v),
-                )?;
-                self.map_data.insert_node(OsmNode {
-                    id: node.id.0 as u64,
-                    lat: node.lat(),
-                    lon: node.lon(),
-                });
-            } else if element.is_way() {
-                let way = element.way().map_or(
-                    Err(OsmDataReaderError::PbfFileError {
-                        error: String::from("expected way, did not get it"),
-                    }),
-                    |v| Ok(v),
-                )?;
-                self.map_data
-                    .insert_way(OsmWay {
-                        id: way.id.0 as u64,
-                        point_ids: way.nodes.iter().map(|v| v.0 as u64).collect(),
-                        tags: Some(
-                            way.tags
-                                .iter()
-                                .map(|v| (v.0.to_string(), v.1.to_string()))
-                                .collect(),
-                        ),
-                    })
-                    .map_err(|error| OsmDataReaderError::MapDataError { error })?;
-            } else if element.is_relation() {
-                let relation = element.relation().map_or(
-                    Err(OsmDataReaderError::PbfFileError {
-                        error: String::from("expected relation, did not get it"),
-                    }),
-                    |v| Ok(v),
-                )?;
-                self.map_data
-                    .insert_relation(OsmRelation {
-                        id: relation.id.0 as u64,
-                        members: relation
-                            .refs
-                            .iter()
-                            .map(|v| -> Result<OsmRelationMember, OsmDataReaderError> {
-                                Ok(OsmRelationMember {
-                                    member_ref: match v.member {
-                                        osmpbfreader::OsmId::Way(id) => id.0 as u64,
-                                        osmpbfreader::OsmId::Node(id) => id.0 as u64,
-                                        osmpbfreader::OsmId::Relation(id) => id.0 as u64,
-                                    },
-                                    role: match v.role.as_str() {
-                                        "from" => OsmRelationMemberRole::From,
-                                        "to" => OsmRelationMemberRole::To,
-                                        "via" => OsmRelationMemberRole::Via,
-                                        _ => Err(OsmDataReaderError::PbfFileError {
-                                            error: String::from("unknown role"),
-                                        })?,
-                                    },
-                                    member_type: match v.member {
-                                        osmpbfreader::OsmId::Way(_) => OsmRelationMemberType::Way,
-                                        osmpbfreader::OsmId::Node(_) => OsmRelationMemberType::Node,
-                                        _ => Err(OsmDataReaderError::PbfFileError {
-                                            error: String::from("unexpected member type"),
-                                        })?,
-                                    },
-                                })
-                            })
-                            .collect::<Result<Vec<OsmRelationMember>, OsmDataReaderError>>()?,
-                        tags: relation
-                            .tags
-                            .iter()
-                            .map(|v| (v.0.to_string(), v.1.to_string()))
-                            .collect(),
-                    })
-                    .map_err(|error| OsmDataReaderError::MapDataError { error })?;
+        let mut skipped = 0;
+        for (id, element) in elements {
+            if let Err(error) = self.insert_pbf_element(element) {
+                error!(id=?id, error=?error, "Error, skipping element");
+                skipped += 1;
             }
         }
+        if skipped > 0 {
+            info!("skipped {skipped} elements with errors");
+        }
 
         self.map_data.generate_point_hashes();
 
@@ -236,10 +427,21 @@ impl OsmDataReader {
 
     fn read_json(&mut self, file: PathBuf) -> Result<(), OsmDataReaderError> {
         let read_start = Instant::now();
-        let mut parser_state = OsmJsonParser::new();
 
         let f = File::open(file).map_err(|error| OsmDataReaderError::FileError { error })?;
-        let mut reader = BufReader::new(f);
+        self.read_json_lines(BufReader::new(f))?;
+
+        self.map_data.generate_point_hashes();
+
+        let read_duration = read_start.elapsed();
+        info!("file read took {} seconds", read_duration.as_secs());
+
+        Ok(())
+    }
+
+    fn read_json_lines<R: BufRead>(&mut self, mut reader: R) -> Result<(), OsmDataReaderError> {
+        let mut parser_state = OsmJsonParser::new();
+        let mut line_number = 0;
         loop {
             let mut line = String::new();
             let len = reader
@@ -248,12 +450,28 @@ impl OsmDataReader {
             if len == 0 {
                 break;
             }
-            let line = line.as_bytes().to_owned();
+            line_number += 1;
+            let line_error = |error| OsmDataReaderError::JsonLineError {
+                error,
+                line_number,
+                snippet: line.trim().chars().take(JSON_ERROR_SNIPPET_LEN).collect(),
+            };
             let elements = parser_state
-                .parse_line(line)
-                .map_err(|error| OsmDataReaderError::ParserError { error })?;
-            self.process_elements(elements)?;
+                .parse_line(line.as_bytes().to_owned())
+                .map_err(line_error)?;
+            self.process_elements(elements)
+                .map_err(|error| match error {
+                    OsmDataReaderError::ParserError { error } => line_error(error),
+                    error => error,
+                })?;
         }
+        Ok(())
+    }
+    fn read_xml(&mut self, file: PathBuf) -> Result<(), OsmDataReaderError> {
+        let read_start = Instant::now();
+
+        let f = File::open(file).map_err(|error| OsmDataReaderError::FileError { error })?;
+        self.read_xml_chunks(BufReader::new(f))?;
 
         self.map_data.generate_point_hashes();
 
@@ -262,4 +480,413 @@ impl OsmDataReader {
 
         Ok(())
     }
+
+    /// Feeds the parser chunks ending with `>`, the parser buffers them while a `>` is inside
+    /// an attribute value. Malformed elements are logged and skipped like in PBF files
+    fn read_xml_chunks<R: BufRead>(&mut self, mut reader: R) -> Result<(), OsmDataReaderError> {
+        let mut parser = OsmXmlParser::new();
+        let mut chunk = Vec::new();
+        let mut skipped = 0;
+        loop {
+            chunk.clear();
+            let len = reader
+                .read_until(b'>', &mut chunk)
+                .map_err(|error| OsmDataReaderError::FileError { error })?;
+            if len == 0 {
+                break;
+            }
+            let element = match std::str::from_utf8(&chunk) {
+                Ok(chunk) => parser
+                    .parse_chunk(chunk)
+                    .map_err(|error| OsmDataReaderError::XmlParserError { error }),
+                Err(error) => Err(OsmDataReaderError::XmlUtf8Error { error }),
+            };
+            let element = match element {
+                Ok(element) => element,
+                Err(error) => {
+                    error!(error=?error, "Error, skipping element");
+                    skipped += 1;
+                    continue;
+                }
+            };
+            match element {
+                None => {}
+                Some(OsmXmlElement::Node(node)) => self.insert_node(node),
+                Some(OsmXmlElement::Way(way)) => {
+                    // editor exports contain buildings, landuse etc, drop them before they
+                    // reach the graph
+                    if !way_tags_are_ok(|key| {
+                        way.tags
+                            .as_ref()
+                            .and_then(|tags| tags.get(key).map(|v| v.as_str()))
+                    }) {
+                        continue;
+                    }
+                    if let Err(error) = self.insert_way(way) {
+                        error!(error=?error, "Error, skipping way");
+                    }
+                }
+                Some(OsmXmlElement::Relation(relation)) => {
+                    let res = self
+                        .map_data
+                        .insert_relation(relation)
+                        .map_err(|error| OsmDataReaderError::MapDataError { error });
+                    if let Err(error) = res {
+                        error!(error=?error, "Error, skipping relation");
+                    }
+                }
+            }
+        }
+        if skipped > 0 {
+            info!("skipped {skipped} elements with errors");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Ref, Relation, RelationId, Tags, Way, WayId};
+    use rusty_fork::rusty_fork_test;
+
+    use crate::map_data::{
+        osm::{OsmNode, OsmWay},
+        poi::PoiCapture,
+    };
+
+    use crate::osm_json_parser::OsmJsonParserError;
+
+    use crate::{geo_utils::BoundingBox, test_utils::set_graph_static};
+
+    use super::{
+        get_motorcycle_access, pbf_obj_is_wanted, way_tags_are_ok, DataSource, OsmDataReader,
+        OsmDataReaderError,
+    };
+
+    #[test]
+    fn motorcycle_access_hierarchy() {
+        let is_ok = |tags: &[(&'static str, &'static str)]| {
+            way_tags_are_ok(|key| tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v))
+        };
+        assert!(is_ok(&[("highway", "primary")]));
+        assert!(!is_ok(&[("highway", "primary"), ("access", "private")]));
+        assert!(is_ok(&[
+            ("highway", "primary"),
+            ("access", "private"),
+            ("motorcycle", "permissive"),
+        ]));
+        assert!(is_ok(&[
+            ("highway", "primary"),
+            ("access", "no"),
+            ("motor_vehicle", "yes"),
+        ]));
+        assert!(!is_ok(&[
+            ("highway", "primary"),
+            ("motor_vehicle", "yes"),
+            ("motorcycle", "no"),
+        ]));
+        assert!(!is_ok(&[
+            ("highway", "primary"),
+            ("vehicle", "no"),
+            ("access", "yes"),
+        ]));
+        assert!(!is_ok(&[
+            ("highway", "track"),
+            ("motor_vehicle", "forestry")
+        ]));
+        assert!(is_ok(&[
+            ("highway", "track"),
+            ("motor_vehicle", "agricultural"),
+            ("motorcycle", "yes"),
+        ]));
+        // other vehicle classes do not apply
+        assert!(is_ok(&[
+            ("highway", "primary"),
+            ("hgv", "no"),
+            ("psv", "no")
+        ]));
+        assert!(is_ok(&[("highway", "path"), ("motorcycle", "designated")]));
+        assert!(!is_ok(&[("highway", "path"), ("access", "yes")]));
+
+        assert_eq!(
+            get_motorcycle_access(|key| match key {
+                "access" => Some("destination"),
+                "vehicle" => Some("yes"),
+                _ => None,
+            }),
+            Some("yes")
+        );
+        assert_eq!(get_motorcycle_access(|_| None), None);
+    }
+
+    #[test]
+    fn json_parser_errors_have_line_number() {
+        let mut reader = OsmDataReader::new(DataSource::JsonFile {
+            file: PathBuf::from("unused.json"),
+        });
+        let input = [
+            r#"{"#,
+            r#"  "elements": ["#,
+            r#"{"#,
+            r#"  "type": "wrong-value","#,
+            r#"  "id": 1,"#,
+            r#"  "lat": 1.0,"#,
+            r#"  "lon": 1.0"#,
+            r#"}"#,
+            r#"  ]"#,
+            r#"}"#,
+        ]
+        .join("\n");
+
+        match reader.read_json_lines(input.as_bytes()) {
+            Err(OsmDataReaderError::JsonLineError {
+                error,
+                line_number,
+                snippet,
+            }) => {
+                assert_eq!(
+                    error,
+                    OsmJsonParserError::UnknownNodeType {
+                        node_type: String::from("wrong-value")
+                    }
+                );
+                assert_eq!(line_number, 4);
+                assert_eq!(snippet, r#""type": "wrong-value","#);
+            }
+            result => panic!("expected a line error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pbf_element_errors_are_returned() {
+        let mut reader = OsmDataReader::new(DataSource::PbfFile {
+            file: PathBuf::from("unused.pbf"),
+        });
+        let relation = |member| {
+            OsmObj::Relation(Relation {
+                id: RelationId(1),
+                tags: Tags::new(),
+                refs: vec![Ref {
+                    member,
+                    role: "outer".into(),
+                }],
+            })
+        };
+
+        assert!(matches!(
+            reader.insert_pbf_element(relation(OsmId::Relation(RelationId(2)))),
+            Err(OsmDataReaderError::PbfFileError { .. })
+        ));
+        assert!(reader
+            .insert_pbf_element(relation(OsmId::Node(NodeId(2))))
+            .is_ok());
+    }
+
+    #[test]
+    fn ways_with_missing_points_are_counted() {
+        let mut reader = OsmDataReader::new(DataSource::JsonFile {
+            file: PathBuf::from("unused.json"),
+        })
+        .max_skipped_ways(Some(1));
+        reader.map_data.insert_node(OsmNode {
+            id: 1,
+            lat: 1.,
+            lon: 1.,
+        });
+        reader.map_data.insert_node(OsmNode {
+            id: 2,
+            lat: 2.,
+            lon: 2.,
+        });
+        let way = |id, point_ids| OsmWay {
+            id,
+            point_ids,
+            tags: Some(HashMap::from([(
+                "highway".to_string(),
+                "primary".to_string(),
+            )])),
+        };
+
+        assert!(reader.insert_way(way(1, vec![1, 2])).is_ok());
+        assert!(reader.insert_way(way(2, vec![2, 3])).is_err());
+        assert_eq!(reader.skipped_ways, 1);
+        assert!(reader.check_skipped_ways().is_ok());
+
+        assert!(reader.insert_way(way(3, vec![4, 1])).is_err());
+        assert!(matches!(
+            reader.check_skipped_ways(),
+            Err(OsmDataReaderError::TooManySkippedWays { skipped: 2, max: 1 })
+        ));
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn bbox_cuts_ways_at_the_edge() {
+            let mut reader = OsmDataReader::new(DataSource::JsonFile {
+                file: PathBuf::from("unused.json"),
+            })
+            .bbox(Some(BoundingBox {
+                min_lat: 0.,
+                min_lon: 0.,
+                max_lat: 10.,
+                max_lon: 10.,
+            }));
+            for (id, lat) in [(1, 1.), (2, 2.), (3, 11.), (4, 4.), (5, 5.), (6, -1.)] {
+                reader.insert_node(OsmNode { id, lat, lon: 1. });
+            }
+            let way = |id, point_ids| OsmWay {
+                id,
+                point_ids,
+                tags: Some(HashMap::from([(
+                    "highway".to_string(),
+                    "primary".to_string(),
+                )])),
+            };
+
+            assert!(reader.map_data.test_get_point_ref_by_id(&3).is_none());
+            assert!(reader.insert_way(way(1, vec![1, 2, 3, 4, 5])).is_ok());
+            assert!(reader.insert_way(way(2, vec![5, 6])).is_ok());
+            assert_eq!(reader.skipped_ways, 0);
+
+            let map_data = set_graph_static(reader.map_data);
+            assert_eq!(map_data.stats().line_count, 2);
+            let line_count = |id| {
+                map_data
+                    .test_get_point_ref_by_id(&id)
+                    .map(|point| point.borrow().lines.len())
+            };
+            assert_eq!(line_count(2), Some(1));
+            assert_eq!(line_count(4), Some(1));
+            assert_eq!(line_count(5), Some(1));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn pbf_prefilter_matches_graph_ways() {
+            use rand::prelude::*;
+
+            let tag_values: [(&str, &[&str]); 6] = [
+                (
+                    "highway",
+                    &[
+                        "primary",
+                        "residential",
+                        "track",
+                        "path",
+                        "footway",
+                        "service",
+                        "motorway_link",
+                    ],
+                ),
+                ("access", &["yes", "no", "private", "destination"]),
+                ("vehicle", &["yes", "no"]),
+                ("motor_vehicle", &["yes", "no", "private", "agricultural"]),
+                ("motorcycle", &["yes", "no", "permissive"]),
+                ("service", &["driveway", "parking_aisle"]),
+            ];
+            let mut reader = OsmDataReader::new(DataSource::PbfFile {
+                file: PathBuf::from("unused.pbf"),
+            });
+            let mut rng = StdRng::seed_from_u64(1317);
+            let mut prefiltered = Vec::new();
+            // way `id` is between nodes `id * 2` and `id * 2 + 1`
+            for id in 1..1000 {
+                for node_id in [id * 2, id * 2 + 1] {
+                    reader.insert_node(OsmNode {
+                        id: node_id,
+                        lat: (node_id % 80) as f64,
+                        lon: (node_id / 100) as f64,
+                    });
+                }
+                let mut tags = Tags::new();
+                for (key, values) in tag_values.iter() {
+                    if rng.gen_bool(0.5) {
+                        tags.insert((*key).into(), (*values.choose(&mut rng).unwrap()).into());
+                    }
+                }
+                let way = OsmObj::Way(Way {
+                    id: WayId(id as i64),
+                    tags,
+                    nodes: vec![NodeId(id as i64 * 2), NodeId(id as i64 * 2 + 1)],
+                });
+                if pbf_obj_is_wanted(&way, &[]) {
+                    prefiltered.push(id);
+                }
+                assert!(reader.insert_pbf_element(way).is_ok());
+            }
+
+            let map_data = set_graph_static(reader.map_data);
+            let in_graph = (1..1000)
+                .filter(|id| {
+                    map_data
+                        .test_get_point_ref_by_id(&(id * 2))
+                        .is_some_and(|point| !point.borrow().lines.is_empty())
+                })
+                .collect::<Vec<_>>();
+            assert!(!prefiltered.is_empty() && prefiltered.len() < 999);
+            assert_eq!(prefiltered, in_graph);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn xml_errors_skip_element() {
+            let mut reader = OsmDataReader::new(DataSource::OsmXmlFile {
+                file: PathBuf::from("unused.osm"),
+            });
+            let input = r#"<osm>
+  <node id="1" lat="57.1" lon="25.0"/>
+  <node id="2" lat="x" lon="25.1"/>
+  <node id="3" lat="57.2" lon="25.2"/>
+  <way id="4"><nd ref="1"/><nd ref="3"/><tag k="highway" v="primary"/><tag k="name" v="A > B"/></way>
+</osm>"#;
+            assert!(reader.read_xml_chunks(input.as_bytes()).is_ok());
+
+            let map_data = set_graph_static(reader.map_data);
+            assert!(map_data.test_get_point_ref_by_id(&2).is_none());
+            let point = map_data.test_get_point_ref_by_id(&1).unwrap();
+            let line = point.borrow().lines[0].clone();
+            assert_eq!(
+                line.borrow().tags.borrow().name().map(|v| v.as_str()),
+                Some("A > B")
+            );
+        }
+    }
+
+    #[test]
+    fn pbf_nodes_matching_captures_are_pois() {
+        let mut reader = OsmDataReader::new(DataSource::PbfFile {
+            file: PathBuf::from("unused.pbf"),
+        })
+        .capture_pois(vec![PoiCapture::parse("amenity=fuel")]);
+        let node = |id, amenity: &str| {
+            let mut tags = Tags::new();
+            tags.insert("amenity".into(), amenity.into());
+            tags.insert("name".into(), "Station".into());
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags,
+                decimicro_lat: 570000000,
+                decimicro_lon: 240000000,
+            })
+        };
+
+        assert!(reader.insert_pbf_element(node(1, "fuel")).is_ok());
+        assert!(reader.insert_pbf_element(node(2, "cafe")).is_ok());
+        let pois = reader
+            .map_data
+            .get_pois()
+            .find_points_within_radius(57., 24., 100.);
+        assert_eq!(pois.len(), 1);
+        assert_eq!(pois[0].id, 1);
+        assert_eq!(pois[0].kind, "amenity=fuel");
+        assert_eq!(pois[0].name.as_deref(), Some("Station"));
+        assert!(reader.map_data.test_get_point_ref_by_id(&2).is_some());
+    }
 }
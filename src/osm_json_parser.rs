@@ -88,6 +88,13 @@ impl OsmElement {
             })?)
     }
 
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .as_ref()
+            .and_then(|tags| tags.get(key))
+            .map(|value| value.as_str())
+    }
+
     pub fn get_node_element(&self) -> Result<OsmNode, OsmJsonParserError> {
         if let Ok(OsmElementType::Node) = self.get_element_type() {
             return Ok(OsmNode {
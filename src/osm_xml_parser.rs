@@ -0,0 +1,489 @@
+use std::{
+    collections::HashMap,
+    num::{ParseFloatError, ParseIntError},
+};
+
+use crate::map_data::osm::{
+    OsmNode, OsmRelation, OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType, OsmWay,
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum OsmXmlParserError {
+    MissingAttribute { element: String, attribute: String },
+    FailedToParseId { error: ParseIntError },
+    FailedToParseLat { error: ParseFloatError },
+    FailedToParseLon { error: ParseFloatError },
+    UnexpectedTag { tag: String, context: String },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum OsmXmlElement {
+    Node(OsmNode),
+    Way(OsmWay),
+    Relation(OsmRelation),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum OsmXmlParserState {
+    Root,
+    Node(OsmNode),
+    Way(OsmWay),
+    Relation(OsmRelation),
+    /// After an error inside an element, everything is ignored up to the end tag e.g. `/way`
+    Skip(String),
+}
+
+/// Streaming parser for `.osm` XML files. The input is fed in chunks that end with `>`,
+/// text between tags is ignored and only `node`, `way` and `relation` elements are read.
+pub struct OsmXmlParser {
+    state: OsmXmlParserState,
+    buffer: String,
+}
+
+fn unescape(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity_end = match rest.find(';') {
+            None => break,
+            Some(end) => end,
+        };
+        let entity = &rest[1..entity_end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map_or_else(
+                    || entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+                    |n| u32::from_str_radix(n, 16).ok(),
+                )
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[entity_end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// `true` when an attribute value in `tag` is not closed yet, a `>` in it does not end the tag
+fn has_open_quote(tag: &str) -> bool {
+    let mut quote = None;
+    for c in tag.chars() {
+        match quote {
+            None if c == '"' || c == '\'' => quote = Some(c),
+            Some(open) if c == open => quote = None,
+            _ => {}
+        }
+    }
+    quote.is_some()
+}
+
+/// Attributes of a tag, `tag` is everything after the tag name
+fn parse_attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim();
+        let value_part = rest[eq + 1..].trim_start();
+        let quote = match value_part.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => break,
+        };
+        let value_end = match value_part[1..].find(quote) {
+            None => break,
+            Some(end) => end + 1,
+        };
+        attributes.insert(name.to_string(), unescape(&value_part[1..value_end]));
+        rest = &value_part[value_end + 1..];
+    }
+    attributes
+}
+
+fn get_attribute<'a>(
+    attributes: &'a HashMap<String, String>,
+    element: &str,
+    attribute: &str,
+) -> Result<&'a String, OsmXmlParserError> {
+    attributes
+        .get(attribute)
+        .ok_or(OsmXmlParserError::MissingAttribute {
+            element: element.to_string(),
+            attribute: attribute.to_string(),
+        })
+}
+
+fn get_id(
+    attributes: &HashMap<String, String>,
+    element: &str,
+    attribute: &str,
+) -> Result<u64, OsmXmlParserError> {
+    get_attribute(attributes, element, attribute)?
+        .parse()
+        .map_err(|error| OsmXmlParserError::FailedToParseId { error })
+}
+
+impl OsmXmlParser {
+    pub fn new() -> Self {
+        Self {
+            state: OsmXmlParserState::Root,
+            buffer: String::new(),
+        }
+    }
+
+    pub fn parse_chunk(&mut self, chunk: &str) -> Result<Option<OsmXmlElement>, OsmXmlParserError> {
+        self.buffer.push_str(chunk);
+        let tag_start = match self.buffer.find('<') {
+            None => {
+                self.buffer.clear();
+                return Ok(None);
+            }
+            Some(start) => start,
+        };
+        let tag = self.buffer[tag_start + 1..].trim_end();
+        let tag = tag.strip_suffix('>').unwrap_or(tag);
+        // comments can contain `>`, keep reading until the end of the comment
+        if tag.starts_with("!--") && !tag.ends_with("--") {
+            return Ok(None);
+        }
+        if !tag.starts_with('!') && has_open_quote(tag) {
+            return Ok(None);
+        }
+        let tag = tag.to_string();
+        self.buffer.clear();
+        self.parse_tag(&tag)
+    }
+
+    /// On an error the rest of the element it happened in is skipped, so that one malformed
+    /// element is one error
+    fn parse_tag(&mut self, tag: &str) -> Result<Option<OsmXmlElement>, OsmXmlParserError> {
+        if tag.starts_with('?') || tag.starts_with('!') {
+            return Ok(None);
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.strip_suffix('/').unwrap_or(tag).trim();
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = &tag[..name_end];
+        let attributes = parse_attributes(&tag[name_end..]);
+
+        let end_tag = match &self.state {
+            OsmXmlParserState::Root
+                if !self_closing && matches!(name, "node" | "way" | "relation") =>
+            {
+                Some(format!("/{name}"))
+            }
+            OsmXmlParserState::Root | OsmXmlParserState::Skip(_) => None,
+            OsmXmlParserState::Node(_) => Some("/node".to_string()),
+            OsmXmlParserState::Way(_) => Some("/way".to_string()),
+            OsmXmlParserState::Relation(_) => Some("/relation".to_string()),
+        };
+        let result = self.parse_element_tag(name, &attributes, self_closing);
+        if let (Err(_), Some(end_tag)) = (&result, end_tag) {
+            if name != end_tag {
+                self.state = OsmXmlParserState::Skip(end_tag);
+            }
+        }
+        result
+    }
+
+    fn parse_element_tag(
+        &mut self,
+        name: &str,
+        attributes: &HashMap<String, String>,
+        self_closing: bool,
+    ) -> Result<Option<OsmXmlElement>, OsmXmlParserError> {
+        let state = std::mem::replace(&mut self.state, OsmXmlParserState::Root);
+        match (state, name) {
+            (OsmXmlParserState::Root, "node") => {
+                let node = OsmNode {
+                    id: get_id(attributes, name, "id")?,
+                    lat: get_attribute(attributes, name, "lat")?
+                        .parse()
+                        .map_err(|error| OsmXmlParserError::FailedToParseLat { error })?,
+                    lon: get_attribute(attributes, name, "lon")?
+                        .parse()
+                        .map_err(|error| OsmXmlParserError::FailedToParseLon { error })?,
+                };
+                if self_closing {
+                    return Ok(Some(OsmXmlElement::Node(node)));
+                }
+                self.state = OsmXmlParserState::Node(node);
+            }
+            (OsmXmlParserState::Root, "way") => {
+                let way = OsmWay {
+                    id: get_id(attributes, name, "id")?,
+                    point_ids: Vec::new(),
+                    tags: None,
+                };
+                if self_closing {
+                    return Ok(Some(OsmXmlElement::Way(way)));
+                }
+                self.state = OsmXmlParserState::Way(way);
+            }
+            (OsmXmlParserState::Root, "relation") => {
+                let relation = OsmRelation {
+                    id: get_id(attributes, name, "id")?,
+                    members: Vec::new(),
+                    tags: HashMap::new(),
+                };
+                if self_closing {
+                    return Ok(Some(OsmXmlElement::Relation(relation)));
+                }
+                self.state = OsmXmlParserState::Relation(relation);
+            }
+            (OsmXmlParserState::Root, "nd" | "tag" | "member") => {
+                return Err(OsmXmlParserError::UnexpectedTag {
+                    tag: name.to_string(),
+                    context: "root".to_string(),
+                });
+            }
+            (OsmXmlParserState::Root, _) => {}
+            (OsmXmlParserState::Node(node), "/node") => {
+                return Ok(Some(OsmXmlElement::Node(node)));
+            }
+            (OsmXmlParserState::Node(node), _) => {
+                self.state = OsmXmlParserState::Node(node);
+            }
+            (OsmXmlParserState::Way(way), "/way") => {
+                return Ok(Some(OsmXmlElement::Way(way)));
+            }
+            (OsmXmlParserState::Way(mut way), "nd") => {
+                way.point_ids.push(get_id(attributes, name, "ref")?);
+                self.state = OsmXmlParserState::Way(way);
+            }
+            (OsmXmlParserState::Way(mut way), "tag") => {
+                way.tags.get_or_insert_with(HashMap::new).insert(
+                    get_attribute(attributes, name, "k")?.clone(),
+                    get_attribute(attributes, name, "v")?.clone(),
+                );
+                self.state = OsmXmlParserState::Way(way);
+            }
+            (OsmXmlParserState::Way(way), _) => {
+                self.state = OsmXmlParserState::Way(way);
+            }
+            (OsmXmlParserState::Relation(relation), "/relation") => {
+                return Ok(Some(OsmXmlElement::Relation(relation)));
+            }
+            (OsmXmlParserState::Relation(mut relation), "member") => {
+                let member_type = match get_attribute(attributes, name, "type")?.as_str() {
+                    "way" => Some(OsmRelationMemberType::Way),
+                    "node" => Some(OsmRelationMemberType::Node),
+                    // nested relations are not used for turn restrictions
+                    _ => None,
+                };
+                if let Some(member_type) = member_type {
+                    relation.members.push(OsmRelationMember {
+                        member_type,
+                        member_ref: get_id(attributes, name, "ref")?,
+                        role: match get_attribute(attributes, name, "role")?.as_str() {
+                            "from" => OsmRelationMemberRole::From,
+                            "to" => OsmRelationMemberRole::To,
+                            "via" => OsmRelationMemberRole::Via,
+                            other => OsmRelationMemberRole::Other(other.to_string()),
+                        },
+                    });
+                }
+                self.state = OsmXmlParserState::Relation(relation);
+            }
+            (OsmXmlParserState::Relation(mut relation), "tag") => {
+                relation.tags.insert(
+                    get_attribute(attributes, name, "k")?.clone(),
+                    get_attribute(attributes, name, "v")?.clone(),
+                );
+                self.state = OsmXmlParserState::Relation(relation);
+            }
+            (OsmXmlParserState::Relation(relation), _) => {
+                self.state = OsmXmlParserState::Relation(relation);
+            }
+            (OsmXmlParserState::Skip(end_tag), name) => {
+                if name != end_tag {
+                    self.state = OsmXmlParserState::Skip(end_tag);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::map_data::osm::{
+        OsmNode, OsmRelation, OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType,
+        OsmWay,
+    };
+
+    use super::{OsmXmlElement, OsmXmlParser, OsmXmlParserError};
+
+    fn parse(input: &str) -> Result<Vec<OsmXmlElement>, OsmXmlParserError> {
+        let mut parser = OsmXmlParser::new();
+        let mut elements = Vec::new();
+        for chunk in input.split_inclusive('>') {
+            if let Some(element) = parser.parse_chunk(chunk)? {
+                elements.push(element);
+            }
+        }
+        Ok(elements)
+    }
+
+    #[test]
+    fn read_osm_xml() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6" generator="JOSM">
+  <bounds minlat="57.14" minlon="24.85" maxlat="57.20" maxlon="25.05"/>
+  <!-- exported <with> comment -->
+  <node id="18483373" lat="57.1995635" lon="25.0419124"/>
+  <node id="18483475" lat="57.1455443" lon="24.8581908" version="3">
+    <tag k="highway" v="traffic_signals"/>
+  </node>
+  <way id="80944232">
+    <nd ref="18483373"/>
+    <nd ref="18483475"/>
+    <tag k="highway" v="living_street"/>
+    <tag k="name" v='Al&#363;ksnes &amp; "iela"'/>
+  </way>
+  <way id="83402701"/>
+  <relation id="14385700">
+    <member type="way" ref="80944232" role="from"/>
+    <member type="node" ref="18483475" role="via"/>
+    <member type="relation" ref="1" role=""/>
+    <member type="way" ref="80944232" role="to"/>
+    <tag k="restriction" v="no_u_turn"/>
+  </relation>
+</osm>
+"#;
+        let elements = parse(input).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                OsmXmlElement::Node(OsmNode {
+                    id: 18483373,
+                    lat: 57.1995635,
+                    lon: 25.0419124,
+                }),
+                OsmXmlElement::Node(OsmNode {
+                    id: 18483475,
+                    lat: 57.1455443,
+                    lon: 24.8581908,
+                }),
+                OsmXmlElement::Way(OsmWay {
+                    id: 80944232,
+                    point_ids: vec![18483373, 18483475],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "living_street".to_string()),
+                        ("name".to_string(), "Alūksnes & \"iela\"".to_string()),
+                    ])),
+                }),
+                OsmXmlElement::Way(OsmWay {
+                    id: 83402701,
+                    point_ids: Vec::new(),
+                    tags: None,
+                }),
+                OsmXmlElement::Relation(OsmRelation {
+                    id: 14385700,
+                    members: vec![
+                        OsmRelationMember {
+                            member_type: OsmRelationMemberType::Way,
+                            role: OsmRelationMemberRole::From,
+                            member_ref: 80944232,
+                        },
+                        OsmRelationMember {
+                            member_type: OsmRelationMemberType::Node,
+                            role: OsmRelationMemberRole::Via,
+                            member_ref: 18483475,
+                        },
+                        OsmRelationMember {
+                            member_type: OsmRelationMemberType::Way,
+                            role: OsmRelationMemberRole::To,
+                            member_ref: 80944232,
+                        },
+                    ],
+                    tags: HashMap::from([("restriction".to_string(), "no_u_turn".to_string())]),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn return_err_on_wrong_values() {
+        assert!(matches!(
+            parse(r#"<node id="1" lat="57.1" />"#),
+            Err(OsmXmlParserError::MissingAttribute { .. })
+        ));
+        assert!(matches!(
+            parse(r#"<node id="a" lat="57.1" lon="25.0"/>"#),
+            Err(OsmXmlParserError::FailedToParseId { .. })
+        ));
+        assert!(matches!(
+            parse(r#"<way id="1"><nd ref="x"/></way>"#),
+            Err(OsmXmlParserError::FailedToParseId { .. })
+        ));
+        assert!(matches!(
+            parse(r#"<osm><nd ref="1"/></osm>"#),
+            Err(OsmXmlParserError::UnexpectedTag { .. })
+        ));
+    }
+
+    #[test]
+    fn quoted_gt_does_not_end_tag() {
+        let elements =
+            parse(r#"<way id="1"><tag k="name" v="A > B"/><tag k='x' v='"<>"'/></way>"#).unwrap();
+        assert_eq!(
+            elements,
+            vec![OsmXmlElement::Way(OsmWay {
+                id: 1,
+                point_ids: Vec::new(),
+                tags: Some(HashMap::from([
+                    ("name".to_string(), "A > B".to_string()),
+                    ("x".to_string(), "\"<>\"".to_string()),
+                ])),
+            })]
+        );
+    }
+
+    #[test]
+    fn skip_rest_of_malformed_element() {
+        let mut parser = OsmXmlParser::new();
+        let mut elements = Vec::new();
+        let mut errors = 0;
+        let input = r#"<osm>
+  <way id="1"><nd ref="x"/><nd ref="2"/><tag k="highway" v="primary"/></way>
+  <way id="a"><nd ref="1"/></way>
+  <node id="3" lat="57.1" lon="25.0"/>
+</osm>"#;
+        for chunk in input.split_inclusive('>') {
+            match parser.parse_chunk(chunk) {
+                Ok(Some(element)) => elements.push(element),
+                Ok(None) => {}
+                Err(_) => errors += 1,
+            }
+        }
+        assert_eq!(errors, 2);
+        assert_eq!(
+            elements,
+            vec![OsmXmlElement::Node(OsmNode {
+                id: 3,
+                lat: 57.1,
+                lon: 25.0,
+            })]
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::{io::Error, path::PathBuf};
+
+use crate::ipc_handler::RouteMessage;
+
+/// Decimal places kept in `RouteMessage::polyline`
+pub const POLYLINE_PRECISION: u32 = 5;
+
+#[derive(Debug)]
+pub enum PolylineWriterError {
+    FileWriteError { error: Error },
+}
+
+pub struct PolylineWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+}
+
+impl PolylineWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf) -> Self {
+        Self { routes, file_name }
+    }
+    pub fn write_polylines(self) -> Result<(), PolylineWriterError> {
+        let mut contents = String::new();
+        for route in self.routes {
+            contents.push_str(&route.polyline);
+            contents.push('\n');
+        }
+
+        std::fs::write(self.file_name, contents)
+            .map_err(|error| PolylineWriterError::FileWriteError { error })?;
+
+        Ok(())
+    }
+}
@@ -1,19 +1,22 @@
 use std::{
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+use serde::Deserialize;
 use tracing::info;
 
 use crate::{
-    gpx_writer::{GpxWriter, GpxWriterError},
+    gpx_writer::{GpxOptions, GpxWriter, GpxWriterError},
     ipc_handler::ResponseMessage,
+    polyline_writer::{PolylineWriter, PolylineWriterError},
 };
 
 #[derive(Debug)]
 pub enum ResultWriterError {
     SerializeJson { error: serde_json::Error },
     Gpx { error: GpxWriterError },
+    Polyline { error: PolylineWriterError },
     RoutesGenerationFailed { error: String },
     Stdout { error: io::Error },
     FileWrite { error: io::Error },
@@ -23,15 +26,80 @@ pub enum ResultWriterError {
 pub enum DataDestination {
     Stdout,
     Gpx { file: PathBuf },
+    GpxTrack { file: PathBuf },
     Json { file: PathBuf },
+    Polyline { file: PathBuf },
+}
+
+/// Output formats for `--format`, each is written next to the output path with its own
+/// extension
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// GPX routes with a CSV summary
+    Gpx,
+    /// GPX tracks with a CSV summary, for devices that only show tracks
+    GpxTrack,
+    Json,
+    /// Encoded polylines, one route per line
+    Polyline,
+}
+
+impl DataDestination {
+    pub fn get_file(&self) -> Option<&Path> {
+        match self {
+            DataDestination::Stdout => None,
+            DataDestination::Gpx { file }
+            | DataDestination::GpxTrack { file }
+            | DataDestination::Json { file }
+            | DataDestination::Polyline { file } => Some(file),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn get_data_destination(&self, output: &Path) -> DataDestination {
+        match self {
+            OutputFormat::Gpx => DataDestination::Gpx {
+                file: output.with_extension("gpx"),
+            },
+            OutputFormat::GpxTrack => DataDestination::GpxTrack {
+                file: output.with_extension("track.gpx"),
+            },
+            OutputFormat::Json => DataDestination::Json {
+                file: output.with_extension("json"),
+            },
+            OutputFormat::Polyline => DataDestination::Polyline {
+                file: output.with_extension("txt"),
+            },
+        }
+    }
 }
 
 pub struct ResultWriter;
 impl ResultWriter {
+    /// Writes the response to every destination, stops at the first error. Missing parent
+    /// directories are created. `gpx_options` are used for the GPX destinations
+    pub fn write_all(
+        dests: &[DataDestination],
+        response: ResponseMessage,
+        gpx_options: GpxOptions,
+    ) -> Result<(), ResultWriterError> {
+        for dest in dests {
+            if let Some(dir) = dest.get_file().and_then(|file| file.parent()) {
+                std::fs::create_dir_all(dir)
+                    .map_err(|error| ResultWriterError::FileWrite { error })?;
+            }
+            ResultWriter::write(dest.clone(), response.clone(), gpx_options)?;
+        }
+        Ok(())
+    }
+
     #[tracing::instrument(skip(response))]
     pub fn write(
         dest: DataDestination,
         response: ResponseMessage,
+        gpx_options: GpxOptions,
     ) -> Result<(), ResultWriterError> {
         match dest {
             DataDestination::Stdout => {
@@ -49,16 +117,47 @@ impl ResultWriter {
                 crate::ipc_handler::RouterResult::Error { message } => {
                     Err(ResultWriterError::RoutesGenerationFailed { error: message })
                 }
-                crate::ipc_handler::RouterResult::Ok { routes } => {
+                crate::ipc_handler::RouterResult::Ok { routes, .. } => {
                     info!("Writing gpx {:?}", file);
 
                     GpxWriter::new(routes, file.clone())
+                        .options(gpx_options)
+                        .write_gpx()
+                        .map_err(|error| ResultWriterError::Gpx { error })?;
+
+                    Ok(())
+                }
+            },
+            DataDestination::GpxTrack { file } => match response.result {
+                crate::ipc_handler::RouterResult::Error { message } => {
+                    Err(ResultWriterError::RoutesGenerationFailed { error: message })
+                }
+                crate::ipc_handler::RouterResult::Ok { routes, .. } => {
+                    info!("Writing gpx tracks {:?}", file);
+
+                    GpxWriter::new(routes, file.clone())
+                        .tracks(true)
+                        .options(gpx_options)
                         .write_gpx()
                         .map_err(|error| ResultWriterError::Gpx { error })?;
 
                     Ok(())
                 }
             },
+            DataDestination::Polyline { file } => match response.result {
+                crate::ipc_handler::RouterResult::Error { message } => {
+                    Err(ResultWriterError::RoutesGenerationFailed { error: message })
+                }
+                crate::ipc_handler::RouterResult::Ok { routes, .. } => {
+                    info!("Writing polylines {:?}", file);
+
+                    PolylineWriter::new(routes, file.clone())
+                        .write_polylines()
+                        .map_err(|error| ResultWriterError::Polyline { error })?;
+
+                    Ok(())
+                }
+            },
             DataDestination::Json { file } => {
                 let json = serde_json::to_string(&response)
                     .map_err(|error| ResultWriterError::SerializeJson { error })?;
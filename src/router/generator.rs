@@ -1,28 +1,99 @@
 use crate::{
+    geo_utils,
     map_data::graph::{MapDataGraph, MapDataPointRef},
-    router::rules::RouterRules,
+    router::rules::{RouterRules, RulesWeight},
 };
-use geo::{HaversineDestination, Point};
 use rayon::prelude::*;
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use tracing::{info, trace, warn};
 
 use super::{
     itinerary::Itinerary,
-    navigator::{NavigationResult, Navigator},
+    navigator::{retain_forks_on_route, ForkExplanation, NavigationResult, Navigator},
     route::Route,
     weights::{
-        weight_check_distance_to_next, weight_heading, weight_no_loops, weight_prefer_same_road,
-        weight_progress_speed, weight_rules_highway, weight_rules_smoothness, weight_rules_surface,
+        weight_avoid_hairpins, weight_avoid_way_ids, weight_check_distance_to_next,
+        weight_corridor, weight_heading, weight_no_loops, weight_no_sharp_turns,
+        weight_penalize_destination_access, weight_prefer_consistent_class,
+        weight_prefer_described_ways, weight_prefer_numbered_roads, weight_prefer_same_road,
+        weight_progress_speed, weight_roundabout_preference, weight_rules_bridge,
+        weight_rules_ford, weight_rules_highway, weight_rules_lit, weight_rules_smoothness,
+        weight_rules_surface, weight_rules_tunnel, weight_vehicle_dimensions, WeightCalc,
     },
 };
 
-const ITINERARY_VARIATION_DISTANCES: [f32; 2] = [10000., 20000.];
+pub const ITINERARY_VARIATION_DISTANCES: [f32; 2] = [10000., 20000.];
+/// Distance at which a generated waypoint counts as reached when the graph around it is dense
+const WAYPOINT_RADIUS_M: f32 = 1000.;
+/// Upper bound for the widened waypoint radius, a larger one would make a waypoint count as
+/// reached by routes that never go near it
+const MAX_WAYPOINT_RADIUS_M: f32 = ITINERARY_VARIATION_DISTANCES[0] / 4.;
+/// Start and finish closer than this make a round trip, a waypoint this far away counts as
+/// reached so the straight line between them says nothing about the length of the route
+const ROUND_TRIP_MAX_M: f32 = WAYPOINT_RADIUS_M;
 const ITINERARY_VARIATION_DEGREES: [f32; 8] = [0., 45., 90., 135., 180., -45., -90., -135.];
 
+pub struct GeneratedRoute {
+    pub route: Route,
+    pub itinerary: Itinerary,
+    /// Decisions at the forks of the route keyed by the fork OSM id, empty unless `explain`
+    /// is set
+    pub fork_explanations: BTreeMap<u64, ForkExplanation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItineraryResult {
+    Finished,
+    Stopped,
+    Stuck,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItineraryOutcomePoint {
+    pub id: u64,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+/// How navigation went for a single itinerary, reported for every itinerary so that the ones
+/// that did not produce a route can be told apart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItineraryOutcome {
+    pub itinerary_id: String,
+    pub result: ItineraryResult,
+    pub steps: usize,
+    /// Fork choices the navigator backtracked out of, see `Navigator::get_discarded_choice_count`
+    pub discarded_choices: usize,
+    pub max_backtrack_depth: usize,
+    /// Last fork the navigator was at
+    pub last_point: Option<ItineraryOutcomePoint>,
+}
+
+pub struct GeneratorResult {
+    pub routes: Vec<GeneratedRoute>,
+    pub outcomes: Vec<ItineraryOutcome>,
+}
+
+/// Result of a single itinerary stored in the checkpoint dir, `route` is `None` when the
+/// navigator got stuck
+#[derive(Serialize, Deserialize)]
+struct ItineraryCheckpoint {
+    route: Option<Route>,
+    itinerary: Itinerary,
+    outcome: ItineraryOutcome,
+    fork_explanations: BTreeMap<u64, ForkExplanation>,
+    /// Written with `explain`, otherwise `fork_explanations` is empty
+    explained: bool,
+}
+
 pub struct Generator {
     start: MapDataPointRef,
     finish: MapDataPointRef,
     rules: RouterRules,
+    checkpoint_dir: Option<PathBuf>,
+    explain: bool,
 }
 
 impl Generator {
@@ -31,20 +102,201 @@ impl Generator {
             start,
             finish,
             rules,
+            checkpoint_dir: None,
+            explain: false,
         }
     }
 
+    /// Keep the weight calc results at the forks of each route in
+    /// `GeneratedRoute::fork_explanations`. Sections replaced by the local search are not
+    /// explained
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Write the result of each itinerary to `checkpoint_dir` and reuse results that are
+    /// already there, so an interrupted run can be resumed.
+    ///
+    /// Checkpoints are keyed by `Itinerary::id` and reference points and lines by their index,
+    /// so they are only valid for the same map data and rules, use a new dir if either changes.
+    /// With `explain` checkpoints written without it are navigated again.
+    pub fn checkpoint_dir(mut self, checkpoint_dir: Option<PathBuf>) -> Self {
+        self.checkpoint_dir = checkpoint_dir;
+        self
+    }
+
+    fn get_checkpoint_file(&self, itinerary: &Itinerary) -> Option<PathBuf> {
+        self.checkpoint_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.checkpoint", itinerary.id())))
+    }
+
+    fn read_checkpoint(&self, itinerary: &Itinerary) -> Option<ItineraryCheckpoint> {
+        let file = self.get_checkpoint_file(itinerary)?;
+        let contents = std::fs::read(&file).ok()?;
+        match bincode::deserialize(&contents[..]) {
+            Ok(checkpoint) => Some(checkpoint),
+            Err(error) => {
+                tracing::error!("Failed to read checkpoint {:?}: {:?}", file, error);
+                None
+            }
+        }
+    }
+
+    fn write_checkpoint(&self, checkpoint: &ItineraryCheckpoint) -> () {
+        let file = match self.get_checkpoint_file(&checkpoint.itinerary) {
+            None => return,
+            Some(file) => file,
+        };
+        let result = bincode::serialize(checkpoint)
+            .map_err(|error| format!("{:?}", error))
+            .and_then(|contents| {
+                std::fs::write(&file, contents).map_err(|error| format!("{:?}", error))
+            });
+        if let Err(error) = result {
+            tracing::error!("Failed to write checkpoint {:?}: {}", file, error);
+        }
+    }
+
+    fn navigate(&self, itinerary: Itinerary, weight_calcs: &[WeightCalc]) -> ItineraryCheckpoint {
+        if let Some(checkpoint) = self.read_checkpoint(&itinerary) {
+            if checkpoint.explained || !self.explain {
+                info!("Using checkpoint for itinerary {}", itinerary.id());
+                return checkpoint;
+            }
+        }
+        let mut navigator = Navigator::new(itinerary, self.rules.clone(), weight_calcs.to_vec())
+            .explain(self.explain);
+        let (route, result) = match navigator.generate_routes() {
+            NavigationResult::Stuck => (None, ItineraryResult::Stuck),
+            NavigationResult::Finished(route) => (Some(route), ItineraryResult::Finished),
+            NavigationResult::Stopped(route) => (Some(route), ItineraryResult::Stopped),
+        };
+        let outcome = ItineraryOutcome {
+            itinerary_id: navigator.get_itinerary().id(),
+            result,
+            steps: navigator.get_steps(),
+            discarded_choices: navigator.get_discarded_choice_count(),
+            max_backtrack_depth: navigator.get_max_backtrack_depth(),
+            last_point: navigator
+                .get_last_fork_point()
+                .map(|point| ItineraryOutcomePoint {
+                    id: point.borrow().id,
+                    lat: point.borrow().lat,
+                    lon: point.borrow().lon,
+                }),
+        };
+        let fork_explanations = route.as_ref().map_or(BTreeMap::new(), |route| {
+            navigator.get_fork_explanations(route)
+        });
+        let checkpoint = ItineraryCheckpoint {
+            route,
+            itinerary: navigator.get_itinerary().clone(),
+            outcome,
+            fork_explanations,
+            explained: self.explain,
+        };
+        self.write_checkpoint(&checkpoint);
+        checkpoint
+    }
+
+    /// Replace sections of the route between junctions with shorter ones found by navigating
+    /// between the junctions again, sections that would add loops are not used
+    fn improve_route(&self, route: Route, weight_calcs: &[WeightCalc]) -> Route {
+        let local_search = &self.rules.basic.local_search;
+        let mut route = route;
+        let mut attempts = 0;
+        let mut from_idx = 0;
+        while attempts < local_search.max_attempts {
+            let points = route.get_points();
+            let junction_idxs = points
+                .iter()
+                .enumerate()
+                .filter(|(idx, point)| {
+                    *idx == 0 || *idx == points.len() - 1 || point.borrow().is_junction()
+                })
+                .map(|(idx, _)| idx)
+                .skip_while(|idx| *idx < from_idx)
+                .collect::<Vec<_>>();
+            let (start_idx, end_idx) = match (
+                junction_idxs.first(),
+                junction_idxs
+                    .get(local_search.span_junctions)
+                    .or(junction_idxs.last()),
+            ) {
+                (Some(start_idx), Some(end_idx)) if start_idx < end_idx => (*start_idx, *end_idx),
+                _ => break,
+            };
+            attempts += 1;
+            from_idx = junction_idxs.get(1).copied().unwrap_or(end_idx);
+
+            let itinerary = Itinerary::new(
+                points[start_idx].clone(),
+                points[end_idx].clone(),
+                Vec::new(),
+                10.,
+            );
+            let mut navigator =
+                Navigator::new(itinerary, self.rules.clone(), weight_calcs.to_vec());
+            let section = match navigator.generate_routes() {
+                NavigationResult::Finished(section) => section,
+                _ => continue,
+            };
+            let section_len_m = (start_idx..end_idx)
+                .filter_map(|idx| route.get_segment_by_index(idx))
+                .map(|segment| segment.get_line().borrow().get_len_m())
+                .sum::<f32>();
+            let gain_m = section_len_m - section.get_len_m();
+            if gain_m <= 0. {
+                continue;
+            }
+            let section_segment_count = section.get_segment_count();
+            let improved = Route::from(
+                (0..start_idx)
+                    .filter_map(|idx| route.get_segment_by_index(idx).cloned())
+                    .chain(section)
+                    .chain(
+                        (end_idx..route.get_segment_count())
+                            .filter_map(|idx| route.get_segment_by_index(idx).cloned()),
+                    )
+                    .collect::<Vec<_>>(),
+            );
+            if improved.get_loop_count() > route.get_loop_count() {
+                continue;
+            }
+            info!("Local search shortened the route by {gain_m}m");
+            from_idx = start_idx + section_segment_count;
+            route = improved;
+        }
+        route
+    }
+
     fn create_waypoints_around(&self, point: &MapDataPointRef) -> Vec<MapDataPointRef> {
-        let point_geo = Point::new(point.borrow().lon, point.borrow().lat);
+        let coords = (point.borrow().lat, point.borrow().lon);
         ITINERARY_VARIATION_DEGREES
             .iter()
             .map(|bearing| {
                 ITINERARY_VARIATION_DISTANCES
                     .iter()
-                    .map(|distance| {
-                        let wp_geo = point_geo.haversine_destination(*bearing, *distance);
-                        let wp = MapDataGraph::get().get_closest_to_coords(wp_geo.y(), wp_geo.x());
-                        wp
+                    .map(move |distance| {
+                        let (lat, lon) = geo_utils::destination(coords, *bearing, *distance);
+                        let waypoint = MapDataGraph::get().get_closest_to_coords(lat, lon);
+                        if let Some(waypoint) = &waypoint {
+                            let snap_distance_m = geo_utils::distance(
+                                (lat, lon),
+                                (waypoint.borrow().lat, waypoint.borrow().lon),
+                            );
+                            if snap_distance_m > MAX_WAYPOINT_RADIUS_M {
+                                warn!(
+                                    bearing,
+                                    distance,
+                                    snap_distance_m,
+                                    "Closest point to the waypoint is far from it"
+                                );
+                            }
+                        }
+                        waypoint
                     })
                     .filter_map(|maybe_wp| maybe_wp)
             })
@@ -52,6 +304,23 @@ impl Generator {
             .collect()
     }
 
+    /// `WAYPOINT_RADIUS_M` widened to the longest line at any of the waypoints, up to
+    /// `MAX_WAYPOINT_RADIUS_M`. In sparse graphs the closest route point can be a full line
+    /// away from the waypoint node, with a smaller radius the waypoint would never count as
+    /// reached
+    fn get_waypoint_radius(waypoints: &[MapDataPointRef]) -> f32 {
+        let radius = waypoints
+            .iter()
+            .flat_map(|waypoint| waypoint.borrow().lines.iter())
+            .map(|line| line.borrow().get_len_m())
+            .fold(WAYPOINT_RADIUS_M, f32::max)
+            .min(MAX_WAYPOINT_RADIUS_M);
+        if radius > WAYPOINT_RADIUS_M {
+            trace!(radius, "Widened the waypoint radius for sparse nodes");
+        }
+        radius
+    }
+
     #[tracing::instrument(skip(self))]
     fn generate_itineraries(&self) -> Vec<Itinerary> {
         let from_waypoints = self.create_waypoints_around(&self.start);
@@ -65,45 +334,431 @@ impl Generator {
 
         from_waypoints.iter().for_each(|from_wp| {
             to_waypoints.iter().for_each(|to_wp| {
+                let waypoints = vec![from_wp.clone(), to_wp.clone()];
                 itineraries.push(Itinerary::new(
                     self.start.clone(),
                     self.finish.clone(),
-                    vec![from_wp.clone(), to_wp.clone()],
-                    1000.,
+                    waypoints.clone(),
+                    Generator::get_waypoint_radius(&waypoints),
                 ))
             })
         });
         itineraries
     }
 
+    fn get_weight_calcs(&self) -> Vec<WeightCalc> {
+        self.rules
+            .get_weights()
+            .iter()
+            .map(|weight| match weight {
+                RulesWeight::ProgressSpeed => weight_progress_speed as WeightCalc,
+                RulesWeight::CheckDistanceToNext => weight_check_distance_to_next,
+                RulesWeight::PreferSameRoad => weight_prefer_same_road,
+                RulesWeight::NoLoops => weight_no_loops,
+                RulesWeight::Heading => weight_heading,
+                RulesWeight::RulesHighway => weight_rules_highway,
+                RulesWeight::RulesSurface => weight_rules_surface,
+                RulesWeight::RulesSmoothness => weight_rules_smoothness,
+                RulesWeight::RulesBridge => weight_rules_bridge,
+                RulesWeight::RulesTunnel => weight_rules_tunnel,
+                RulesWeight::RulesFord => weight_rules_ford,
+                RulesWeight::DestinationAccess => weight_penalize_destination_access,
+                RulesWeight::PreferNumberedRoads => weight_prefer_numbered_roads,
+                RulesWeight::AvoidHairpins => weight_avoid_hairpins,
+                RulesWeight::VehicleDimensions => weight_vehicle_dimensions,
+                RulesWeight::NoSharpTurns => weight_no_sharp_turns,
+                RulesWeight::AvoidWayIds => weight_avoid_way_ids,
+                RulesWeight::RulesLit => weight_rules_lit,
+                RulesWeight::Corridor => weight_corridor,
+                RulesWeight::PreferConsistentClass => weight_prefer_consistent_class,
+                RulesWeight::PreferDescribedWays => weight_prefer_described_ways,
+                RulesWeight::RoundaboutPreference => weight_roundabout_preference,
+            })
+            .collect()
+    }
+
     #[tracing::instrument(skip(self))]
-    pub fn generate_routes(self) -> Vec<Route> {
+    pub fn generate_routes(self) -> GeneratorResult {
         let itineraries = self.generate_itineraries();
         info!("Created {} itineraries", itineraries.len());
-        itineraries
+        let weight_calcs = self.get_weight_calcs();
+        if let Some(checkpoint_dir) = &self.checkpoint_dir {
+            if let Err(error) = std::fs::create_dir_all(checkpoint_dir) {
+                tracing::error!("Failed to create checkpoint dir: {:?}", error);
+            }
+        }
+        let checkpoints = itineraries
             .into_par_iter()
-            .map(|itinerary| {
-                Navigator::new(
-                    itinerary,
-                    self.rules.clone(),
-                    vec![
-                        weight_progress_speed,
-                        weight_check_distance_to_next,
-                        weight_prefer_same_road,
-                        weight_no_loops,
-                        weight_heading,
-                        weight_rules_highway,
-                        weight_rules_surface,
-                        weight_rules_smoothness,
-                    ],
-                )
+            .map(|itinerary| self.navigate(itinerary, &weight_calcs))
+            .collect::<Vec<_>>();
+
+        let mut result = GeneratorResult {
+            routes: Vec::new(),
+            outcomes: Vec::new(),
+        };
+        for checkpoint in checkpoints {
+            if checkpoint.outcome.result != ItineraryResult::Finished {
+                info!(
+                    outcome = debug(&checkpoint.outcome),
+                    "Itinerary did not finish"
+                );
+            }
+            result.outcomes.push(checkpoint.outcome);
+            if let Some(route) = checkpoint.route {
+                result.routes.push(GeneratedRoute {
+                    route,
+                    itinerary: checkpoint.itinerary,
+                    fork_explanations: checkpoint.fork_explanations,
+                });
+            }
+        }
+        info!(
+            "Generated {} routes from {} itineraries",
+            result.routes.len(),
+            result.outcomes.len()
+        );
+
+        if self.rules.basic.local_search.enabled {
+            result.routes = result
+                .routes
+                .into_par_iter()
+                .map(|generated| {
+                    let route = self.improve_route(generated.route, &weight_calcs);
+                    let mut fork_explanations = generated.fork_explanations;
+                    retain_forks_on_route(&mut fork_explanations, &route);
+                    GeneratedRoute {
+                        route,
+                        itinerary: generated.itinerary,
+                        fork_explanations,
+                    }
+                })
+                .collect();
+        }
+
+        let max_detour = &self.rules.basic.max_detour;
+        let start_finish_m = self.start.borrow().distance_between(&self.finish);
+        if max_detour.enabled && start_finish_m < ROUND_TRIP_MAX_M {
+            warn!("Max detour does not apply to a round trip, keeping all routes");
+        } else if max_detour.enabled {
+            let max_len_m = start_finish_m * max_detour.ratio;
+            let route_count = result.routes.len();
+            result
+                .routes
+                .retain(|generated| generated.route.get_len_m() <= max_len_m);
+            info!(
+                "Discarded {} routes longer than {max_len_m}m",
+                route_count - result.routes.len()
+            );
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::{
+            graph::MapDataGraph,
+            osm::{OsmNode, OsmWay},
+        },
+        router::{
+            generator::{Generator, ItineraryResult, MAX_WAYPOINT_RADIUS_M, WAYPOINT_RADIUS_M},
+            route::{segment::Segment, Route},
+            rules::RouterRules,
+        },
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1, TestGraphBuilder},
+    };
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn generate_routes_checkpoint() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let checkpoint_dir =
+                std::env::temp_dir().join(format!("ridi-router-checkpoint-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&checkpoint_dir);
+
+            let generator = Generator::new(from.clone(), to.clone(), RouterRules::default())
+                .checkpoint_dir(Some(checkpoint_dir.clone()));
+            // small test graph snaps many waypoints to the same point, so ids repeat
+            let itinerary_ids = generator
+                .generate_itineraries()
+                .iter()
+                .map(|itinerary| itinerary.id())
+                .collect::<HashSet<_>>();
+            let routes = generator.generate_routes().routes;
+            assert_eq!(
+                std::fs::read_dir(&checkpoint_dir).unwrap().count(),
+                itinerary_ids.len()
+            );
+
+            let resumed_routes = Generator::new(from.clone(), to.clone(), RouterRules::default())
+                .checkpoint_dir(Some(checkpoint_dir.clone()))
                 .generate_routes()
-            })
-            .filter_map(|nav_route| match nav_route {
-                NavigationResult::Stuck => None,
-                NavigationResult::Finished(route) => Some(route),
-                NavigationResult::Stopped(route) => Some(route),
-            })
-            .collect::<Vec<_>>()
+                .routes;
+            assert_eq!(resumed_routes.len(), routes.len());
+            for (resumed, route) in resumed_routes.iter().zip(routes.iter()) {
+                assert_eq!(resumed.route, route.route);
+                assert_eq!(resumed.itinerary.id(), route.itinerary.id());
+            }
+
+            // checkpoints without explanations are not reused with explain
+            let explained_routes = Generator::new(from, to, RouterRules::default())
+                .checkpoint_dir(Some(checkpoint_dir.clone()))
+                .explain(true)
+                .generate_routes()
+                .routes;
+            assert!(explained_routes
+                .iter()
+                .any(|generated| !generated.fork_explanations.is_empty()));
+
+            std::fs::remove_dir_all(&checkpoint_dir).unwrap();
+        }
+
+        #[test]
+        fn generate_routes_outcomes() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+
+            let generator = Generator::new(from, to, RouterRules::default());
+            let itinerary_count = generator.generate_itineraries().len();
+            let result = generator.generate_routes();
+
+            assert_eq!(result.outcomes.len(), itinerary_count);
+            assert_eq!(
+                result.routes.len(),
+                result
+                    .outcomes
+                    .iter()
+                    .filter(|outcome| outcome.result != ItineraryResult::Stuck)
+                    .count()
+            );
+            assert!(result.outcomes.iter().all(|outcome| outcome.steps > 0));
+            assert!(result
+                .outcomes
+                .iter()
+                .any(|outcome| outcome.result == ItineraryResult::Finished));
+        }
+
+        #[test]
+        fn generate_routes_max_detour() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let generate = |ratio| {
+                let mut rules = RouterRules::default();
+                rules.basic.max_detour.enabled = true;
+                rules.basic.max_detour.ratio = ratio;
+                Generator::new(from.clone(), to.clone(), rules)
+                    .generate_routes()
+                    .routes
+            };
+
+            let routes = Generator::new(from.clone(), to.clone(), RouterRules::default())
+                .generate_routes()
+                .routes;
+            assert_eq!(generate(1000.).len(), routes.len());
+
+            let max_len_m = from.borrow().distance_between(&to) * 1.5;
+            assert!(generate(1.5)
+                .iter()
+                .all(|generated| generated.route.get_len_m() <= max_len_m));
+
+            // a round trip has no straight line distance to compare to
+            let mut rules = RouterRules::default();
+            rules.basic.max_detour.enabled = true;
+            let round_trip = |rules| {
+                Generator::new(from.clone(), from.clone(), rules)
+                    .generate_routes()
+                    .routes
+                    .len()
+            };
+            let route_count = round_trip(rules);
+            assert!(route_count > 0);
+            assert_eq!(route_count, round_trip(RouterRules::default()));
+        }
+
+        #[test]
+        fn generate_routes_max_detour_adjacent_start_finish() {
+            // 1 and 2 are 11m apart, 3 and 4 loop back from 2 to 1 over 2km
+            let mut map_data = TestGraphBuilder::new()
+                .nodes_at(&[
+                    (1, 0., 0.),
+                    (2, 0., 0.0001),
+                    (3, 0.01, 0.0001),
+                    (4, 0.01, 0.),
+                ])
+                .way(1, &[1, 2], &[("highway", "primary")])
+                .way(2, &[2, 3, 4, 1], &[("highway", "primary")])
+                .build();
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let start_finish_m = from.borrow().distance_between(&to);
+            let generate = |rules| Generator::new(from.clone(), to.clone(), rules).generate_routes();
+
+            let routes = generate(RouterRules::default()).routes;
+            assert!(routes
+                .iter()
+                .any(|generated| generated.route.get_len_m() > start_finish_m * 2.5));
+
+            let mut rules = RouterRules::default();
+            rules.basic.max_detour.enabled = true;
+            assert_eq!(generate(rules).routes.len(), routes.len());
+        }
+
+        #[test]
+        fn generate_routes_across_antimeridian() {
+            // Fiji, the road crosses from 179.99 east to 179.99 west
+            let nodes = vec![
+                (1, -17., 179.97),
+                (2, -17., 179.98),
+                (3, -17., 179.999),
+                (4, -17., -179.99),
+                (5, -17., -179.98),
+            ]
+            .into_iter()
+            .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+            .collect();
+            let ways = vec![OsmWay {
+                id: 1,
+                point_ids: vec![1, 2, 3, 4, 5],
+                tags: Some(HashMap::from([(
+                    "highway".to_string(),
+                    "primary".to_string(),
+                )])),
+            }];
+            let mut map_data = graph_from_test_dataset((nodes, ways, Vec::new()));
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let closest = MapDataGraph::get()
+                .get_closest_to_coords(-17., -179.9999)
+                .unwrap();
+            assert_eq!(closest.borrow().id, 3);
+
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&5).unwrap();
+            let generator = Generator::new(from, to, RouterRules::default());
+            assert!(generator.generate_itineraries().len() > 1);
+            let result = generator.generate_routes();
+            assert!(result
+                .outcomes
+                .iter()
+                .any(|outcome| outcome.result == ItineraryResult::Finished));
+            assert!(result.routes.iter().any(|generated| generated
+                .route
+                .get_points()
+                .iter()
+                .map(|point| point.borrow().id)
+                .eq([1, 2, 3, 4, 5])));
+        }
+
+        #[test]
+        fn waypoint_radius_follows_node_spacing() {
+            // 1 - 2 is about 5.5km, 2 - 3 about 1.1km, 3 - 4 - 5 about 550m each
+            let nodes = vec![
+                (1, 0., 0.),
+                (2, 0.05, 0.),
+                (3, 0.06, 0.),
+                (4, 0.065, 0.),
+                (5, 0.07, 0.),
+            ]
+            .into_iter()
+            .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+            .collect();
+            let ways = vec![OsmWay {
+                id: 1,
+                point_ids: vec![1, 2, 3, 4, 5],
+                tags: Some(HashMap::from([(
+                    "highway".to_string(),
+                    "primary".to_string(),
+                )])),
+            }];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let line_len_m = point(2).borrow().distance_between(&point(3));
+
+            assert_eq!(Generator::get_waypoint_radius(&[]), WAYPOINT_RADIUS_M);
+            assert_eq!(
+                Generator::get_waypoint_radius(&[point(4), point(5)]),
+                WAYPOINT_RADIUS_M
+            );
+            assert_eq!(
+                Generator::get_waypoint_radius(&[point(4), point(3)]),
+                line_len_m
+            );
+            assert_eq!(
+                Generator::get_waypoint_radius(&[point(2)]),
+                MAX_WAYPOINT_RADIUS_M
+            );
+            assert_eq!(
+                Generator::get_waypoint_radius(&[point(1)]),
+                MAX_WAYPOINT_RADIUS_M
+            );
+        }
+
+        #[test]
+        fn improve_route_local_search() {
+            // 10 - 1 - 4 - 5 with a detour 1 - 2 - 3 - 4 north of it
+            let nodes = vec![
+                (10, 0., -0.01),
+                (1, 0., 0.),
+                (2, 0.01, 0.),
+                (3, 0.01, 0.01),
+                (4, 0., 0.01),
+                (5, 0., 0.02),
+            ]
+            .into_iter()
+            .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+            .collect();
+            let way = |id, point_ids| OsmWay {
+                id,
+                point_ids,
+                tags: Some(HashMap::from([(
+                    "highway".to_string(),
+                    "primary".to_string(),
+                )])),
+            };
+            let ways = vec![way(1, vec![10, 1, 4, 5]), way(2, vec![1, 2, 3, 4])];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let mut route = Route::new();
+            for (from, to) in [(10, 1), (1, 2), (2, 3), (3, 4), (4, 5)] {
+                let (line, end_point) = MapDataGraph::get()
+                    .get_adjacent(point(from))
+                    .into_iter()
+                    .find(|(_, end_point)| end_point.borrow().id == to)
+                    .unwrap();
+                route.add_segment(Segment::new(line, end_point));
+            }
+            let ids = |route: &Route| {
+                route
+                    .get_points()
+                    .iter()
+                    .map(|point| point.borrow().id)
+                    .collect::<Vec<_>>()
+            };
+
+            let mut rules = RouterRules::default();
+            rules.basic.local_search.span_junctions = 1;
+            let generator = Generator::new(point(10), point(5), rules);
+            let weight_calcs = generator.get_weight_calcs();
+            let improved = generator.improve_route(route.clone(), &weight_calcs);
+            assert_eq!(ids(&improved), vec![10, 1, 4, 5]);
+            assert!(improved.get_len_m() < route.get_len_m());
+
+            assert_eq!(
+                ids(&generator.improve_route(improved.clone(), &weight_calcs)),
+                ids(&improved)
+            );
+        }
     }
 }
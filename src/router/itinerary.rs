@@ -1,14 +1,17 @@
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
 use crate::map_data::graph::MapDataPointRef;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Itinerary {
     start: MapDataPointRef,
     finish: MapDataPointRef,
     waypoints: Vec<MapDataPointRef>,
     next: MapDataPointRef,
     waypoint_radius: f32,
+    visited_waypoints: Vec<MapDataPointRef>,
 }
 
 impl Display for Itinerary {
@@ -40,6 +43,7 @@ impl Itinerary {
             next: waypoints.get(0).map_or(finish.clone(), |w| w.clone()),
             waypoints,
             finish,
+            visited_waypoints: Vec::new(),
         }
     }
 
@@ -56,13 +60,18 @@ impl Itinerary {
         )
     }
 
+    /// Advance to the following waypoint, or the finish, once `current` is within the
+    /// waypoint radius of the next waypoint. Waypoints are taken in order and none is skipped
+    /// even when the finish or a later waypoint is closer.
     pub fn check_set_next(&mut self, current: MapDataPointRef) -> () {
-        if current.borrow().distance_between(&self.finish)
-            < current.borrow().distance_between(&self.next)
-        {
-            self.next = self.finish.clone();
-        } else if current.borrow().distance_between(&self.next) <= self.waypoint_radius {
+        if self.next == self.finish {
+            return;
+        }
+        if current.borrow().distance_between(&self.next) <= self.waypoint_radius {
             if let Some(idx) = self.waypoints.iter().position(|w| w == &self.next) {
+                if !self.visited_waypoints.contains(&self.next) {
+                    self.visited_waypoints.push(self.next.clone());
+                }
                 self.next = self
                     .waypoints
                     .get(idx + 1)
@@ -88,4 +97,97 @@ impl Itinerary {
     pub fn get_waypoints(&self) -> &Vec<MapDataPointRef> {
         &self.waypoints
     }
+
+    /// Waypoints that were within the waypoint radius when the navigator passed them
+    pub fn get_visited_waypoints(&self) -> &Vec<MapDataPointRef> {
+        &self.visited_waypoints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::{graph::MapDataGraph, osm::OsmNode},
+        router::itinerary::Itinerary,
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn itinerary_visited_waypoints() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let mut itinerary = Itinerary::new(point(1), point(9), vec![point(3), point(5)], 10.);
+            itinerary.check_set_next(point(2));
+            assert_eq!(itinerary.get_next(), &point(3));
+            assert!(itinerary.get_visited_waypoints().is_empty());
+
+            itinerary.check_set_next(point(3));
+            assert_eq!(itinerary.get_next(), &point(5));
+            assert_eq!(itinerary.get_visited_waypoints(), &vec![point(3)]);
+
+            // finish is closer than the next waypoint, waypoint 5 is still next
+            itinerary.check_set_next(point(8));
+            assert_eq!(itinerary.get_next(), &point(5));
+            assert_eq!(itinerary.get_visited_waypoints(), &vec![point(3)]);
+
+            itinerary.check_set_next(point(5));
+            assert_eq!(itinerary.get_next(), &point(9));
+            assert_eq!(itinerary.get_visited_waypoints(), &vec![point(3), point(5)]);
+        }
+
+        #[test]
+        fn itinerary_l_shaped_waypoints_in_order() {
+            //  2 --- 3
+            //  |     |
+            //  1     4 (finish)
+            //  |
+            //  0 (start)
+            let node = |id, lat, lon| OsmNode { id, lat, lon };
+            set_graph_static(graph_from_test_dataset((
+                vec![
+                    node(10, 57.0, 24.0),
+                    node(11, 57.03, 24.0),
+                    node(12, 57.06, 24.0),
+                    node(13, 57.06, 24.06),
+                    node(14, 57.03, 24.06),
+                    node(15, 57.0599, 24.0),
+                    node(16, 57.0301, 24.06),
+                ],
+                Vec::new(),
+                Vec::new(),
+            )));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let mut itinerary = Itinerary::new(
+                point(10),
+                point(14),
+                vec![point(11), point(12), point(13)],
+                100.,
+            );
+            itinerary.check_set_next(point(11));
+            assert_eq!(itinerary.get_next(), &point(12));
+
+            // the finish is much closer than waypoint 13 once at 12, it is not skipped
+            itinerary.check_set_next(point(15));
+            assert_eq!(itinerary.get_next(), &point(13));
+            itinerary.check_set_next(point(16));
+            assert_eq!(itinerary.get_next(), &point(13));
+            assert_eq!(
+                itinerary.get_visited_waypoints(),
+                &vec![point(11), point(12)]
+            );
+
+            itinerary.check_set_next(point(13));
+            assert_eq!(itinerary.get_next(), &point(14));
+            assert_eq!(
+                itinerary.get_visited_waypoints(),
+                &vec![point(11), point(12), point(13)]
+            );
+        }
+    }
 }
@@ -1,25 +1,48 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
+    time::{Duration, Instant},
 };
 
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use tracing::{field::DebugValue, info, trace};
 
 use crate::{map_data::graph::MapDataPointRef, router::rules::RouterRules};
 
 use super::{
     itinerary::Itinerary,
-    route::Route,
+    route::{segment::Segment, Route},
     walker::{Walker, WalkerMoveResult},
-    weights::{WeightCalc, WeightCalcInput},
+    weights::{CorridorTrack, WeightCalc, WeightCalcInput},
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WeightCalcResult {
     UseWithWeight(u8),
     DoNotUse,
 }
 
+/// Weight calc results of every choice at a fork in the order of the weight calcs, choices
+/// are keyed by the OSM id of the point right after the fork
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForkExplanation {
+    pub chosen: u64,
+    pub choices: BTreeMap<u64, Vec<WeightCalcResult>>,
+}
+
+/// Keep the forks, keyed by the OSM id of the fork point, where the route takes the chosen
+/// point right after the fork point
+pub fn retain_forks_on_route(forks: &mut BTreeMap<u64, ForkExplanation>, route: &Route) {
+    let taken = route
+        .get_points()
+        .windows(2)
+        .map(|points| (points[0].borrow().id, points[1].borrow().id))
+        .collect::<HashSet<_>>();
+    forks.retain(|fork_id, fork| taken.contains(&(*fork_id, fork.chosen)));
+}
+
 #[derive(Debug)]
 pub struct DiscardedForkChoices {
     choices: HashMap<MapDataPointRef, HashSet<MapDataPointRef>>,
@@ -96,9 +119,14 @@ impl ForkWeights {
         }
     }
 
+    /// Heaviest first, equal weights are ordered by the OSM id of the choice point so the
+    /// same input always picks the same fork regardless of the `HashMap` order
     fn get_choices_sorted_by_weight(&self) -> Vec<(&MapDataPointRef, &u32)> {
         let mut vec = self.weight_list.iter().collect::<Vec<_>>();
-        vec.sort_by(|v, v2| v2.1.cmp(v.1));
+        vec.sort_by(|v, v2| {
+            v2.1.cmp(v.1)
+                .then_with(|| v.0.borrow().id.cmp(&v2.0.borrow().id))
+        });
         vec
     }
 
@@ -125,6 +153,8 @@ impl Debug for ForkWeights {
     }
 }
 
+const STEP_LIMIT: usize = 1000000;
+
 pub enum NavigationResult {
     Stuck,
     Stopped(Route),
@@ -137,26 +167,165 @@ pub struct Navigator {
     walker: Walker,
     weight_calcs: Vec<WeightCalc>,
     discarded_fork_choices: DiscardedForkChoices,
+    steps: usize,
+    discarded_choice_count: usize,
+    backtrack_depth: usize,
+    max_backtrack_depth: usize,
+    last_fork_point: Option<MapDataPointRef>,
+    deadline: Option<Instant>,
+    explain: bool,
+    fork_explanations: BTreeMap<u64, ForkExplanation>,
+    corridor: Option<CorridorTrack>,
 }
 
 impl Navigator {
     pub fn new(itinerary: Itinerary, rules: RouterRules, weight_calcs: Vec<WeightCalc>) -> Self {
         Navigator {
             walker: Walker::new(itinerary.get_from().clone(), itinerary.get_to().clone()),
+            corridor: CorridorTrack::from_rules(&rules, &itinerary),
             itinerary,
             rules,
             weight_calcs,
             discarded_fork_choices: DiscardedForkChoices::new(),
+            steps: 0,
+            discarded_choice_count: 0,
+            backtrack_depth: 0,
+            max_backtrack_depth: 0,
+            last_fork_point: None,
+            deadline: None,
+            explain: false,
+            fork_explanations: BTreeMap::new(),
         }
     }
 
+    /// Record the weight calc results at each fork, see `get_fork_explanations`
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// The last decision at each fork of `route`, empty unless `explain` is set. Forks the
+    /// walker backtracked out of are left out
+    pub fn get_fork_explanations(&self, route: &Route) -> BTreeMap<u64, ForkExplanation> {
+        let mut forks = self.fork_explanations.clone();
+        retain_forks_on_route(&mut forks, route);
+        forks
+    }
+
+    pub fn get_itinerary(&self) -> &Itinerary {
+        &self.itinerary
+    }
+
+    /// Steps taken over all passes
+    pub fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Fork choices the walker backtracked out of over all passes, high counts mean the
+    /// weights keep picking choices that lead nowhere
+    pub fn get_discarded_choice_count(&self) -> usize {
+        self.discarded_choice_count
+    }
+
+    /// Most forks the walker backtracked over in a row before it could choose again
+    pub fn get_max_backtrack_depth(&self) -> usize {
+        self.max_backtrack_depth
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn backtrack(&mut self) {
+        self.walker.move_backwards_to_prev_fork();
+        self.discarded_choice_count += 1;
+        self.backtrack_depth += 1;
+        self.max_backtrack_depth = self.max_backtrack_depth.max(self.backtrack_depth);
+    }
+
+    /// Last fork the walker was at, `None` if no fork was reached
+    pub fn get_last_fork_point(&self) -> Option<&MapDataPointRef> {
+        self.last_fork_point.as_ref()
+    }
+
     #[tracing::instrument(skip(self), fields(id = self.itinerary.id()))]
-    pub fn generate_routes(mut self) -> NavigationResult {
+    pub fn generate_routes(&mut self) -> NavigationResult {
         info!("Route gen for itinerary {}", self.itinerary);
+        // a timeout that is not a valid duration or too far out to be reached runs without one
+        self.deadline = self
+            .rules
+            .itinerary_timeout_s
+            .and_then(|timeout_s| Duration::try_from_secs_f32(timeout_s).ok())
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+
+        if self.rules.basic.iterative_deepening.enabled {
+            return self.navigate_iterative_deepening();
+        }
+        self.navigate(STEP_LIMIT)
+    }
+
+    fn navigate_iterative_deepening(&mut self) -> NavigationResult {
+        let rules = self.rules.clone();
+        let itinerary = self.itinerary.clone();
+        let deepening = rules.basic.iterative_deepening.clone();
+
+        let mut best: Option<(Route, Itinerary, BTreeMap<u64, ForkExplanation>)> = None;
+        let mut last_result = NavigationResult::Stuck;
+        for pass in 0..deepening.passes {
+            let relax_m = if deepening.passes > 1 {
+                deepening.initial_tolerance_m * (deepening.passes - 1 - pass) as f32
+                    / (deepening.passes - 1) as f32
+            } else {
+                deepening.initial_tolerance_m
+            };
+
+            self.rules = rules.clone();
+            self.rules.basic.progression_direction.tolerance_m += relax_m;
+            self.itinerary = itinerary.clone();
+            self.walker = Walker::new(itinerary.get_from().clone(), itinerary.get_to().clone());
+            self.discarded_fork_choices = DiscardedForkChoices::new();
+            self.backtrack_depth = 0;
+            self.fork_explanations = BTreeMap::new();
+
+            match self.navigate(deepening.pass_step_limit) {
+                NavigationResult::Finished(route) => {
+                    info!(pass, relax_m, "Iterative deepening pass finished");
+                    best = Some((
+                        route,
+                        self.itinerary.clone(),
+                        self.fork_explanations.clone(),
+                    ));
+                }
+                result => {
+                    info!(pass, relax_m, "Iterative deepening pass did not finish");
+                    if best.is_some() {
+                        break;
+                    }
+                    last_result = result;
+                    if self.timed_out() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.rules = rules;
+        match best {
+            Some((route, itinerary, fork_explanations)) => {
+                self.itinerary = itinerary;
+                self.fork_explanations = fork_explanations;
+                NavigationResult::Finished(route)
+            }
+            None => last_result,
+        }
+    }
 
+    fn navigate(&mut self, step_limit: usize) -> NavigationResult {
         let mut loop_counter = 0;
         loop {
             loop_counter += 1;
+            self.steps += 1;
 
             let move_result = self.walker.move_forward_to_next_fork();
 
@@ -166,6 +335,17 @@ impl Navigator {
                 "Next step"
             );
 
+            #[cfg(feature = "live-debug")]
+            if self.steps % crate::live_debug::PUBLISH_EVERY_STEPS == 0
+                || move_result == Ok(WalkerMoveResult::Finish)
+            {
+                crate::live_debug::publish_route(
+                    &self.itinerary.id(),
+                    self.walker.get_route(),
+                    move_result == Ok(WalkerMoveResult::Finish),
+                );
+            }
+
             if move_result == Ok(WalkerMoveResult::Finish) {
                 trace!("Finished with route");
                 return NavigationResult::Finished(self.walker.get_route().clone());
@@ -185,35 +365,42 @@ impl Navigator {
                 };
 
                 self.itinerary.check_set_next(last_point.clone());
-
-                let fork_weights = fork_choices.clone().into_iter().fold(
-                    ForkWeights::new(),
-                    |mut fork_weights, fork_route_segment| {
-                        let fork_weight_calc_results = self
+                self.last_fork_point = Some(last_point.clone());
+
+                // weight calcs walk ahead from the fork, run them for all choices in parallel
+                // and only aggregate the results afterwards
+                let fork_segments: Vec<Segment> = fork_choices.clone().into();
+                let fork_weight_calc_results = fork_segments
+                    .par_iter()
+                    .map(|fork_route_segment| {
+                        let weight_calc_results = self
                             .weight_calcs
                             .iter()
                             .map(|weight_calc| {
                                 let weight_calc_result = weight_calc(WeightCalcInput {
                                     route: self.walker.get_route(),
                                     itinerary: &self.itinerary,
-                                    current_fork_segment: &fork_route_segment,
+                                    current_fork_segment: fork_route_segment,
                                     all_fork_segments: &fork_choices,
                                     walker_from_fork: Walker::new(
                                         fork_route_segment.get_end_point().clone(),
                                         self.itinerary.get_next().clone(),
                                     ),
                                     rules: &self.rules,
+                                    corridor: self.corridor.as_ref(),
                                 });
                                 trace!(result = debug(&weight_calc_result), "Weight calc");
                                 weight_calc_result
                             })
                             .collect::<Vec<_>>();
+                        (fork_route_segment.get_end_point(), weight_calc_results)
+                    })
+                    .collect::<Vec<_>>();
 
-                        fork_weights.add_calc_result(
-                            &fork_route_segment.get_end_point(),
-                            &fork_weight_calc_results,
-                        );
-
+                let fork_weights = fork_weight_calc_results.iter().fold(
+                    ForkWeights::new(),
+                    |mut fork_weights, (choice_point_ref, weight_calc_results)| {
+                        fork_weights.add_calc_result(choice_point_ref, weight_calc_results);
                         fork_weights
                     },
                 );
@@ -222,24 +409,43 @@ impl Navigator {
                 let chosen_fork_point = fork_weights.get_choice_id_by_index_from_heaviest(0);
 
                 if let Some(chosen_fork_point) = chosen_fork_point {
+                    if self.explain {
+                        self.fork_explanations.insert(
+                            last_point.borrow().id,
+                            ForkExplanation {
+                                chosen: chosen_fork_point.borrow().id,
+                                choices: fork_weight_calc_results
+                                    .iter()
+                                    .map(|(choice_point_ref, weight_calc_results)| {
+                                        (choice_point_ref.borrow().id, weight_calc_results.clone())
+                                    })
+                                    .collect(),
+                            },
+                        );
+                    }
                     self.discarded_fork_choices
                         .add_discarded_choice(&last_point, &chosen_fork_point);
                     self.walker.set_fork_choice_point_ref(chosen_fork_point);
+                    self.backtrack_depth = 0;
                 } else {
-                    self.walker.move_backwards_to_prev_fork();
+                    self.backtrack();
                     if self.walker.get_route().get_junction_before_last_segment() == None {
                         info!("Stuck");
                         return NavigationResult::Stuck;
                     }
                 }
             } else if move_result == Ok(WalkerMoveResult::DeadEnd) {
-                self.walker.move_backwards_to_prev_fork();
+                self.backtrack();
             }
 
-            if loop_counter >= 1000000 {
+            if loop_counter >= step_limit {
                 info!("Reached loop {loop_counter}, stopping");
                 return NavigationResult::Stopped(self.walker.get_route().clone());
             }
+            if self.timed_out() {
+                info!("Reached the itinerary timeout at loop {loop_counter}, stopping");
+                return NavigationResult::Stopped(self.walker.get_route().clone());
+            }
         }
     }
 }
@@ -259,7 +465,7 @@ mod test {
         },
     };
 
-    use super::Navigator;
+    use super::{ForkWeights, Navigator};
     use rusty_fork::rusty_fork_test;
 
     rusty_fork_test! {
@@ -292,6 +498,24 @@ mod test {
             };
 
             assert!(route_matches_ids(route.clone(), vec![2, 3, 6, 7]));
+            assert!(navigator.get_fork_explanations(&route).is_empty());
+
+            let mut navigator =
+                Navigator::new(itinerary.clone(), RouterRules::default(), vec![weight])
+                    .explain(true);
+            let route = match navigator.generate_routes() {
+                crate::router::navigator::NavigationResult::Finished(r) => r,
+                _ => {
+                    assert!(false);
+                    return ();
+                }
+            };
+            let forks = navigator.get_fork_explanations(&route);
+            let fork = forks.get(&3).expect("to explain the fork at 3");
+            assert_eq!(fork.chosen, 6);
+            assert_eq!(fork.choices.get(&6), Some(&vec![WeightCalcResult::UseWithWeight(10)]));
+            assert_eq!(fork.choices.get(&4), Some(&vec![WeightCalcResult::UseWithWeight(1)]));
+            assert!(forks.keys().all(|id| [1, 2, 3, 6].contains(id)));
 
             fn weight2(input: WeightCalcInput) -> WeightCalcResult {
                 let prev_point = match input.route.get_segment_last() {
@@ -306,7 +530,7 @@ mod test {
                 }
                 WeightCalcResult::UseWithWeight(1)
             }
-            let navigator = Navigator::new(itinerary,RouterRules::default(), vec![weight2]);
+            let mut navigator = Navigator::new(itinerary,RouterRules::default(), vec![weight2]);
             let route = match navigator.generate_routes() {
                 crate::router::navigator::NavigationResult::Finished(r) => r,
                 _ => {
@@ -348,7 +572,7 @@ mod test {
             let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
             let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
             let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
-            let navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
+            let mut navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
             let route = match navigator.generate_routes() {
                 crate::router::navigator::NavigationResult::Finished(r) => r,
                 _ => {
@@ -358,6 +582,9 @@ mod test {
             };
 
             assert!(route_matches_ids(route.clone(), vec![2, 3, 6, 7]));
+            // only the choice of 5 at 3 was backtracked out of
+            assert_eq!(navigator.get_discarded_choice_count(), 1);
+            assert_eq!(navigator.get_max_backtrack_depth(), 1);
         }
     }
 
@@ -372,7 +599,7 @@ mod test {
             let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
             let to = MapDataGraph::get().test_get_point_ref_by_id(&11).unwrap();
             let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
-            let navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
+            let mut navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
 
             if let NavigationResult::Finished(_) = navigator.generate_routes() {
                 assert!(false);
@@ -394,13 +621,63 @@ mod test {
             let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
             let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
             let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
-            let navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
+            let mut navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight]);
             if let NavigationResult::Finished(_) = navigator.generate_routes() {
                 assert!(false);
             }
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_stop_on_timeout() {
+            fn weight(_input: WeightCalcInput) -> WeightCalcResult {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                WeightCalcResult::UseWithWeight(1)
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
+            let rules = RouterRules {
+                itinerary_timeout_s: Some(0.01),
+                ..RouterRules::default()
+            };
+            let mut navigator = Navigator::new(itinerary, rules, vec![weight]);
+
+            assert!(matches!(
+                navigator.generate_routes(),
+                NavigationResult::Stopped(_)
+            ));
+            assert_eq!(navigator.get_steps(), 1);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_invalid_timeout() {
+            fn weight(_input: WeightCalcInput) -> WeightCalcResult {
+                WeightCalcResult::DoNotUse
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
+
+            for timeout_s in [f32::NAN, -1., f32::MAX] {
+                let rules = RouterRules {
+                    itinerary_timeout_s: Some(timeout_s),
+                    ..RouterRules::default()
+                };
+                let mut navigator = Navigator::new(itinerary.clone(), rules, vec![weight]);
+                navigator.generate_routes();
+                assert!(navigator.deadline.is_none());
+            }
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -434,7 +711,7 @@ mod test {
             let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
             let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
             let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
-            let navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight1, weight2]);
+            let mut navigator = Navigator::new(itinerary, RouterRules::default(), vec![weight1, weight2]);
             let route = match navigator.generate_routes() {
                 crate::router::navigator::NavigationResult::Finished(r) => r,
                 _ => {
@@ -445,4 +722,95 @@ mod test {
             assert!(route_matches_ids(route.clone(), vec![2, 3, 4, 8, 6, 7]));
         }
     }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_iterative_deepening() {
+            fn weight(input: WeightCalcInput) -> WeightCalcResult {
+                let prev_point = match input.route.get_segment_last() {
+                    Some(segment) => segment.get_end_point(),
+                    None => &input.itinerary.get_to().clone(),
+                };
+                // relaxed passes prefer the detour, the last pass forbids it
+                let relaxed = input.rules.basic.progression_direction.tolerance_m > 0.;
+                if prev_point.borrow().id == 3
+                    && input.current_fork_segment.get_end_point().borrow().id == 4
+                {
+                    if relaxed {
+                        return WeightCalcResult::UseWithWeight(10);
+                    }
+                    return WeightCalcResult::DoNotUse;
+                }
+                WeightCalcResult::UseWithWeight(1)
+            }
+            fn weight_block_last(input: WeightCalcInput) -> WeightCalcResult {
+                if input.rules.basic.progression_direction.tolerance_m == 0.
+                    && input.current_fork_segment.get_end_point().borrow().id == 7
+                {
+                    return WeightCalcResult::DoNotUse;
+                }
+                WeightCalcResult::UseWithWeight(0)
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let itinerary = Itinerary::new(from, to, Vec::new(), 0.);
+            let mut rules = RouterRules::default();
+            rules.basic.iterative_deepening.enabled = true;
+            rules.basic.iterative_deepening.passes = 2;
+
+            let mut navigator = Navigator::new(itinerary.clone(), rules.clone(), vec![weight]);
+            let route = match navigator.generate_routes() {
+                NavigationResult::Finished(r) => r,
+                _ => {
+                    assert!(false);
+                    return ();
+                }
+            };
+            // the tighter pass finished and replaced the relaxed route
+            assert!(route_matches_ids(route.clone(), vec![2, 3, 6, 7]));
+
+            let mut navigator =
+                Navigator::new(itinerary, rules, vec![weight, weight_block_last]);
+            let route = match navigator.generate_routes() {
+                NavigationResult::Finished(r) => r,
+                _ => {
+                    assert!(false);
+                    return ();
+                }
+            };
+            // the tighter pass got stuck, the relaxed route is kept
+            assert!(route_matches_ids(route.clone(), vec![2, 3, 4, 8, 6, 7]));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn fork_weights_ties_sorted_by_id() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let weights = |ids: &[u64]| {
+                let mut fork_weights = ForkWeights::new();
+                for id in ids {
+                    let weight = if *id == 4 { 5 } else { 3 };
+                    fork_weights
+                        .add_calc_result(&point(*id), &vec![WeightCalcResult::UseWithWeight(weight)]);
+                }
+                (0..ids.len())
+                    .map(|idx| {
+                        fork_weights
+                            .get_choice_id_by_index_from_heaviest(idx)
+                            .unwrap()
+                            .borrow()
+                            .id
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(weights(&[7, 2, 4, 5]), vec![4, 2, 5, 7]);
+            assert_eq!(weights(&[5, 7, 2, 4]), vec![4, 2, 5, 7]);
+        }
+    }
 }
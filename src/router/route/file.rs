@@ -0,0 +1,204 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::map_data::graph::MapDataGraph;
+
+use super::{segment::Segment, Route};
+
+/// Version of the route file format, bump it when a change breaks reading older files
+pub const ROUTE_FILE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum RouteFileError {
+    FileWrite {
+        error: io::Error,
+    },
+    FileRead {
+        error: io::Error,
+    },
+    Json {
+        error: serde_json::Error,
+    },
+    UnsupportedVersion {
+        version: u32,
+    },
+    NodeNotFound {
+        node_id: u64,
+    },
+    LineNotFound {
+        way_id: u64,
+        from_node_id: u64,
+        to_node_id: u64,
+    },
+}
+
+/// A travelled line referenced by OSM ids so that the file does not depend on the graph
+/// it was generated with, `to_lat` and `to_lon` are for tools that do not load the map data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteFileSegment {
+    pub way_id: u64,
+    pub from_node_id: u64,
+    pub to_node_id: u64,
+    pub to_lat: f32,
+    pub to_lon: f32,
+}
+
+/// Stable interchange format for routes, written as JSON by `Route::save`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteFile {
+    pub version: u32,
+    pub segments: Vec<RouteFileSegment>,
+}
+
+impl RouteFile {
+    pub fn from_route(route: &Route) -> Self {
+        let points = route.get_points();
+        Self {
+            version: ROUTE_FILE_VERSION,
+            segments: route
+                .route_segments
+                .iter()
+                .enumerate()
+                .map(|(idx, segment)| RouteFileSegment {
+                    way_id: segment.get_way_id(),
+                    from_node_id: points[idx].borrow().id,
+                    to_node_id: segment.get_end_point().borrow().id,
+                    to_lat: segment.get_end_point().borrow().lat,
+                    to_lon: segment.get_end_point().borrow().lon,
+                })
+                .collect(),
+        }
+    }
+
+    /// Resolve the segments against the loaded map data
+    pub fn to_route(&self) -> Result<Route, RouteFileError> {
+        if self.version != ROUTE_FILE_VERSION {
+            return Err(RouteFileError::UnsupportedVersion {
+                version: self.version,
+            });
+        }
+        let map_data = MapDataGraph::get();
+        let get_point = |node_id| {
+            map_data
+                .get_point_ref_by_osm_id(node_id)
+                .ok_or(RouteFileError::NodeNotFound { node_id })
+        };
+        // the segments are consecutive, `from` is the end point of the previous segment and
+        // `to` is found on its lines, only the first point needs a lookup by id
+        let mut route_segments: Vec<Segment> = Vec::with_capacity(self.segments.len());
+        for segment in &self.segments {
+            let from = match route_segments.last() {
+                Some(previous) if previous.get_end_point().borrow().id == segment.from_node_id => {
+                    previous.get_end_point().clone()
+                }
+                _ => get_point(segment.from_node_id)?,
+            };
+            let line = from
+                .borrow()
+                .lines
+                .iter()
+                .find(|line| {
+                    let line = line.borrow();
+                    let other = if line.points.0 == from {
+                        &line.points.1
+                    } else {
+                        &line.points.0
+                    };
+                    line.way_id == segment.way_id && other.borrow().id == segment.to_node_id
+                })
+                .cloned()
+                .ok_or(RouteFileError::LineNotFound {
+                    way_id: segment.way_id,
+                    from_node_id: segment.from_node_id,
+                    to_node_id: segment.to_node_id,
+                })?;
+            let to = {
+                let line = line.borrow();
+                if line.points.0 == from {
+                    line.points.1.clone()
+                } else {
+                    line.points.0.clone()
+                }
+            };
+            route_segments.push(Segment::new(line, to));
+        }
+        Ok(Route { route_segments })
+    }
+}
+
+impl Route {
+    /// Write the route to `path` in the `RouteFile` format
+    pub fn save(&self, path: &Path) -> Result<(), RouteFileError> {
+        let contents = serde_json::to_string_pretty(&RouteFile::from_route(self))
+            .map_err(|error| RouteFileError::Json { error })?;
+        fs::write(path, contents).map_err(|error| RouteFileError::FileWrite { error })
+    }
+
+    /// Read a route written by `save`, the map data the route was generated on, or a newer
+    /// version of it, must be loaded
+    pub fn load(path: &Path) -> Result<Route, RouteFileError> {
+        let contents =
+            fs::read_to_string(path).map_err(|error| RouteFileError::FileRead { error })?;
+        let route_file: RouteFile =
+            serde_json::from_str(&contents).map_err(|error| RouteFileError::Json { error })?;
+        route_file.to_route()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::graph::MapDataGraph,
+        router::route::{segment::Segment, Route},
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    use super::{RouteFile, RouteFileError, ROUTE_FILE_VERSION};
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_file_round_trip() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let segment = |from: u64, to: u64| {
+                let (line, end_point) = MapDataGraph::get()
+                    .get_adjacent(point(from))
+                    .into_iter()
+                    .find(|(_, end_point)| end_point.borrow().id == to)
+                    .unwrap();
+                Segment::new(line, end_point)
+            };
+            let route = Route::from(vec![segment(1, 2), segment(2, 3), segment(3, 6)]);
+
+            let route_file = RouteFile::from_route(&route);
+            assert_eq!(route_file.version, ROUTE_FILE_VERSION);
+            assert_eq!(
+                route_file
+                    .segments
+                    .iter()
+                    .map(|segment| (segment.way_id, segment.from_node_id, segment.to_node_id))
+                    .collect::<Vec<_>>(),
+                vec![(1234, 1, 2), (1234, 2, 3), (5367, 3, 6)]
+            );
+            assert_eq!(route_file.to_route().unwrap(), route);
+
+            let mut wrong_version = route_file.clone();
+            wrong_version.version = ROUTE_FILE_VERSION + 1;
+            assert!(matches!(
+                wrong_version.to_route(),
+                Err(RouteFileError::UnsupportedVersion { .. })
+            ));
+
+            let mut wrong_way = route_file.clone();
+            wrong_way.segments[2].way_id = 1234;
+            assert!(matches!(
+                wrong_way.to_route(),
+                Err(RouteFileError::LineNotFound { way_id: 1234, .. })
+            ));
+        }
+    }
+}
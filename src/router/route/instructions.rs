@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use super::segment::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Maneuver {
+    Depart,
+    Continue,
+    SlightLeft,
+    Left,
+    SharpLeft,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+    Arrive,
+}
+
+impl Maneuver {
+    /// `turn_deg` is the change in bearing at the junction in the range -180 to 180,
+    /// negative values turn left
+    pub fn from_turn_deg(turn_deg: f32) -> Self {
+        let abs_turn = turn_deg.abs();
+        if abs_turn < 20. {
+            Maneuver::Continue
+        } else if abs_turn >= 170. {
+            Maneuver::UTurn
+        } else if turn_deg < 0. {
+            if abs_turn < 45. {
+                Maneuver::SlightLeft
+            } else if abs_turn < 135. {
+                Maneuver::Left
+            } else {
+                Maneuver::SharpLeft
+            }
+        } else if abs_turn < 45. {
+            Maneuver::SlightRight
+        } else if abs_turn < 135. {
+            Maneuver::Right
+        } else {
+            Maneuver::SharpRight
+        }
+    }
+
+    fn text(&self) -> &'static str {
+        match self {
+            Maneuver::Depart => "Depart",
+            Maneuver::Continue => "Continue",
+            Maneuver::SlightLeft => "Keep slightly left",
+            Maneuver::Left => "Turn left",
+            Maneuver::SharpLeft => "Turn sharp left",
+            Maneuver::SlightRight => "Keep slightly right",
+            Maneuver::Right => "Turn right",
+            Maneuver::SharpRight => "Turn sharp right",
+            Maneuver::UTurn => "Make a U-turn",
+            Maneuver::Arrive => "Arrive at destination",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instruction {
+    pub maneuver: Maneuver,
+    /// Name and/or ref of the road taken after the maneuver
+    pub road: Option<String>,
+    /// Signed destination of the road taken after the maneuver, shown instead of the road
+    #[serde(default)]
+    pub destination: Option<String>,
+    /// Index of the maneuver point in `Route::get_points`
+    pub point_idx: usize,
+    pub distance_to_next_m: f64,
+}
+
+impl Instruction {
+    pub fn text(&self) -> String {
+        match (&self.maneuver, &self.destination, &self.road) {
+            (Maneuver::Arrive, _, _) | (_, None, None) => self.maneuver.text().to_string(),
+            (maneuver, Some(destination), _) => {
+                format!("{} towards {destination}", maneuver.text())
+            }
+            (Maneuver::Depart, None, Some(road)) => format!("Depart on {road}"),
+            (maneuver, None, Some(road)) => format!("{} onto {road}", maneuver.text()),
+        }
+    }
+}
+
+pub fn get_road(segment: &Segment) -> Option<String> {
+    let line = segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    match (tags.name(), tags.hw_ref()) {
+        (Some(name), Some(hw_ref)) => Some(format!("{name} ({hw_ref})")),
+        (Some(name), None) => Some(name.to_string()),
+        (None, Some(hw_ref)) => Some(hw_ref.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// `destination` and `destination:ref` as they are on the signs, `Riga, Jurmala / A2`. The
+/// `:forward` and `:backward` variants are used by the direction of travel, the plain tags
+/// only apply on one way lines or when travelling forward
+pub fn get_destination(segment: &Segment) -> Option<String> {
+    let line = segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    let (places, refs) = if line.is_one_way() || line.points.1 == *segment.get_end_point() {
+        (
+            tags.destination_forward().or(tags.destination()),
+            tags.destination_ref_forward().or(tags.destination_ref()),
+        )
+    } else {
+        (tags.destination_backward(), tags.destination_ref_backward())
+    };
+    let places = places.map(|places| places.replace(';', ", "));
+    let refs = refs.map(|refs| refs.replace(';', ", "));
+    match (places, refs) {
+        (Some(places), Some(refs)) => Some(format!("{places} / {refs}")),
+        (places, refs) => places.or(refs),
+    }
+}
+
+/// Bearing of the segment in the direction of travel
+pub fn get_bearing(segment: &Segment) -> f32 {
+    let line = segment.get_line().borrow();
+    let end_point = segment.get_end_point();
+    let start_point = if line.points.1 == *end_point {
+        &line.points.0
+    } else {
+        &line.points.1
+    };
+    start_point.borrow().bearing_to(end_point)
+}
+
+/// Change in bearing from `from` to `to` normalized to -180 to 180
+pub fn get_turn_deg(from: f32, to: f32) -> f32 {
+    let turn = (to - from) % 360.;
+    if turn > 180. {
+        turn - 360.
+    } else if turn <= -180. {
+        turn + 360.
+    } else {
+        turn
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_turn_deg, Maneuver};
+
+    #[test]
+    fn turn_deg_wraps() {
+        assert_eq!(get_turn_deg(10., 100.), 90.);
+        assert_eq!(get_turn_deg(170., -170.), 20.);
+        assert_eq!(get_turn_deg(-170., 170.), -20.);
+        assert_eq!(get_turn_deg(0., 180.), 180.);
+        assert_eq!(get_turn_deg(90., 0.), -90.);
+    }
+
+    #[test]
+    fn maneuver_from_turn_deg() {
+        assert_eq!(Maneuver::from_turn_deg(5.), Maneuver::Continue);
+        assert_eq!(Maneuver::from_turn_deg(-30.), Maneuver::SlightLeft);
+        assert_eq!(Maneuver::from_turn_deg(90.), Maneuver::Right);
+        assert_eq!(Maneuver::from_turn_deg(-150.), Maneuver::SharpLeft);
+        assert_eq!(Maneuver::from_turn_deg(175.), Maneuver::UTurn);
+    }
+}
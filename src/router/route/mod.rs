@@ -1,38 +1,90 @@
+pub mod file;
+pub mod instructions;
+pub mod polyline;
 pub mod segment;
 pub mod segment_list;
 
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
-use geo::{HaversineBearing, Point as GeoPoint};
+use geo::{LineString, SimplifyIdx, MEAN_EARTH_RADIUS};
 use serde::{Deserialize, Serialize};
 
-use crate::map_data::{line::MapDataLine, point::MapDataPoint};
+use crate::{
+    geo_utils::bearing_difference,
+    map_data::{graph::MapDataPointRef, line::MapDataLine, point::MapDataPoint},
+    router::{
+        itinerary::Itinerary,
+        rules::{RouterRules, RulesDistanceMetric, RulesRouteSort},
+    },
+};
 
-use self::segment::Segment;
+use self::{
+    instructions::{get_bearing, get_destination, get_road, get_turn_deg, Instruction, Maneuver},
+    segment::Segment,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RouteStatElement {
     pub len_m: f64,
     pub percentage: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Point {
     pub lat: f64,
     pub lon: f64,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct RouteStats {
     pub len_m: f64,
     pub junction_count: u32,
     pub highway: HashMap<String, RouteStatElement>,
     pub surface: HashMap<String, RouteStatElement>,
     pub smoothness: HashMap<String, RouteStatElement>,
+    pub bridge: HashMap<String, RouteStatElement>,
+    pub tunnel: HashMap<String, RouteStatElement>,
+    pub ford: HashMap<String, RouteStatElement>,
+    /// `lit` values, `no` is the length known to be unlit
+    pub lit: HashMap<String, RouteStatElement>,
     pub mean_point: Point,
     pub direction_change_ratio: f64,
+    /// Length of the segments travelled on a line the route already used, e.g. out and back
+    /// on a dead end
+    pub retraced_len_m: f64,
+    /// Number of times the route enters a roundabout
+    pub roundabout_count: u32,
+    pub waypoints_reached: usize,
+    pub waypoints_total: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl RouteStats {
+    /// Routes with a smaller key come first in `sort`
+    pub fn get_sort_key(&self, sort: RulesRouteSort) -> f64 {
+        match sort {
+            RulesRouteSort::Length => self.len_m,
+            RulesRouteSort::Curviness => -self.direction_change_ratio,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfilePoint {
+    pub cum_dist_m: f64,
+    pub lat: f32,
+    pub lon: f32,
+    /// Always `None` until elevation data is available
+    pub elevation_m: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum RouteError {
+    OneWayReversed { line_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Route {
     route_segments: Vec<Segment>,
 }
@@ -52,6 +104,12 @@ impl Route {
     pub fn get_segment_count(&self) -> usize {
         self.route_segments.len()
     }
+    pub fn get_len_m(&self) -> f32 {
+        self.route_segments
+            .iter()
+            .map(|segment| segment.get_line().borrow().get_len_m())
+            .sum()
+    }
     pub fn remove_last_segment(&mut self) -> Option<Segment> {
         self.route_segments.pop()
     }
@@ -80,6 +138,31 @@ impl Route {
         }
         false
     }
+    /// Number of times the route came back to a point it already passed, consecutive
+    /// revisited points count as one
+    pub fn get_loop_count(&self) -> usize {
+        let mut visited = HashSet::new();
+        let mut loop_count = 0;
+        let mut prev_revisited = false;
+        for segment in &self.route_segments {
+            let revisited = !visited.insert(segment.get_end_point().clone());
+            if revisited && !prev_revisited {
+                loop_count += 1;
+            }
+            prev_revisited = revisited;
+        }
+        loop_count
+    }
+    /// Length of the segments that travel a line already used earlier in the route in either
+    /// direction, the first use of a line is not counted
+    fn get_retraced_len_m(&self, distance_metric: &RulesDistanceMetric) -> f64 {
+        let mut used_lines = HashSet::new();
+        self.route_segments
+            .iter()
+            .filter(|segment| !used_lines.insert(segment.get_line().borrow().line_id()))
+            .map(|segment| get_line_len_m(segment, distance_metric))
+            .sum()
+    }
     pub fn get_steps_from_end(&self, num_of_steps: usize) -> Option<Segment> {
         if self.route_segments.len() < num_of_steps + 1 {
             return None;
@@ -88,8 +171,185 @@ impl Route {
             .get(self.route_segments.len() - 1 - num_of_steps)
             .cloned()
     }
+    pub fn get_start_point(&self) -> Option<MapDataPointRef> {
+        let first_segment = self.route_segments.first()?;
+        let line = first_segment.get_line().borrow();
+        if line.points.0 == *first_segment.get_end_point() {
+            Some(line.points.1.clone())
+        } else {
+            Some(line.points.0.clone())
+        }
+    }
+    /// Start point followed by the end points of all segments in route order
+    pub fn get_points(&self) -> Vec<MapDataPointRef> {
+        let mut points = Vec::new();
+        if let Some(start_point) = self.get_start_point() {
+            points.push(start_point);
+        }
+        points.extend(
+            self.route_segments
+                .iter()
+                .map(|segment| segment.get_end_point().clone()),
+        );
+        points
+    }
+    /// Cumulative distance at each point of `get_points`
+    pub fn profile(&self) -> Vec<ProfilePoint> {
+        let mut cum_dist_m: f64 = 0.;
+        let mut profile = Vec::with_capacity(self.route_segments.len() + 1);
+        if let Some(start_point) = self.get_start_point() {
+            profile.push(ProfilePoint {
+                cum_dist_m,
+                lat: start_point.borrow().lat,
+                lon: start_point.borrow().lon,
+                elevation_m: None,
+            });
+        }
+        for segment in &self.route_segments {
+            cum_dist_m += segment.get_line().borrow().get_len_m() as f64;
+            profile.push(ProfilePoint {
+                cum_dist_m,
+                lat: segment.get_end_point().borrow().lat,
+                lon: segment.get_end_point().borrow().lon,
+                elevation_m: None,
+            });
+        }
+        profile
+    }
+    /// Points where refueling is suggested with a range of `max_range_m`, each is the last
+    /// point that can be reached since the previous refuel. Segments longer than the range
+    /// are not split.
+    pub fn get_refuel_points(&self, max_range_m: f64) -> Vec<ProfilePoint> {
+        let profile = self.profile();
+        let mut refuel_points: Vec<ProfilePoint> = Vec::new();
+        let mut last_refuel_m = 0.;
+        for points in profile.windows(2) {
+            if points[1].cum_dist_m - last_refuel_m > max_range_m
+                && points[0].cum_dist_m > last_refuel_m
+            {
+                last_refuel_m = points[0].cum_dist_m;
+                refuel_points.push(points[0].clone());
+            }
+        }
+        refuel_points
+    }
+    /// OSM way id of each segment in route order
+    pub fn get_way_ids(&self) -> Vec<u64> {
+        self.route_segments
+            .iter()
+            .map(|segment| segment.get_way_id())
+            .collect()
+    }
+    /// The same lines travelled from the last point back to the start. Fails when a one-way
+    /// line would be travelled against its direction, turn restrictions are not checked.
+    pub fn reverse(&self) -> Result<Route, RouteError> {
+        let points = self.get_points();
+        let mut route_segments = Vec::with_capacity(self.route_segments.len());
+        for (idx, segment) in self.route_segments.iter().enumerate().rev() {
+            let line = segment.get_line();
+            let end_point = points[idx].clone();
+            if line.borrow().is_one_way() && line.borrow().points.0 == end_point {
+                return Err(RouteError::OneWayReversed {
+                    line_id: line.borrow().line_id(),
+                });
+            }
+            route_segments.push(Segment::new(line.clone(), end_point));
+        }
+        Ok(Route { route_segments })
+    }
+    /// Maneuvers at the junctions where the direction or the road changes, starting with
+    /// `Depart` and ending with `Arrive`. Empty for a route without segments.
+    pub fn to_instructions(&self) -> Vec<Instruction> {
+        let first_segment = match self.route_segments.first() {
+            None => return Vec::new(),
+            Some(segment) => segment,
+        };
+        let mut instructions = vec![Instruction {
+            maneuver: Maneuver::Depart,
+            road: get_road(first_segment),
+            destination: get_destination(first_segment),
+            point_idx: 0,
+            distance_to_next_m: 0.,
+        }];
+        for (idx, segment) in self.route_segments.iter().enumerate() {
+            if let Some(last) = instructions.last_mut() {
+                last.distance_to_next_m += segment.get_line().borrow().get_len_m() as f64;
+            }
+            let next_segment = match self.route_segments.get(idx + 1) {
+                None => continue,
+                Some(next_segment) => next_segment,
+            };
+            if !segment.get_end_point().borrow().is_junction() {
+                continue;
+            }
+            let maneuver = Maneuver::from_turn_deg(get_turn_deg(
+                get_bearing(segment),
+                get_bearing(next_segment),
+            ));
+            let road = get_road(next_segment);
+            if maneuver == Maneuver::Continue
+                && instructions.last().map_or(false, |last| last.road == road)
+            {
+                continue;
+            }
+            instructions.push(Instruction {
+                maneuver,
+                road,
+                destination: get_destination(next_segment),
+                point_idx: idx + 1,
+                distance_to_next_m: 0.,
+            });
+        }
+        instructions.push(Instruction {
+            maneuver: Maneuver::Arrive,
+            road: None,
+            destination: None,
+            point_idx: self.route_segments.len(),
+            distance_to_next_m: 0.,
+        });
+        instructions
+    }
+    /// (lat, lon) of `get_points` simplified with Ramer-Douglas-Peucker, points closer than
+    /// `tolerance_m` to the simplified track are dropped. The first and last points are kept.
+    pub fn simplify(&self, tolerance_m: f64) -> Vec<(f32, f32)> {
+        let coords = self
+            .get_points()
+            .iter()
+            .map(|point| (point.borrow().lat, point.borrow().lon))
+            .collect::<Vec<_>>();
+        let (lat_0, lon_0) = match coords.first() {
+            None => return Vec::new(),
+            Some(coords) => (coords.0 as f64, coords.1 as f64),
+        };
+        // equirectangular projection around the start, good enough at route scale
+        let lon_scale = lat_0.to_radians().cos();
+        let line_string = LineString::from(
+            coords
+                .iter()
+                .map(|(lat, lon)| {
+                    (
+                        (*lon as f64 - lon_0).to_radians() * lon_scale * MEAN_EARTH_RADIUS,
+                        (*lat as f64 - lat_0).to_radians() * MEAN_EARTH_RADIUS,
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        line_string
+            .simplify_idx(&tolerance_m)
+            .into_iter()
+            .map(|idx| coords[idx])
+            .collect()
+    }
+    pub fn to_polyline(&self, precision: u32) -> String {
+        let coords = self
+            .get_points()
+            .iter()
+            .map(|point| (point.borrow().lat as f64, point.borrow().lon as f64))
+            .collect::<Vec<_>>();
+        polyline::encode(&coords, precision)
+    }
 
-    pub fn calc_stats(&self) -> RouteStats {
+    pub fn calc_stats(&self, itinerary: &Itinerary, rules: &RouterRules) -> RouteStats {
         fn update_map(
             tag_val: &Option<&smartstring::alias::String>,
             line_len: f64,
@@ -119,7 +379,11 @@ impl Route {
                     key.clone(),
                     RouteStatElement {
                         len_m: line_len.clone(),
-                        percentage: line_len / len_m * 100.,
+                        percentage: if len_m > 0. {
+                            line_len / len_m * 100.
+                        } else {
+                            0.
+                        },
                     },
                 );
             }
@@ -131,13 +395,24 @@ impl Route {
         let mut highway: HashMap<String, f64> = HashMap::new();
         let mut surface: HashMap<String, f64> = HashMap::new();
         let mut smoothness: HashMap<String, f64> = HashMap::new();
+        let mut bridge: HashMap<String, f64> = HashMap::new();
+        let mut tunnel: HashMap<String, f64> = HashMap::new();
+        let mut ford: HashMap<String, f64> = HashMap::new();
+        let mut lit: HashMap<String, f64> = HashMap::new();
         let mut lat_sum: f64 = 0.;
         let mut lon_sum: f64 = 0.;
         let mut prev_bearing: Option<f32> = None;
         let mut tot_bearing_diff: f64 = 0.;
+        let mut roundabout_count = 0;
+        let mut prev_roundabout = false;
         for segment in &self.route_segments {
-            let line_len: f64 = segment.get_line().borrow().get_len_m().into();
+            let line_len = get_line_len_m(segment, &rules.distance_metric);
             len_m += line_len;
+            let roundabout = segment.get_line().borrow().is_roundabout();
+            if roundabout && !prev_roundabout {
+                roundabout_count += 1;
+            }
+            prev_roundabout = roundabout;
             if segment.get_end_point().borrow().is_junction() {
                 junction_count += 1;
             }
@@ -148,22 +423,25 @@ impl Route {
             update_map(&surface_val, line_len, &mut surface);
             let smoothness_val = line_tags.smoothness();
             update_map(&smoothness_val, line_len, &mut smoothness);
+            if let Some(bridge_val) = line_tags.bridge() {
+                update_map(&Some(bridge_val), line_len, &mut bridge);
+            }
+            if let Some(tunnel_val) = line_tags.tunnel() {
+                update_map(&Some(tunnel_val), line_len, &mut tunnel);
+            }
+            if let Some(ford_val) = line_tags.ford() {
+                update_map(&Some(ford_val), line_len, &mut ford);
+            }
+            if let Some(lit_val) = line_tags.lit() {
+                update_map(&Some(lit_val), line_len, &mut lit);
+            }
 
             lat_sum += segment.get_end_point().borrow().lat as f64;
             lon_sum += segment.get_end_point().borrow().lon as f64;
 
-            let line = segment.get_line().borrow();
-            let point_1 = line.points.0.borrow();
-            let point_2 = line.points.1.borrow();
-            let geo_point_1 = GeoPoint::new(point_1.lon, point_1.lat);
-            let geo_point_2 = GeoPoint::new(point_2.lon, point_2.lat);
-            let curr_bearing = if line.points.1 == *segment.get_end_point() {
-                geo_point_1.haversine_bearing(geo_point_2)
-            } else {
-                geo_point_2.haversine_bearing(geo_point_1)
-            };
+            let curr_bearing = get_bearing(segment);
             if let Some(prev_bearing) = prev_bearing {
-                tot_bearing_diff += (prev_bearing - curr_bearing).abs() as f64;
+                tot_bearing_diff += bearing_difference(prev_bearing, curr_bearing) as f64;
             }
             prev_bearing = if segment.get_end_point().borrow().is_junction() {
                 None
@@ -178,15 +456,41 @@ impl Route {
             highway: calc_stat_map(len_m, &highway),
             smoothness: calc_stat_map(len_m, &smoothness),
             surface: calc_stat_map(len_m, &surface),
-            mean_point: Point {
-                lat: lat_sum / self.get_segment_count() as f64,
-                lon: lon_sum / self.get_segment_count() as f64,
+            bridge: calc_stat_map(len_m, &bridge),
+            tunnel: calc_stat_map(len_m, &tunnel),
+            ford: calc_stat_map(len_m, &ford),
+            lit: calc_stat_map(len_m, &lit),
+            mean_point: match self.get_segment_count() {
+                0 => Point {
+                    lat: itinerary.get_from().borrow().lat as f64,
+                    lon: itinerary.get_from().borrow().lon as f64,
+                },
+                count => Point {
+                    lat: lat_sum / count as f64,
+                    lon: lon_sum / count as f64,
+                },
+            },
+            direction_change_ratio: if len_m > 0. {
+                tot_bearing_diff / len_m * 1000.
+            } else {
+                0.
             },
-            direction_change_ratio: tot_bearing_diff / len_m * 1000.,
+            retraced_len_m: self.get_retraced_len_m(&rules.distance_metric),
+            roundabout_count,
+            waypoints_reached: itinerary.get_visited_waypoints().len(),
+            waypoints_total: itinerary.get_waypoints().len(),
         }
     }
 }
 
+/// Length of the line of the segment by the distance metric of the rules
+fn get_line_len_m(segment: &Segment, distance_metric: &RulesDistanceMetric) -> f64 {
+    match distance_metric {
+        RulesDistanceMetric::Haversine => segment.get_line().borrow().get_len_m().into(),
+        RulesDistanceMetric::Geodesic => segment.get_line().borrow().get_geodesic_len_m(),
+    }
+}
+
 impl From<Vec<Segment>> for Route {
     fn from(route_segments: Vec<Segment>) -> Self {
         Route { route_segments }
@@ -208,3 +512,413 @@ impl IntoIterator for Route {
         self.route_segments.into_iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        map_data::{
+            graph::MapDataGraph,
+            osm::{OsmNode, OsmWay},
+        },
+        router::{
+            itinerary::Itinerary,
+            route::{instructions::Maneuver, segment::Segment, ProfilePoint, Route, RouteError},
+            rules::{RouterRules, RulesDistanceMetric},
+        },
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1, TestGraphBuilder},
+    };
+    use rusty_fork::rusty_fork_test;
+
+    fn route_from_ids(ids: Vec<u64>) -> Route {
+        let mut route = Route::new();
+        let mut point = MapDataGraph::get()
+            .test_get_point_ref_by_id(&ids[0])
+            .unwrap();
+        for next_id in &ids[1..] {
+            let (line, next_point) = MapDataGraph::get()
+                .get_adjacent(point.clone())
+                .into_iter()
+                .find(|(_, p)| p.borrow().id == *next_id)
+                .unwrap();
+            route.add_segment(Segment::new(line, next_point.clone()));
+            point = next_point;
+        }
+        route
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_reverse() {
+            // 1 - 2 both ways, 2 -> 3 one way
+            let nodes = vec![1, 2, 3]
+                .into_iter()
+                .map(|id| OsmNode {
+                    id,
+                    lat: id as f64,
+                    lon: id as f64,
+                })
+                .collect();
+            let ways = vec![
+                OsmWay {
+                    id: 1,
+                    point_ids: vec![1, 2],
+                    tags: Some(HashMap::from([("highway".to_string(), "primary".to_string())])),
+                },
+                OsmWay {
+                    id: 2,
+                    point_ids: vec![2, 3],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "primary".to_string()),
+                        ("oneway".to_string(), "yes".to_string()),
+                    ])),
+                },
+            ];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+
+            let route = route_from_ids(vec![1, 2]);
+            let reversed = route.reverse().unwrap();
+            assert_eq!(
+                reversed.get_points().iter().map(|p| p.borrow().id).collect::<Vec<_>>(),
+                vec![2, 1]
+            );
+            assert_eq!(reversed.reverse().unwrap(), route);
+
+            let route = route_from_ids(vec![1, 2, 3]);
+            assert!(matches!(
+                route.reverse(),
+                Err(RouteError::OneWayReversed { .. })
+            ));
+            assert_eq!(Route::new().reverse().unwrap(), Route::new());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_to_instructions() {
+            //     4
+            //     |
+            //     2 - 3
+            //     |
+            //     1
+            let nodes = vec![(1, 0., 0.), (2, 0.01, 0.), (3, 0.01, 0.01), (4, 0.02, 0.)]
+                .into_iter()
+                .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+                .collect();
+            let ways = vec![
+                OsmWay {
+                    id: 1,
+                    point_ids: vec![1, 2, 4],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "primary".to_string()),
+                        ("name".to_string(), "Main".to_string()),
+                    ])),
+                },
+                OsmWay {
+                    id: 2,
+                    point_ids: vec![2, 3],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "secondary".to_string()),
+                        ("ref".to_string(), "B2".to_string()),
+                    ])),
+                },
+            ];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+
+            let route = route_from_ids(vec![1, 2, 3]);
+            let instructions = route.to_instructions();
+            assert_eq!(
+                instructions
+                    .iter()
+                    .map(|instruction| (instruction.maneuver, instruction.point_idx))
+                    .collect::<Vec<_>>(),
+                vec![(Maneuver::Depart, 0), (Maneuver::Right, 1), (Maneuver::Arrive, 2)]
+            );
+            assert_eq!(instructions[0].text(), "Depart on Main");
+            assert_eq!(instructions[1].text(), "Turn right onto B2");
+            assert_eq!(
+                instructions[0].distance_to_next_m,
+                route.get_segment_by_index(0).unwrap().get_line().borrow().get_len_m() as f64
+            );
+
+            let route = route_from_ids(vec![1, 2, 4]);
+            let instructions = route.to_instructions();
+            assert_eq!(instructions.len(), 2);
+            assert_eq!(instructions[1].maneuver, Maneuver::Arrive);
+            assert!(instructions[0].distance_to_next_m > instructions[1].distance_to_next_m);
+
+            assert!(Route::new().to_instructions().is_empty());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_instructions_follow_signage() {
+            //     4
+            //     |
+            //     2 - 3
+            //     |
+            //     1
+            let nodes = vec![(1, 0., 0.), (2, 0.01, 0.), (3, 0.01, 0.01), (4, 0.02, 0.)]
+                .into_iter()
+                .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+                .collect();
+            let ways = vec![
+                OsmWay {
+                    id: 1,
+                    point_ids: vec![1, 2, 4],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "primary".to_string()),
+                        ("name".to_string(), "Main".to_string()),
+                        ("destination:ref".to_string(), "A1".to_string()),
+                        ("destination:backward".to_string(), "Valmiera".to_string()),
+                    ])),
+                },
+                OsmWay {
+                    id: 2,
+                    point_ids: vec![2, 3],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "secondary".to_string()),
+                        ("ref".to_string(), "B2".to_string()),
+                        ("destination".to_string(), "Riga;Jurmala".to_string()),
+                        ("destination:ref".to_string(), "A2".to_string()),
+                    ])),
+                },
+            ];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+
+            let instructions = route_from_ids(vec![1, 2, 3]).to_instructions();
+            assert_eq!(instructions[0].text(), "Depart towards A1");
+            assert_eq!(instructions[1].road, Some("B2".to_string()));
+            assert_eq!(instructions[1].text(), "Turn right towards Riga, Jurmala / A2");
+            assert_eq!(instructions[2].text(), "Arrive at destination");
+
+            // against the direction of the ways only the backward signs apply
+            let instructions = route_from_ids(vec![3, 2, 1]).to_instructions();
+            assert_eq!(instructions[0].text(), "Depart on B2");
+            assert_eq!(instructions[1].text(), "Turn left towards Valmiera");
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_loop_count() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+
+            let route = route_from_ids(vec![1, 2, 3, 6, 7]);
+            assert!(!route.has_looped());
+            assert_eq!(route.get_loop_count(), 0);
+
+            let route = route_from_ids(vec![2, 3, 6, 8, 4, 3]);
+            assert!(route.has_looped());
+            assert_eq!(route.get_loop_count(), 1);
+
+            // second lap on the same loop is still one loop
+            let route = route_from_ids(vec![2, 3, 6, 8, 4, 3, 6, 8, 4, 3, 5]);
+            assert_eq!(route.get_loop_count(), 1);
+
+            let route = route_from_ids(vec![2, 3, 6, 8, 4, 3, 2, 3]);
+            assert_eq!(route.get_loop_count(), 2);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_retraced_len() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let retraced_len_m = |ids, rules: &RouterRules| {
+                let route = route_from_ids(ids);
+                let itinerary = Itinerary::new(
+                    route.get_start_point().unwrap(),
+                    route.get_segment_last().unwrap().get_end_point().clone(),
+                    Vec::new(),
+                    10.,
+                );
+                route.calc_stats(&itinerary, rules).retraced_len_m
+            };
+            let rules = RouterRules::default();
+
+            assert_eq!(retraced_len_m(vec![1, 2, 3, 6, 7], &rules), 0.);
+            // a loop back to 3 does not use a line twice
+            assert_eq!(retraced_len_m(vec![2, 3, 6, 8, 4, 3], &rules), 0.);
+
+            let line = route_from_ids(vec![2, 3]);
+            let line_len = line.get_len_m() as f64;
+            let retraced_ids = vec![2, 3, 6, 8, 4, 3, 2, 3];
+            assert!((retraced_len_m(retraced_ids.clone(), &rules) - 2. * line_len).abs() < 0.01);
+
+            let rules = RouterRules {
+                distance_metric: RulesDistanceMetric::Geodesic,
+                ..RouterRules::default()
+            };
+            let geodesic_len = line.get_segment_last().unwrap().get_line().borrow().get_geodesic_len_m();
+            assert!((retraced_len_m(retraced_ids, &rules) - 2. * geodesic_len).abs() < 0.01);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_profile() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = route_from_ids(vec![1, 2, 3, 6]);
+            let profile = route.profile();
+
+            assert_eq!(profile.len(), route.get_points().len());
+            assert_eq!(profile[0].cum_dist_m, 0.);
+            assert_eq!((profile[0].lat, profile[0].lon), (1., 1.));
+            assert!(profile
+                .windows(2)
+                .all(|points| points[1].cum_dist_m > points[0].cum_dist_m));
+            assert!((profile[3].cum_dist_m - route.get_len_m() as f64).abs() < 1.);
+            assert!(profile.iter().all(|point| point.elevation_m.is_none()));
+            assert!(Route::new().profile().is_empty());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_refuel_points() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = route_from_ids(vec![1, 2, 3, 6]);
+            let profile = route.profile();
+            let ids = |points: Vec<ProfilePoint>| {
+                points
+                    .iter()
+                    .map(|point| {
+                        profile
+                            .iter()
+                            .position(|profile_point| profile_point == point)
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(ids(route.get_refuel_points(200_000.)), vec![1, 2]);
+            assert_eq!(ids(route.get_refuel_points(350_000.)), vec![2]);
+            assert!(route.get_refuel_points(route.get_len_m() as f64 + 1.).is_empty());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_simplify() {
+            // 1 - 2 - 3 east with 2 about 1m off the line, 3 - 4 about 111m north
+            let nodes = vec![(1, 0., 0.), (2, 0.00001, 0.001), (3, 0., 0.002), (4, 0.001, 0.002)]
+                .into_iter()
+                .map(|(id, lat, lon)| OsmNode { id, lat, lon })
+                .collect();
+            let ways = vec![OsmWay {
+                id: 1,
+                point_ids: vec![1, 2, 3, 4],
+                tags: Some(HashMap::from([("highway".to_string(), "primary".to_string())])),
+            }];
+            set_graph_static(graph_from_test_dataset((nodes, ways, Vec::new())));
+            let route = route_from_ids(vec![1, 2, 3, 4]);
+            let points = route
+                .get_points()
+                .iter()
+                .map(|point| (point.borrow().lat, point.borrow().lon))
+                .collect::<Vec<_>>();
+
+            assert_eq!(route.simplify(0.1), points);
+            assert_eq!(route.simplify(10.), vec![points[0], points[2], points[3]]);
+            assert_eq!(route.simplify(1000.), vec![points[0], points[3]]);
+            assert!(Route::new().simplify(10.).is_empty());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_to_polyline() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = route_from_ids(vec![1, 2, 3]);
+
+            assert_eq!(route.get_start_point().unwrap().borrow().id, 1);
+            assert_eq!(route.to_polyline(5), "_ibE_ibE_ibE_ibE_ibE_ibE");
+            assert_eq!(Route::new().to_polyline(5), "");
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_stats_distance_metric() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = route_from_ids(vec![1, 2, 3]);
+            let itinerary = Itinerary::new(
+                route.get_start_point().unwrap(),
+                route.get_segment_last().unwrap().get_end_point().clone(),
+                Vec::new(),
+                10.,
+            );
+
+            let haversine_stats = route.calc_stats(&itinerary, &RouterRules::default());
+            let geodesic_stats = route.calc_stats(
+                &itinerary,
+                &RouterRules {
+                    distance_metric: RulesDistanceMetric::Geodesic,
+                    ..RouterRules::default()
+                },
+            );
+
+            assert!(geodesic_stats.len_m > 0.);
+            assert!((haversine_stats.len_m - geodesic_stats.len_m).abs() / geodesic_stats.len_m < 0.01);
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn route_stats_start_is_finish() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let itinerary = Itinerary::new(start.clone(), start, Vec::new(), 10.);
+
+            let stats = Route::new().calc_stats(&itinerary, &RouterRules::default());
+            assert_eq!(stats.len_m, 0.);
+            assert_eq!(stats.junction_count, 0);
+            assert!(stats.highway.is_empty());
+            assert_eq!((stats.mean_point.lat, stats.mean_point.lon), (1., 1.));
+            assert_eq!(stats.direction_change_ratio, 0.);
+
+            let stats = route_from_ids(vec![1, 2]).calc_stats(&itinerary, &RouterRules::default());
+            assert!(stats.len_m > 0.);
+            assert_eq!((stats.mean_point.lat, stats.mean_point.lon), (2., 2.));
+            assert_eq!(stats.direction_change_ratio, 0.);
+            assert!(stats
+                .highway
+                .values()
+                .all(|stat| stat.percentage.is_finite()));
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn direction_change_across_north() {
+            // 1 - 2 heads about 10 degrees west of north, 2 - 3 about 10 degrees east of it
+            let map_data = TestGraphBuilder::new()
+                .nodes_at(&[(1, 0., 0.), (2, 0.01, -0.00176), (3, 0.02, 0.)])
+                .way(1, &[1, 2, 3], &[("highway", "primary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let itinerary = Itinerary::new(point(1), point(3), Vec::new(), 10.);
+
+            // northbound crosses north, southbound crosses south, both turn 20 degrees
+            for ids in [vec![1, 2, 3], vec![3, 2, 1]] {
+                let stats = route_from_ids(ids).calc_stats(&itinerary, &RouterRules::default());
+                let bearing_diff = stats.direction_change_ratio * stats.len_m / 1000.;
+                assert!((bearing_diff - 20.).abs() < 0.5, "{bearing_diff}");
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/// Encodes coordinates with Google's encoded polyline algorithm.
+/// `coords` are (lat, lon) pairs, `precision` is the number of decimal places kept,
+/// 5 being the common default.
+pub fn encode(coords: &[(f64, f64)], precision: u32) -> String {
+    let factor = 10_f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let mut prev_lat: i64 = 0;
+    let mut prev_lon: i64 = 0;
+
+    for (lat, lon) in coords {
+        let lat = (lat * factor).round() as i64;
+        let lon = (lon * factor).round() as i64;
+        encode_value(lat - prev_lat, &mut encoded);
+        encode_value(lon - prev_lon, &mut encoded);
+        prev_lat = lat;
+        prev_lon = lon;
+    }
+
+    encoded
+}
+
+fn encode_value(value: i64, encoded: &mut String) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        encoded.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    encoded.push((value as u8 + 63) as char);
+}
+
+#[cfg(test)]
+mod test {
+    use super::encode;
+
+    #[test]
+    fn encode_reference_polyline() {
+        let coords = vec![(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)];
+        assert_eq!(encode(&coords, 5), "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+    }
+
+    #[test]
+    fn encode_empty() {
+        assert_eq!(encode(&Vec::new(), 5), "");
+    }
+}
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::map_data::graph::{MapDataLineRef, MapDataPointRef};
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub struct Segment {
     line: MapDataLineRef,
     end_point: MapDataPointRef,
@@ -20,6 +20,9 @@ impl Segment {
     pub fn get_line(&self) -> &MapDataLineRef {
         &self.line
     }
+    pub fn get_way_id(&self) -> u64 {
+        self.line.borrow().way_id
+    }
 }
 
 impl Debug for Segment {
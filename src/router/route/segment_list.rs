@@ -1,10 +1,12 @@
 use std::{fmt::Debug, rc::Rc};
 
+use serde::{Deserialize, Serialize};
+
 use crate::map_data::graph::MapDataPointRef;
 
 use super::segment::Segment;
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub struct SegmentList {
     segment_list: Vec<Segment>,
 }
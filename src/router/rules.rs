@@ -1,14 +1,44 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+
+use crate::map_data::graph::ELEMENT_TAG_KEYS;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, BufRead, IsTerminal};
-use std::{path::PathBuf, str::Utf8Error};
+use std::{
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
 
 #[derive(Debug)]
 pub enum RulesError {
     FileRead { error: io::Error },
     FileParse { error: Utf8Error },
     JsonParse { error: serde_json::Error },
+    YamlParse { error: serde_yaml::Error },
+    TomlParse { error: toml::de::Error },
     StdinRead { error: io::Error },
+    InvalidValue { name: String, cause: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RulesFileFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl RulesFileFormat {
+    fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,10 +63,109 @@ impl Default for BasicRulePreferSameRoad {
     }
 }
 
+/// Adds `priority` to fork choices that stay on the highway class of the last segment of the
+/// route and half of it to the classes next to it in `class_order`, listed from the highest
+/// class down. `_link` ways count as their class, classes not in the list get nothing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRulePreferConsistentClass {
+    pub enabled: bool,
+    pub priority: u8,
+    pub class_order: Vec<String>,
+}
+
+impl Default for BasicRulePreferConsistentClass {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 60,
+            class_order: [
+                "motorway",
+                "trunk",
+                "primary",
+                "secondary",
+                "tertiary",
+                "unclassified",
+                "residential",
+                "living_street",
+                "track",
+            ]
+            .iter()
+            .map(|class| class.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// Adds `priority` to fork choices on ways that have all of `tags` and a share of it for
+/// the ones that have some, so that sparsely tagged ways that are more likely to be
+/// mis-tagged are only used when there is no better described alternative
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRulePreferDescribedWays {
+    pub enabled: bool,
+    pub priority: u8,
+    pub tags: Vec<String>,
+}
+
+impl Default for BasicRulePreferDescribedWays {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 30,
+            tags: vec!["surface".to_string(), "name".to_string()],
+        }
+    }
+}
+
+/// What to do with roundabouts, `neutral` leaves them to the other weights
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BasicRuleRoundaboutAction {
+    #[default]
+    Neutral,
+    Avoid,
+}
+
+/// With `avoid` the fork choices that do not enter a roundabout get `priority`, staying in
+/// or leaving a roundabout is not an entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleRoundaboutPreference {
+    pub action: BasicRuleRoundaboutAction,
+    pub priority: u8,
+}
+
+impl Default for BasicRuleRoundaboutPreference {
+    fn default() -> Self {
+        Self {
+            action: BasicRuleRoundaboutAction::Neutral,
+            priority: 40,
+        }
+    }
+}
+
+/// Adds `priority` to fork choices on roads with a `ref`, stacks with `prefer_same_road`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRulePreferNumberedRoads {
+    pub enabled: bool,
+    pub priority: u8,
+}
+
+impl Default for BasicRulePreferNumberedRoads {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 20,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicRuleProgressDirection {
     pub enabled: bool,
     pub check_steps_back: usize,
+    /// Metres the route may move away from the next waypoint compared to `check_steps_back`
+    /// steps ago before the fork is discarded
+    #[serde(default)]
+    pub tolerance_m: f32,
 }
 
 impl Default for BasicRuleProgressDirection {
@@ -44,10 +173,159 @@ impl Default for BasicRuleProgressDirection {
         Self {
             enabled: true,
             check_steps_back: 1000,
+            tolerance_m: 0.,
+        }
+    }
+}
+
+/// Run the navigator in several passes, each with a step limit. The first pass relaxes the
+/// progression direction by `initial_tolerance_m` to find a feasible route quickly, every
+/// following pass tightens it until the last pass uses the configured tolerance. The route
+/// from the tightest pass that finished is used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleIterativeDeepening {
+    pub enabled: bool,
+    pub passes: usize,
+    pub initial_tolerance_m: f32,
+    pub pass_step_limit: usize,
+}
+
+impl Default for BasicRuleIterativeDeepening {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            passes: 3,
+            initial_tolerance_m: 2000.,
+            pass_step_limit: 100000,
+        }
+    }
+}
+
+/// Ways tagged `access=destination` or `motor_vehicle=destination` are kept in the graph so
+/// that endpoints on them can be reached, every other fork choice gets `priority` so these
+/// ways are only used when there is no alternative
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleDestinationAccess {
+    pub enabled: bool,
+    pub priority: u8,
+}
+
+impl Default for BasicRuleDestinationAccess {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            priority: 255,
+        }
+    }
+}
+
+/// Drop generated routes longer than `ratio` times the straight line distance from start to
+/// finish, round trips where start and finish are within 1km of each other keep all routes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleMaxDetour {
+    pub enabled: bool,
+    pub ratio: f32,
+}
+
+impl Default for BasicRuleMaxDetour {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ratio: 2.5,
+        }
+    }
+}
+
+/// Do not use fork choices that turn more than `max_turn_deg` away from the current heading
+/// within `lookahead_m` metres, catches switchbacks that are drawn over several short lines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleAvoidHairpins {
+    pub enabled: bool,
+    pub max_turn_deg: f32,
+    pub lookahead_m: f32,
+}
+
+impl Default for BasicRuleAvoidHairpins {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_turn_deg: 135.,
+            lookahead_m: 100.,
         }
     }
 }
 
+/// Do not use fork choices where the angle between the last segment of the route and the
+/// fork segment is under `under_deg`, 180 is going straight through the fork. The other fork
+/// choices get a share of `priority` that grows from 0 at `under_deg` to all of it at 180.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleNoSharpTurns {
+    pub enabled: bool,
+    pub under_deg: f32,
+    pub priority: u8,
+}
+
+impl Default for BasicRuleNoSharpTurns {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            under_deg: 150.,
+            priority: 60,
+        }
+    }
+}
+
+/// Do not use fork choices that end more than `width_m` from `reference_track`, a list of
+/// `[lat, lon]` pairs, or from the straight line from start to finish when it is not set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleCorridor {
+    pub enabled: bool,
+    pub width_m: f32,
+    pub reference_track: Option<Vec<(f32, f32)>>,
+}
+
+impl Default for BasicRuleCorridor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width_m: 10000.,
+            reference_track: None,
+        }
+    }
+}
+
+/// After generation navigate again between junctions `span_junctions` apart and use the new
+/// section when it is shorter, up to `max_attempts` navigations per route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicRuleLocalSearch {
+    pub enabled: bool,
+    pub max_attempts: usize,
+    pub span_junctions: usize,
+}
+
+impl Default for BasicRuleLocalSearch {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_attempts: 20,
+            span_junctions: 4,
+        }
+    }
+}
+
+/// How often a route may come back to points it already passed, checked by the `no_loops`
+/// weight. Consecutive revisited points, like riding a loop twice, count as one loop.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum BasicRuleLoops {
+    #[default]
+    Forbid,
+    AllowUpTo {
+        count: usize,
+    },
+    Unlimited,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicRuleProgressSpeed {
     pub enabled: bool,
@@ -70,36 +348,345 @@ pub struct BasicRules {
     #[serde(default)]
     pub prefer_same_road: BasicRulePreferSameRoad,
 
+    #[serde(default)]
+    pub prefer_numbered_roads: BasicRulePreferNumberedRoads,
+
+    #[serde(default)]
+    pub prefer_consistent_class: BasicRulePreferConsistentClass,
+
+    #[serde(default)]
+    pub prefer_described_ways: BasicRulePreferDescribedWays,
+
+    #[serde(default)]
+    pub roundabout_preference: BasicRuleRoundaboutPreference,
+
     #[serde(default)]
     pub progression_direction: BasicRuleProgressDirection,
 
     #[serde(default)]
     pub progression_speed: BasicRuleProgressSpeed,
+
+    #[serde(default)]
+    pub iterative_deepening: BasicRuleIterativeDeepening,
+
+    #[serde(default)]
+    pub destination_access: BasicRuleDestinationAccess,
+
+    #[serde(default)]
+    pub max_detour: BasicRuleMaxDetour,
+
+    #[serde(default)]
+    pub loops: BasicRuleLoops,
+
+    #[serde(default)]
+    pub avoid_hairpins: BasicRuleAvoidHairpins,
+
+    #[serde(default)]
+    pub no_sharp_turns: BasicRuleNoSharpTurns,
+
+    #[serde(default)]
+    pub corridor: BasicRuleCorridor,
+
+    #[serde(default)]
+    pub local_search: BasicRuleLocalSearch,
+}
+
+/// Distance calculation used for the route stats. Haversine is fast but off by
+/// up to ~0.5%, geodesic uses the WGS84 ellipsoid and is accurate but slower.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesDistanceMetric {
+    #[default]
+    Haversine,
+    Geodesic,
+}
+
+/// Weight functions that can be listed in the `weights` section of the rules.
+/// Parameters for the weights are taken from the `basic` section.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesWeight {
+    ProgressSpeed,
+    CheckDistanceToNext,
+    PreferSameRoad,
+    NoLoops,
+    Heading,
+    RulesHighway,
+    RulesSurface,
+    RulesSmoothness,
+    RulesBridge,
+    RulesTunnel,
+    RulesFord,
+    DestinationAccess,
+    PreferNumberedRoads,
+    AvoidHairpins,
+    VehicleDimensions,
+    NoSharpTurns,
+    AvoidWayIds,
+    RulesLit,
+    Corridor,
+    PreferConsistentClass,
+    PreferDescribedWays,
+    RoundaboutPreference,
+}
+
+pub const DEFAULT_WEIGHTS: [RulesWeight; 22] = [
+    RulesWeight::ProgressSpeed,
+    RulesWeight::CheckDistanceToNext,
+    RulesWeight::PreferSameRoad,
+    RulesWeight::NoLoops,
+    RulesWeight::Heading,
+    RulesWeight::RulesHighway,
+    RulesWeight::RulesSurface,
+    RulesWeight::RulesSmoothness,
+    RulesWeight::RulesBridge,
+    RulesWeight::RulesTunnel,
+    RulesWeight::RulesFord,
+    RulesWeight::DestinationAccess,
+    RulesWeight::PreferNumberedRoads,
+    RulesWeight::AvoidHairpins,
+    RulesWeight::VehicleDimensions,
+    RulesWeight::NoSharpTurns,
+    RulesWeight::AvoidWayIds,
+    RulesWeight::RulesLit,
+    RulesWeight::Corridor,
+    RulesWeight::PreferConsistentClass,
+    RulesWeight::PreferDescribedWays,
+    RulesWeight::RoundaboutPreference,
+];
+
+/// Order of the routes in the output, without it they are in the order they were generated
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesRouteSort {
+    /// Shortest first
+    Length,
+    /// Largest `direction_change_ratio` first
+    Curviness,
 }
 
+/// Named starting points for the rules, values given in the rules file override the preset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesPreset {
+    /// Prefer motorways and other major roads
+    Fastest,
+    /// Head straight for the finish with no road preferences
+    Shortest,
+    /// Prefer minor roads and avoid motorways and trunk roads
+    Curvy,
+    /// Prefer roads outside of residential areas
+    AvoidCities,
+}
+
+fn tag_actions(
+    actions: &[(&str, RulesTagValueAction)],
+) -> Option<HashMap<String, RulesTagValueAction>> {
+    Some(
+        actions
+            .iter()
+            .map(|(tag, action)| (tag.to_string(), action.clone()))
+            .collect(),
+    )
+}
+
+/// Apply the values of `overrides` on top of `value`, objects are merged key by key and
+/// everything else is replaced
+fn merge_json(value: &mut serde_json::Value, overrides: serde_json::Value) -> () {
+    match (value, overrides) {
+        (serde_json::Value::Object(value), serde_json::Value::Object(overrides)) => {
+            for (key, override_value) in overrides {
+                match value.get_mut(&key) {
+                    Some(existing) => merge_json(existing, override_value),
+                    None => {
+                        value.insert(key, override_value);
+                    }
+                }
+            }
+        }
+        (value, overrides) => *value = overrides,
+    }
+}
+
+/// Size of the vehicle, ways with a `maxheight`, `maxwidth`, `maxlength` or `maxweight` below
+/// the matching dimension are not used. Dimensions that are not set are not checked.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct VehicleDimensions {
+    pub height_m: Option<f32>,
+    pub width_m: Option<f32>,
+    pub length_m: Option<f32>,
+    pub weight_t: Option<f32>,
+}
+
+impl VehicleDimensions {
+    pub fn is_set(&self) -> bool {
+        self.height_m.is_some()
+            || self.width_m.is_some()
+            || self.length_m.is_some()
+            || self.weight_t.is_some()
+    }
+}
+
+/// Coordinates further from a road are most likely a mistake, e.g. swapped lat and lon
+pub const DEFAULT_MAX_SNAP_DISTANCE_M: f32 = 2000.;
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct RouterRules {
     #[serde(default)]
     pub basic: BasicRules,
+    /// Weights to run and their order, `None` runs `DEFAULT_WEIGHTS`
+    pub weights: Option<Vec<RulesWeight>>,
+    #[serde(default)]
+    pub distance_metric: RulesDistanceMetric,
     pub highway: Option<HashMap<String, RulesTagValueAction>>,
     pub surface: Option<HashMap<String, RulesTagValueAction>>,
     pub smoothness: Option<HashMap<String, RulesTagValueAction>>,
+    pub bridge: Option<HashMap<String, RulesTagValueAction>>,
+    pub tunnel: Option<HashMap<String, RulesTagValueAction>>,
+    pub ford: Option<HashMap<String, RulesTagValueAction>>,
+    pub lit: Option<HashMap<String, RulesTagValueAction>>,
+    #[serde(default)]
+    pub vehicle_dimensions: VehicleDimensions,
+    /// Range of the vehicle, when set the output marks where refueling is suggested
+    pub refuel_range_km: Option<f32>,
+    /// When set the output also has each route simplified to this tolerance
+    pub simplify_tolerance_m: Option<f32>,
+    /// Start and finish coordinates further than this from the closest road are an error,
+    /// `None` uses `DEFAULT_MAX_SNAP_DISTANCE_M`
+    pub max_snap_distance_m: Option<f32>,
+    /// Navigation of an itinerary stops after this many seconds, over all iterative deepening
+    /// passes, and keeps the route it has so far
+    pub itinerary_timeout_s: Option<f32>,
+    /// OSM ways that are never used, e.g. ones that are mis-tagged, a fork is not taken when an
+    /// avoided way is anywhere before the next fork
+    #[serde(default)]
+    pub avoid_way_ids: HashSet<u64>,
+    pub sort_routes: Option<RulesRouteSort>,
 }
 
 impl RouterRules {
+    pub fn get_max_snap_distance_m(&self) -> f32 {
+        self.max_snap_distance_m
+            .unwrap_or(DEFAULT_MAX_SNAP_DISTANCE_M)
+    }
+    pub fn from_preset(preset: RulesPreset) -> Self {
+        match preset {
+            RulesPreset::Fastest => Self {
+                highway: tag_actions(&[
+                    ("motorway", RulesTagValueAction::Priority { value: 60 }),
+                    ("trunk", RulesTagValueAction::Priority { value: 50 }),
+                    ("primary", RulesTagValueAction::Priority { value: 40 }),
+                    ("secondary", RulesTagValueAction::Priority { value: 20 }),
+                    ("living_street", RulesTagValueAction::Avoid),
+                    ("track", RulesTagValueAction::Avoid),
+                ]),
+                ..Self::default()
+            },
+            RulesPreset::Shortest => Self {
+                basic: BasicRules {
+                    prefer_same_road: BasicRulePreferSameRoad {
+                        enabled: false,
+                        ..BasicRulePreferSameRoad::default()
+                    },
+                    ..BasicRules::default()
+                },
+                ..Self::default()
+            },
+            RulesPreset::Curvy => Self {
+                basic: BasicRules {
+                    prefer_same_road: BasicRulePreferSameRoad {
+                        enabled: true,
+                        priority: 20,
+                    },
+                    ..BasicRules::default()
+                },
+                highway: tag_actions(&[
+                    ("motorway", RulesTagValueAction::Avoid),
+                    ("trunk", RulesTagValueAction::Avoid),
+                    ("primary", RulesTagValueAction::Priority { value: 5 }),
+                    ("secondary", RulesTagValueAction::Priority { value: 20 }),
+                    ("tertiary", RulesTagValueAction::Priority { value: 40 }),
+                    ("unclassified", RulesTagValueAction::Priority { value: 30 }),
+                ]),
+                ..Self::default()
+            },
+            // residential roads are needed to get in and out of towns so they are not avoided,
+            // everything else is just preferred over them
+            RulesPreset::AvoidCities => Self {
+                highway: tag_actions(&[
+                    ("motorway", RulesTagValueAction::Priority { value: 30 }),
+                    ("trunk", RulesTagValueAction::Priority { value: 30 }),
+                    ("primary", RulesTagValueAction::Priority { value: 30 }),
+                    ("secondary", RulesTagValueAction::Priority { value: 30 }),
+                    ("tertiary", RulesTagValueAction::Priority { value: 30 }),
+                    ("unclassified", RulesTagValueAction::Priority { value: 30 }),
+                    ("track", RulesTagValueAction::Priority { value: 30 }),
+                    ("living_street", RulesTagValueAction::Avoid),
+                ]),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Parse rules json, with a preset the json only needs the values that differ from it
+    fn parse(text: &str, preset: Option<RulesPreset>) -> Result<Self, RulesError> {
+        Self::parse_as(text, RulesFileFormat::Json, preset)
+    }
+
+    fn parse_as(
+        text: &str,
+        format: RulesFileFormat,
+        preset: Option<RulesPreset>,
+    ) -> Result<Self, RulesError> {
+        match (format, preset) {
+            (RulesFileFormat::Json, None) => {
+                serde_json::from_str(text).map_err(|error| RulesError::JsonParse { error })
+            }
+            (RulesFileFormat::Yaml, None) => {
+                serde_yaml::from_str(text).map_err(|error| RulesError::YamlParse { error })
+            }
+            (RulesFileFormat::Toml, None) => {
+                toml::from_str(text).map_err(|error| RulesError::TomlParse { error })
+            }
+            (RulesFileFormat::Json, Some(preset)) => Self::apply_to_preset(
+                preset,
+                serde_json::from_str(text).map_err(|error| RulesError::JsonParse { error })?,
+            ),
+            (RulesFileFormat::Yaml, Some(preset)) => Self::apply_to_preset(
+                preset,
+                serde_yaml::from_str(text).map_err(|error| RulesError::YamlParse { error })?,
+            ),
+            (RulesFileFormat::Toml, Some(preset)) => Self::apply_to_preset(
+                preset,
+                toml::from_str(text).map_err(|error| RulesError::TomlParse { error })?,
+            ),
+        }
+    }
+
+    /// Preset rules with the values in `overrides` replacing the preset ones
+    fn apply_to_preset(
+        preset: RulesPreset,
+        overrides: serde_json::Value,
+    ) -> Result<Self, RulesError> {
+        let mut value = serde_json::to_value(RouterRules::from_preset(preset))
+            .map_err(|error| RulesError::JsonParse { error })?;
+        merge_json(&mut value, overrides);
+        serde_json::from_value(value).map_err(|error| RulesError::JsonParse { error })
+    }
+
+    /// The format is picked from the file extension, json when it is not yaml or toml
     #[tracing::instrument]
-    pub fn read_from_file(file: PathBuf) -> Result<Self, RulesError> {
+    pub fn read_from_file(file: PathBuf, preset: Option<RulesPreset>) -> Result<Self, RulesError> {
+        let format = RulesFileFormat::from_path(&file);
         let file = std::fs::read(file).map_err(|error| RulesError::FileRead { error })?;
         let text =
             std::str::from_utf8(&file[..]).map_err(|error| RulesError::FileParse { error })?;
-        let rules: RouterRules =
-            serde_json::from_str(text).map_err(|error| RulesError::JsonParse { error })?;
 
-        Ok(rules)
+        Self::parse_as(text, format, preset)
     }
 
     #[tracing::instrument]
-    pub fn read_from_stdin() -> Result<Self, RulesError> {
+    pub fn read_from_stdin(preset: Option<RulesPreset>) -> Result<Self, RulesError> {
         let mut text = String::new();
         let stdin = io::stdin();
         let rules: RouterRules = if !stdin.is_terminal() {
@@ -108,18 +695,468 @@ impl RouterRules {
                 text.push_str(&line);
             }
 
-            serde_json::from_str(&text).map_err(|error| RulesError::JsonParse { error })?
+            Self::parse(&text, preset)?
         } else {
-            RouterRules::default()
+            preset.map_or(RouterRules::default(), RouterRules::from_preset)
         };
 
         Ok(rules)
     }
 
-    pub fn read(file: Option<PathBuf>) -> Result<Self, RulesError> {
+    pub fn read(file: Option<PathBuf>, preset: Option<RulesPreset>) -> Result<Self, RulesError> {
         match file {
-            None => Self::read_from_stdin(),
-            Some(file) => Self::read_from_file(file),
+            None => Self::read_from_stdin(preset),
+            Some(file) => Self::read_from_file(file, preset),
+        }
+    }
+
+    /// Combine with `overrides`, tag value actions are merged with the ones in `overrides`
+    /// winning and `weights` is replaced when set. `basic` and `distance_metric` always have a
+    /// value so they are kept from `self`.
+    pub fn merge(self, overrides: RouterRules) -> Self {
+        fn merge_actions(
+            actions: Option<HashMap<String, RulesTagValueAction>>,
+            overrides: Option<HashMap<String, RulesTagValueAction>>,
+        ) -> Option<HashMap<String, RulesTagValueAction>> {
+            match (actions, overrides) {
+                (Some(mut actions), Some(overrides)) => {
+                    actions.extend(overrides);
+                    Some(actions)
+                }
+                (actions, None) => actions,
+                (None, overrides) => overrides,
+            }
+        }
+        Self {
+            weights: overrides.weights.or(self.weights),
+            highway: merge_actions(self.highway, overrides.highway),
+            surface: merge_actions(self.surface, overrides.surface),
+            smoothness: merge_actions(self.smoothness, overrides.smoothness),
+            bridge: merge_actions(self.bridge, overrides.bridge),
+            tunnel: merge_actions(self.tunnel, overrides.tunnel),
+            ford: merge_actions(self.ford, overrides.ford),
+            lit: merge_actions(self.lit, overrides.lit),
+            sort_routes: overrides.sort_routes.or(self.sort_routes),
+            ..self
+        }
+    }
+
+    /// Checks the constraints that parsing alone does not, returns all problems found
+    pub fn validate(&self) -> Vec<RulesError> {
+        let mut errors = Vec::new();
+        if self.basic.progression_direction.check_steps_back == 0 {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.progression_direction.check_steps_back".to_string(),
+                cause: "must be greater than 0".to_string(),
+            });
+        }
+        if self.basic.progression_speed.check_steps_back == 0 {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.progression_speed.check_steps_back".to_string(),
+                cause: "must be greater than 0".to_string(),
+            });
+        }
+        let tolerance = self.basic.progression_direction.tolerance_m;
+        if !tolerance.is_finite() || tolerance < 0. {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.progression_direction.tolerance_m".to_string(),
+                cause: format!("must be 0 or a positive number, got {tolerance}"),
+            });
+        }
+        let deepening = &self.basic.iterative_deepening;
+        if deepening.enabled {
+            if deepening.passes == 0 {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.iterative_deepening.passes".to_string(),
+                    cause: "must be greater than 0".to_string(),
+                });
+            }
+            if deepening.pass_step_limit == 0 {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.iterative_deepening.pass_step_limit".to_string(),
+                    cause: "must be greater than 0".to_string(),
+                });
+            }
+            if !deepening.initial_tolerance_m.is_finite() || deepening.initial_tolerance_m < 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.iterative_deepening.initial_tolerance_m".to_string(),
+                    cause: format!(
+                        "must be 0 or a positive number, got {}",
+                        deepening.initial_tolerance_m
+                    ),
+                });
+            }
+        }
+        let max_detour = &self.basic.max_detour;
+        if max_detour.enabled && (!max_detour.ratio.is_finite() || max_detour.ratio < 1.) {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.max_detour.ratio".to_string(),
+                cause: format!("must be 1 or greater, got {}", max_detour.ratio),
+            });
+        }
+        let hairpins = &self.basic.avoid_hairpins;
+        if hairpins.enabled {
+            if !(0. ..=180.).contains(&hairpins.max_turn_deg) {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.avoid_hairpins.max_turn_deg".to_string(),
+                    cause: format!("must be between 0 and 180, got {}", hairpins.max_turn_deg),
+                });
+            }
+            if !hairpins.lookahead_m.is_finite() || hairpins.lookahead_m < 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.avoid_hairpins.lookahead_m".to_string(),
+                    cause: format!(
+                        "must be 0 or a positive number, got {}",
+                        hairpins.lookahead_m
+                    ),
+                });
+            }
+        }
+        let sharp_turns = &self.basic.no_sharp_turns;
+        if sharp_turns.enabled && !(0. ..=180.).contains(&sharp_turns.under_deg) {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.no_sharp_turns.under_deg".to_string(),
+                cause: format!("must be between 0 and 180, got {}", sharp_turns.under_deg),
+            });
+        }
+        let corridor = &self.basic.corridor;
+        if corridor.enabled {
+            if !corridor.width_m.is_finite() || corridor.width_m <= 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.corridor.width_m".to_string(),
+                    cause: format!("must be a positive number, got {}", corridor.width_m),
+                });
+            }
+            if corridor
+                .reference_track
+                .as_ref()
+                .map_or(false, |track| track.len() < 2)
+            {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.corridor.reference_track".to_string(),
+                    cause: "must have at least two points".to_string(),
+                });
+            }
+        }
+        let consistent_class = &self.basic.prefer_consistent_class;
+        if consistent_class.enabled && consistent_class.class_order.is_empty() {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.prefer_consistent_class.class_order".to_string(),
+                cause: "must list at least one highway class".to_string(),
+            });
+        }
+        let described_ways = &self.basic.prefer_described_ways;
+        if described_ways.enabled && described_ways.tags.is_empty() {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.prefer_described_ways.tags".to_string(),
+                cause: "must list at least one tag".to_string(),
+            });
         }
+        for tag in &described_ways.tags {
+            if !ELEMENT_TAG_KEYS.contains(&tag.as_str()) {
+                errors.push(RulesError::InvalidValue {
+                    name: "basic.prefer_described_ways.tags".to_string(),
+                    cause: format!("{tag} is not stored, use one of {:?}", ELEMENT_TAG_KEYS),
+                });
+            }
+        }
+        let local_search = &self.basic.local_search;
+        if local_search.enabled && local_search.span_junctions == 0 {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.local_search.span_junctions".to_string(),
+                cause: "must be at least 1".to_string(),
+            });
+        }
+        let dimensions = &self.vehicle_dimensions;
+        for (name, value) in [
+            ("height_m", dimensions.height_m),
+            ("width_m", dimensions.width_m),
+            ("length_m", dimensions.length_m),
+            ("weight_t", dimensions.weight_t),
+        ] {
+            if let Some(value) = value {
+                if !value.is_finite() || value <= 0. {
+                    errors.push(RulesError::InvalidValue {
+                        name: format!("vehicle_dimensions.{name}"),
+                        cause: format!("must be a positive number, got {value}"),
+                    });
+                }
+            }
+        }
+        if let Some(range) = self.refuel_range_km {
+            if !range.is_finite() || range <= 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "refuel_range_km".to_string(),
+                    cause: format!("must be a positive number, got {range}"),
+                });
+            }
+        }
+        if let Some(tolerance) = self.simplify_tolerance_m {
+            if !tolerance.is_finite() || tolerance <= 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "simplify_tolerance_m".to_string(),
+                    cause: format!("must be a positive number, got {tolerance}"),
+                });
+            }
+        }
+        if let Some(distance) = self.max_snap_distance_m {
+            if !distance.is_finite() || distance <= 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "max_snap_distance_m".to_string(),
+                    cause: format!("must be a positive number, got {distance}"),
+                });
+            }
+        }
+        if let Some(timeout) = self.itinerary_timeout_s {
+            if !timeout.is_finite() || timeout <= 0. {
+                errors.push(RulesError::InvalidValue {
+                    name: "itinerary_timeout_s".to_string(),
+                    cause: format!("must be a positive number, got {timeout}"),
+                });
+            }
+        }
+        let ratio = self
+            .basic
+            .progression_speed
+            .last_step_distance_below_avg_with_ratio;
+        if !ratio.is_finite() || ratio <= 0. {
+            errors.push(RulesError::InvalidValue {
+                name: "basic.progression_speed.last_step_distance_below_avg_with_ratio".to_string(),
+                cause: format!("must be a positive number, got {ratio}"),
+            });
+        }
+        if let Some(weights) = &self.weights {
+            if weights.is_empty() {
+                errors.push(RulesError::InvalidValue {
+                    name: "weights".to_string(),
+                    cause: "must list at least one weight".to_string(),
+                });
+            }
+            for (idx, weight) in weights.iter().enumerate() {
+                if weights[..idx].contains(weight) {
+                    errors.push(RulesError::InvalidValue {
+                        name: "weights".to_string(),
+                        cause: format!("{:?} is listed more than once", weight),
+                    });
+                }
+            }
+        }
+        // hard constraints that are configured but would be ignored without their weight
+        let weights = self.get_weights();
+        for (name, configured, weight) in [
+            (
+                "vehicle_dimensions",
+                self.vehicle_dimensions.is_set(),
+                RulesWeight::VehicleDimensions,
+            ),
+            (
+                "avoid_way_ids",
+                !self.avoid_way_ids.is_empty(),
+                RulesWeight::AvoidWayIds,
+            ),
+            ("basic.corridor", corridor.enabled, RulesWeight::Corridor),
+            (
+                "basic.avoid_hairpins",
+                hairpins.enabled,
+                RulesWeight::AvoidHairpins,
+            ),
+            (
+                "basic.no_sharp_turns",
+                sharp_turns.enabled,
+                RulesWeight::NoSharpTurns,
+            ),
+        ] {
+            if configured && !weights.contains(&weight) {
+                errors.push(RulesError::InvalidValue {
+                    name: name.to_string(),
+                    cause: format!("is set but {:?} is not in weights", weight),
+                });
+            }
+        }
+
+        errors
+    }
+
+    pub fn get_weights(&self) -> Vec<RulesWeight> {
+        match &self.weights {
+            None => DEFAULT_WEIGHTS.to_vec(),
+            Some(weights) => weights.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use std::path::Path;
+
+    use super::{
+        RouterRules, RulesFileFormat, RulesPreset, RulesRouteSort, RulesTagValueAction,
+        RulesWeight, DEFAULT_WEIGHTS,
+    };
+
+    #[test]
+    fn rules_weights() {
+        let rules = RouterRules::default();
+        assert_eq!(rules.get_weights(), DEFAULT_WEIGHTS.to_vec());
+
+        let rules: RouterRules =
+            serde_json::from_str(r#"{"weights": ["heading", "no_loops"]}"#).unwrap();
+        assert_eq!(
+            rules.get_weights(),
+            vec![RulesWeight::Heading, RulesWeight::NoLoops]
+        );
+
+        let rules = serde_json::from_str::<RouterRules>(r#"{"weights": ["no_such_weight"]}"#);
+        assert!(rules.is_err());
+    }
+
+    #[test]
+    fn rules_validate() {
+        assert!(RouterRules::default().validate().is_empty());
+
+        let mut rules = RouterRules::default();
+        rules.basic.progression_direction.check_steps_back = 0;
+        rules
+            .basic
+            .progression_speed
+            .last_step_distance_below_avg_with_ratio = -1.;
+        rules.weights = Some(vec![RulesWeight::Heading, RulesWeight::Heading]);
+        assert_eq!(rules.validate().len(), 3);
+
+        rules.weights = Some(Vec::new());
+        assert_eq!(rules.validate().len(), 3);
+
+        let mut rules = RouterRules::default();
+        rules.basic.prefer_described_ways.tags = vec!["name".to_string(), "colour".to_string()];
+        assert_eq!(rules.validate().len(), 1);
+
+        let mut rules = RouterRules::default();
+        rules.max_snap_distance_m = Some(0.);
+        rules.itinerary_timeout_s = Some(f32::NAN);
+        assert_eq!(rules.validate().len(), 2);
+
+        let mut rules = RouterRules {
+            avoid_way_ids: [1].into(),
+            ..RouterRules::default()
+        };
+        rules.basic.corridor.enabled = true;
+        assert!(rules.validate().is_empty());
+        rules.weights = Some(vec![RulesWeight::Heading, RulesWeight::Corridor]);
+        assert_eq!(rules.validate().len(), 1);
+    }
+
+    #[test]
+    fn rules_presets() {
+        for preset in [
+            RulesPreset::Fastest,
+            RulesPreset::Shortest,
+            RulesPreset::Curvy,
+            RulesPreset::AvoidCities,
+        ] {
+            assert!(RouterRules::from_preset(preset).validate().is_empty());
+        }
+        // presets only change the weight parameters, constraints from the rules file still apply
+        let rules =
+            RouterRules::parse(r#"{"avoid_way_ids": [1]}"#, Some(RulesPreset::Shortest)).unwrap();
+        assert_eq!(rules.get_weights(), DEFAULT_WEIGHTS.to_vec());
+        assert!(rules.validate().is_empty());
+
+        let rules = RouterRules::parse(
+            r#"{"basic": {"prefer_same_road": {"priority": 10}}, "highway": {"motorway": {"action": "priority", "value": 1}}}"#,
+            Some(RulesPreset::Curvy),
+        )
+        .unwrap();
+        assert!(rules.basic.prefer_same_road.enabled);
+        assert_eq!(rules.basic.prefer_same_road.priority, 10);
+        let highway = rules.highway.unwrap();
+        assert!(matches!(
+            highway.get("motorway"),
+            Some(RulesTagValueAction::Priority { value: 1 })
+        ));
+        assert!(matches!(
+            highway.get("trunk"),
+            Some(RulesTagValueAction::Avoid)
+        ));
+    }
+
+    #[test]
+    fn rules_file_formats() {
+        let format = |path| RulesFileFormat::from_path(Path::new(path));
+        assert_eq!(format("rules.json"), RulesFileFormat::Json);
+        assert_eq!(format("rules.yaml"), RulesFileFormat::Yaml);
+        assert_eq!(format("dir/rules.YML"), RulesFileFormat::Yaml);
+        assert_eq!(format("rules.toml"), RulesFileFormat::Toml);
+        assert_eq!(format("rules"), RulesFileFormat::Json);
+
+        let yaml = "highway:\n  motorway:\n    action: priority\n    value: 1\n";
+        let rules = RouterRules::parse_as(yaml, RulesFileFormat::Yaml, None).unwrap();
+        assert!(matches!(
+            rules.highway.unwrap().get("motorway"),
+            Some(RulesTagValueAction::Priority { value: 1 })
+        ));
+
+        let toml = "[highway.motorway]\naction = \"priority\"\nvalue = 1\n";
+        let rules =
+            RouterRules::parse_as(toml, RulesFileFormat::Toml, Some(RulesPreset::Curvy)).unwrap();
+        let highway = rules.highway.unwrap();
+        assert!(matches!(
+            highway.get("motorway"),
+            Some(RulesTagValueAction::Priority { value: 1 })
+        ));
+        assert!(matches!(
+            highway.get("trunk"),
+            Some(RulesTagValueAction::Avoid)
+        ));
+
+        assert!(RouterRules::parse_as("highway: [", RulesFileFormat::Yaml, None).is_err());
+    }
+
+    #[test]
+    fn rules_merge() {
+        let rules = RouterRules {
+            highway: Some(HashMap::from([
+                (
+                    "motorway".to_string(),
+                    RulesTagValueAction::Priority { value: 10 },
+                ),
+                (
+                    "trunk".to_string(),
+                    RulesTagValueAction::Priority { value: 5 },
+                ),
+            ])),
+            weights: Some(vec![RulesWeight::Heading]),
+            sort_routes: Some(RulesRouteSort::Length),
+            ..RouterRules::default()
+        }
+        .merge(RouterRules {
+            highway: Some(HashMap::from([(
+                "motorway".to_string(),
+                RulesTagValueAction::Avoid,
+            )])),
+            surface: Some(HashMap::from([(
+                "gravel".to_string(),
+                RulesTagValueAction::Avoid,
+            )])),
+            ..RouterRules::default()
+        });
+
+        let highway = rules.highway.unwrap();
+        assert!(matches!(
+            highway.get("motorway"),
+            Some(RulesTagValueAction::Avoid)
+        ));
+        assert!(matches!(
+            highway.get("trunk"),
+            Some(RulesTagValueAction::Priority { value: 5 })
+        ));
+        assert!(matches!(
+            rules.surface.unwrap().get("gravel"),
+            Some(RulesTagValueAction::Avoid)
+        ));
+        assert_eq!(rules.sort_routes, Some(RulesRouteSort::Length));
+        assert!(rules.smoothness.is_none());
+        assert_eq!(rules.weights, Some(vec![RulesWeight::Heading]));
     }
 }
@@ -101,62 +101,11 @@ impl Walker {
             &self.start.borrow()
         };
 
-        let center_point_borrowed = center_point.borrow();
-        let only_allow_rules = center_point_borrowed
-            .rules
-            .iter()
-            .filter(|rule| {
-                rule.rule_type == MapDataRuleType::OnlyAllowed
-                    && rule.from_lines.contains(&center_line)
-            })
-            .collect::<Vec<_>>();
-
-        let not_allow_rules = center_point_borrowed
-            .rules
-            .iter()
-            .filter(|rule| {
-                rule.rule_type == MapDataRuleType::NotAllowed
-                    && rule.from_lines.contains(&center_line)
-            })
-            .collect::<Vec<_>>();
-
         MapDataGraph::get()
-            .get_adjacent(center_point.clone())
+            .get_adjacent_directed(center_point.clone(), Some(center_line))
             .into_iter()
-            .filter(|(line_next, point_next)| {
-                // do not offer the same line as you came from
-                if point_next.borrow().id == prev_point.id {
-                    return false;
-                }
-
-                // exclude if next line is one way and the direction is backwards
-                if line_next.borrow().is_one_way() && &line_next.borrow().points.1 == center_point {
-                    return false;
-                }
-
-                // if no rules exist, don't check anything further
-                if center_point.borrow().rules.len() == 0 {
-                    return true;
-                }
-
-                // if not allow rules exist, make sure next line is not in them
-                if not_allow_rules
-                    .iter()
-                    .any(|rule| rule.to_lines.contains(line_next))
-                {
-                    return false;
-                }
-
-                // if only allow rules exist, only check those
-                if only_allow_rules.len() > 0 {
-                    return only_allow_rules
-                        .iter()
-                        .any(|rule| rule.to_lines.contains(line_next));
-                }
-
-                // must not be in not allow rules
-                true
-            })
+            // do not offer the same line as you came from
+            .filter(|(_, point_next)| point_next.borrow().id != prev_point.id)
             .map(|(line, end_point)| Segment::new(line, end_point))
             .collect()
     }
@@ -1,14 +1,23 @@
 use std::collections::HashMap;
 
-use geo::{HaversineBearing, Point};
+use geo::MEAN_EARTH_RADIUS;
 use tracing::{error, trace};
 
-use crate::router::rules::{RouterRules, RulesTagValueAction};
+use crate::{
+    geo_utils::{bearing_between, bearing_difference},
+    map_data::graph::MapDataPointRef,
+    router::rules::{BasicRuleLoops, BasicRuleRoundaboutAction, RouterRules, RulesTagValueAction},
+};
 
 use super::{
     itinerary::Itinerary,
     navigator::WeightCalcResult,
-    route::{segment::Segment, segment_list::SegmentList, Route},
+    route::{
+        instructions::{get_bearing, get_turn_deg},
+        segment::Segment,
+        segment_list::SegmentList,
+        Route,
+    },
     walker::{Walker, WalkerMoveResult},
 };
 
@@ -19,6 +28,8 @@ pub struct WeightCalcInput<'a> {
     pub itinerary: &'a Itinerary,
     pub walker_from_fork: Walker,
     pub rules: &'a RouterRules,
+    /// Projected once per navigation, `weight_corridor` projects it when it is not set
+    pub corridor: Option<&'a CorridorTrack>,
 }
 
 pub type WeightCalc = fn(input: WeightCalcInput) -> WeightCalcResult;
@@ -43,36 +54,133 @@ pub fn weight_heading(input: WeightCalcInput) -> WeightCalcResult {
         Some(last_segment) => last_segment,
         None => input.current_fork_segment,
     };
-    let fork_point_geo = Point::new(
-        fork_segment.get_end_point().borrow().lon,
-        fork_segment.get_end_point().borrow().lat,
-    );
-    let next_point_geo = Point::new(
-        input.itinerary.get_next().borrow().lon,
-        input.itinerary.get_next().borrow().lat,
-    );
-    let next_bearing = fork_point_geo.haversine_bearing(next_point_geo);
-    let fork_line_one_geo = Point::new(
-        fork_segment.get_line().borrow().points.0.borrow().lon,
-        fork_segment.get_line().borrow().points.0.borrow().lat,
-    );
-    let fork_line_two_geo = Point::new(
-        fork_segment.get_line().borrow().points.1.borrow().lon,
-        fork_segment.get_line().borrow().points.1.borrow().lat,
+    let fork_point = fork_segment.get_end_point().borrow();
+    let next_point = input.itinerary.get_next().borrow();
+    let next_bearing = bearing_between(
+        (fork_point.lat, fork_point.lon),
+        (next_point.lat, next_point.lon),
     );
-    let fork_bearing = if &fork_segment.get_line().borrow().points.1 == fork_segment.get_end_point()
-    {
-        fork_line_one_geo.haversine_bearing(fork_line_two_geo)
-    } else {
-        fork_line_two_geo.haversine_bearing(fork_line_one_geo)
-    };
+    let fork_bearing = get_bearing(fork_segment);
 
-    let degree_offset_from_next =
-        ((180.0 - fork_bearing.abs()) - (180.0 - next_bearing.abs())).abs();
+    WeightCalcResult::UseWithWeight(get_heading_weight(fork_bearing, next_bearing))
+}
+
+/// 255 when the fork heads straight at the next waypoint, lower the further it turns away. The
+/// offset is taken across north so 5 and 355 are 10 degrees apart
+fn get_heading_weight(fork_bearing: f32, next_bearing: f32) -> u8 {
+    let degree_offset_from_next = bearing_difference(fork_bearing, next_bearing);
 
     let ratio: f32 = 255.0 / 180.0;
 
-    WeightCalcResult::UseWithWeight(255 - (degree_offset_from_next / ratio).round() as u8)
+    255 - (degree_offset_from_next / ratio).round() as u8
+}
+
+/// The fork segment followed by the segments the walker passes without stopping at a fork,
+/// up to the next junction, a dead end or `finish`
+struct BranchSegments {
+    first_point: MapDataPointRef,
+    finish: MapDataPointRef,
+    next: Option<Segment>,
+}
+
+impl BranchSegments {
+    fn new(fork_segment: &Segment, finish: &MapDataPointRef) -> Self {
+        Self {
+            first_point: fork_segment.get_end_point().clone(),
+            finish: finish.clone(),
+            next: Some(fork_segment.clone()),
+        }
+    }
+
+    fn get_next_segment(segment: &Segment) -> Option<Segment> {
+        let end_point = segment.get_end_point();
+        let next_line = {
+            let point = end_point.borrow();
+            if point.lines.len() != 2 {
+                return None;
+            }
+            point
+                .lines
+                .iter()
+                .find(|line| *line != segment.get_line())?
+                .clone()
+        };
+        let next_point = {
+            let line = next_line.borrow();
+            if line.points.0 == *end_point {
+                line.points.1.clone()
+            } else {
+                line.points.0.clone()
+            }
+        };
+        Some(Segment::new(next_line, next_point))
+    }
+}
+
+impl Iterator for BranchSegments {
+    type Item = Segment;
+
+    fn next(&mut self) -> Option<Segment> {
+        let segment = self.next.take()?;
+        if *segment.get_end_point() != self.finish {
+            self.next = BranchSegments::get_next_segment(&segment)
+                .filter(|next| *next.get_end_point() != self.first_point);
+        }
+        Some(segment)
+    }
+}
+
+/// Follows the fork choice through points that are not junctions for up to
+/// `avoid_hairpins.lookahead_m` metres and discards it when the heading turns by more than
+/// `avoid_hairpins.max_turn_deg` compared to the last segment of the route
+pub fn weight_avoid_hairpins(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_avoid_hairpins");
+    let rule = &input.rules.basic.avoid_hairpins;
+    if !rule.enabled {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let entry_bearing = match input.route.get_segment_last() {
+        Some(segment) => get_bearing(segment),
+        None => return WeightCalcResult::UseWithWeight(0),
+    };
+
+    let mut distance = 0.;
+    for segment in BranchSegments::new(input.current_fork_segment, input.itinerary.get_to()) {
+        if get_turn_deg(entry_bearing, get_bearing(&segment)).abs() > rule.max_turn_deg {
+            return WeightCalcResult::DoNotUse;
+        }
+        distance += segment.get_line().borrow().get_len_m();
+        if distance >= rule.lookahead_m {
+            break;
+        }
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+/// Discards fork choices that turn sharper than `no_sharp_turns.under_deg` allows, the angle
+/// is 180 degrees minus the turn from the last segment of the route to the fork segment
+pub fn weight_no_sharp_turns(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_no_sharp_turns");
+    let rule = &input.rules.basic.no_sharp_turns;
+    if !rule.enabled {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let entry_bearing = match input.route.get_segment_last() {
+        Some(segment) => get_bearing(segment),
+        None => return WeightCalcResult::UseWithWeight(0),
+    };
+    let turn_deg = get_turn_deg(entry_bearing, get_bearing(input.current_fork_segment));
+    let angle_deg = 180. - turn_deg.abs();
+    if angle_deg < rule.under_deg {
+        return WeightCalcResult::DoNotUse;
+    }
+    if rule.under_deg >= 180. {
+        return WeightCalcResult::UseWithWeight(rule.priority);
+    }
+
+    let straightness = (angle_deg - rule.under_deg) / (180. - rule.under_deg);
+    WeightCalcResult::UseWithWeight((rule.priority as f32 * straightness).round() as u8)
 }
 
 pub fn weight_prefer_same_road(input: WeightCalcInput) -> WeightCalcResult {
@@ -103,8 +211,21 @@ pub fn weight_prefer_same_road(input: WeightCalcInput) -> WeightCalcResult {
         .borrow()
         .name();
 
-    if (current_ref.is_some() && fork_ref.is_some() && current_ref == fork_ref)
-        || (current_name.is_some() && fork_name.is_some() && current_name == fork_name)
+    let current_is_link = input
+        .route
+        .get_segment_last()
+        .is_some_and(|s| s.get_line().borrow().tags.borrow().is_link());
+    let fork_is_link = input
+        .current_fork_segment
+        .get_line()
+        .borrow()
+        .tags
+        .borrow()
+        .is_link();
+
+    if current_is_link == fork_is_link
+        && ((current_ref.is_some() && fork_ref.is_some() && current_ref == fork_ref)
+            || (current_name.is_some() && fork_name.is_some() && current_name == fork_name))
     {
         return WeightCalcResult::UseWithWeight(input.rules.basic.prefer_same_road.priority);
     }
@@ -112,9 +233,98 @@ pub fn weight_prefer_same_road(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::UseWithWeight(0)
 }
 
+pub fn weight_prefer_numbered_roads(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_prefer_numbered_roads");
+    let rule = &input.rules.basic.prefer_numbered_roads;
+    if rule.enabled
+        && input
+            .current_fork_segment
+            .get_line()
+            .borrow()
+            .tags
+            .borrow()
+            .hw_ref()
+            .is_some()
+    {
+        return WeightCalcResult::UseWithWeight(rule.priority);
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+pub fn weight_prefer_consistent_class(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_prefer_consistent_class");
+    let rule = &input.rules.basic.prefer_consistent_class;
+    if !rule.enabled {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let get_rank = |segment: &Segment| {
+        let line = segment.get_line().borrow();
+        let tags = line.tags.borrow();
+        tags.highway_class()
+            .and_then(|class| rule.class_order.iter().position(|rank| rank == class))
+    };
+    let current_rank = match input.route.get_segment_last().and_then(get_rank) {
+        Some(rank) => rank,
+        None => return WeightCalcResult::UseWithWeight(0),
+    };
+    match get_rank(input.current_fork_segment).map(|rank| rank.abs_diff(current_rank)) {
+        Some(0) => WeightCalcResult::UseWithWeight(rule.priority),
+        Some(1) => WeightCalcResult::UseWithWeight(rule.priority / 2),
+        _ => WeightCalcResult::UseWithWeight(0),
+    }
+}
+
+pub fn weight_prefer_described_ways(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_prefer_described_ways");
+    let rule = &input.rules.basic.prefer_described_ways;
+    if !rule.enabled || rule.tags.is_empty() {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let line = input.current_fork_segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    let described = rule
+        .tags
+        .iter()
+        .filter(|tag| tags.get_tag(tag).is_some())
+        .count();
+    WeightCalcResult::UseWithWeight((rule.priority as usize * described / rule.tags.len()) as u8)
+}
+
+pub fn weight_roundabout_preference(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_roundabout_preference");
+    let rule = &input.rules.basic.roundabout_preference;
+    if rule.action == BasicRuleRoundaboutAction::Neutral {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let on_roundabout = input
+        .route
+        .get_segment_last()
+        .is_some_and(|segment| segment.get_line().borrow().is_roundabout());
+    if !on_roundabout
+        && input
+            .current_fork_segment
+            .get_line()
+            .borrow()
+            .is_roundabout()
+    {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+
+    WeightCalcResult::UseWithWeight(rule.priority)
+}
+
 pub fn weight_no_loops(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_no_loops");
-    if input.route.has_looped() {
+    if !input.route.has_looped() {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let allowed = match input.rules.basic.loops {
+        BasicRuleLoops::Forbid => false,
+        BasicRuleLoops::AllowUpTo { count } => input.route.get_loop_count() <= count,
+        BasicRuleLoops::Unlimited => true,
+    };
+    if !allowed {
         return WeightCalcResult::DoNotUse;
     }
 
@@ -145,7 +355,9 @@ pub fn weight_check_distance_to_next(input: WeightCalcInput) -> WeightCalcResult
             .distance_between(&input.itinerary.get_next()),
     };
 
-    if distance_to_end_current > distance_to_end_steps_back {
+    if distance_to_end_current
+        > distance_to_end_steps_back + input.rules.basic.progression_direction.tolerance_m
+    {
         return WeightCalcResult::DoNotUse;
     }
     WeightCalcResult::UseWithWeight(0)
@@ -159,6 +371,9 @@ pub fn weight_progress_speed(input: WeightCalcInput) -> WeightCalcResult {
     }
 
     let check_steps_back = input.rules.basic.progression_speed.check_steps_back;
+    if check_steps_back == 0 {
+        return WeightCalcResult::UseWithWeight(0);
+    }
 
     let current_point = match input.route.get_segment_last() {
         None => return WeightCalcResult::UseWithWeight(0),
@@ -196,11 +411,11 @@ pub fn weight_progress_speed(input: WeightCalcInput) -> WeightCalcResult {
 
 fn get_rule_for_tag(
     rule: &Option<HashMap<String, RulesTagValueAction>>,
-    segment_tag: Option<&smartstring::alias::String>,
+    segment_tag: Option<&str>,
 ) -> Option<WeightCalcResult> {
     if let Some(ref rule_tag) = rule {
         if let Some(segment_tag) = segment_tag {
-            let rule_tag = rule_tag.get(&segment_tag.to_string());
+            let rule_tag = rule_tag.get(segment_tag);
             if let Some(rule_tag) = rule_tag {
                 return Some(match rule_tag {
                     RulesTagValueAction::Avoid => WeightCalcResult::DoNotUse,
@@ -217,15 +432,34 @@ fn get_rule_for_tag(
 pub fn weight_rules_highway(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_rules_highway");
 
+    let line = input.current_fork_segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    if let Some(res) = get_rule_for_tag(&input.rules.highway, tags.highway().map(|v| v.as_str())) {
+        return res;
+    }
+    // links without a rule of their own follow the rule of their road class
+    if tags.is_link() {
+        if let Some(res) = get_rule_for_tag(&input.rules.highway, tags.highway_class()) {
+            return res;
+        }
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+pub fn weight_rules_surface(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_surface");
+
     if let Some(res) = get_rule_for_tag(
-        &input.rules.highway,
+        &input.rules.surface,
         input
             .current_fork_segment
             .get_line()
             .borrow()
             .tags
             .borrow()
-            .highway(),
+            .surface()
+            .map(|v| v.as_str()),
     ) {
         return res;
     }
@@ -233,18 +467,19 @@ pub fn weight_rules_highway(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::UseWithWeight(0)
 }
 
-pub fn weight_rules_surface(input: WeightCalcInput) -> WeightCalcResult {
-    trace!("weight_rules_surface");
+pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_smoothness");
 
     if let Some(res) = get_rule_for_tag(
-        &input.rules.surface,
+        &input.rules.smoothness,
         input
             .current_fork_segment
             .get_line()
             .borrow()
             .tags
             .borrow()
-            .surface(),
+            .smoothness()
+            .map(|v| v.as_str()),
     ) {
         return res;
     }
@@ -252,18 +487,53 @@ pub fn weight_rules_surface(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::UseWithWeight(0)
 }
 
-pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
-    trace!("weight_rules_smoothness");
+pub fn weight_rules_bridge(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_bridge");
 
     if let Some(res) = get_rule_for_tag(
-        &input.rules.smoothness,
+        &input.rules.bridge,
         input
             .current_fork_segment
             .get_line()
             .borrow()
-            .tags
+            .bridge()
+            .map(|v| v.as_str()),
+    ) {
+        return res;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+pub fn weight_rules_tunnel(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_tunnel");
+
+    if let Some(res) = get_rule_for_tag(
+        &input.rules.tunnel,
+        input
+            .current_fork_segment
+            .get_line()
+            .borrow()
+            .tunnel()
+            .map(|v| v.as_str()),
+    ) {
+        return res;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+pub fn weight_rules_ford(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_ford");
+
+    if let Some(res) = get_rule_for_tag(
+        &input.rules.ford,
+        input
+            .current_fork_segment
+            .get_line()
             .borrow()
-            .smoothness(),
+            .ford()
+            .map(|v| v.as_str()),
     ) {
         return res;
     }
@@ -271,10 +541,192 @@ pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::UseWithWeight(0)
 }
 
+pub fn weight_rules_lit(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_rules_lit");
+
+    if let Some(res) = get_rule_for_tag(
+        &input.rules.lit,
+        input
+            .current_fork_segment
+            .get_line()
+            .borrow()
+            .lit()
+            .map(|v| v.as_str()),
+    ) {
+        return res;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+/// `reference_track`, or the line from start to finish, on a flat projection around the mean
+/// latitude of the track. The east west scale is off by a few percent a degree of latitude
+/// away from it which is accurate enough for corridor widths
+pub struct CorridorTrack {
+    lon_scale: f32,
+    points: Vec<(f32, f32)>,
+}
+
+impl CorridorTrack {
+    pub fn new(track: &[(f32, f32)]) -> Self {
+        let mean_lat = track.iter().map(|(lat, _)| lat).sum::<f32>() / track.len().max(1) as f32;
+        let mut corridor = Self {
+            lon_scale: mean_lat.to_radians().cos(),
+            points: Vec::new(),
+        };
+        corridor.points = track
+            .iter()
+            .map(|(lat, lon)| corridor.project(*lat, *lon))
+            .collect();
+        corridor
+    }
+
+    /// `None` when the corridor rule is not enabled
+    pub fn from_rules(rules: &RouterRules, itinerary: &Itinerary) -> Option<Self> {
+        let rule = &rules.basic.corridor;
+        if !rule.enabled {
+            return None;
+        }
+        Some(match rule.reference_track.as_deref() {
+            Some(track) => CorridorTrack::new(track),
+            None => CorridorTrack::new(
+                &[itinerary.get_from(), itinerary.get_to()]
+                    .iter()
+                    .map(|point| (point.borrow().lat, point.borrow().lon))
+                    .collect::<Vec<_>>(),
+            ),
+        })
+    }
+
+    fn project(&self, lat: f32, lon: f32) -> (f32, f32) {
+        (
+            lon.to_radians() * self.lon_scale * MEAN_EARTH_RADIUS as f32,
+            lat.to_radians() * MEAN_EARTH_RADIUS as f32,
+        )
+    }
+
+    /// Distance from the coordinates to the closest point of the track
+    pub fn get_distance_m(&self, lat: f32, lon: f32) -> f32 {
+        let (x, y) = self.project(lat, lon);
+        if self.points.len() == 1 {
+            return ((self.points[0].0 - x).powi(2) + (self.points[0].1 - y).powi(2)).sqrt();
+        }
+        self.points
+            .windows(2)
+            .map(|line| {
+                let ((x1, y1), (x2, y2)) = (
+                    (line[0].0 - x, line[0].1 - y),
+                    (line[1].0 - x, line[1].1 - y),
+                );
+                let (dx, dy) = (x2 - x1, y2 - y1);
+                let len_sq = dx * dx + dy * dy;
+                let t = if len_sq == 0. {
+                    0.
+                } else {
+                    (-(x1 * dx + y1 * dy) / len_sq).clamp(0., 1.)
+                };
+                ((x1 + t * dx).powi(2) + (y1 + t * dy).powi(2)).sqrt()
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
+}
+
+pub fn weight_corridor(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_corridor");
+    let rule = &input.rules.basic.corridor;
+    if !rule.enabled {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let projected;
+    let track = match input.corridor {
+        Some(track) => track,
+        None => match CorridorTrack::from_rules(input.rules, input.itinerary) {
+            Some(track) => {
+                projected = track;
+                &projected
+            }
+            None => return WeightCalcResult::UseWithWeight(0),
+        },
+    };
+    let end_point = input.current_fork_segment.get_end_point().borrow();
+    if track.get_distance_m(end_point.lat, end_point.lon) > rule.width_m {
+        return WeightCalcResult::DoNotUse;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+pub fn weight_penalize_destination_access(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_penalize_destination_access");
+    let rule = &input.rules.basic.destination_access;
+    if !rule.enabled
+        || input
+            .current_fork_segment
+            .get_line()
+            .borrow()
+            .is_destination_access()
+    {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+
+    WeightCalcResult::UseWithWeight(rule.priority)
+}
+
+/// Checks every line up to the next fork, a low bridge is often its own short way between
+/// points that are not forks
+pub fn weight_vehicle_dimensions(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_vehicle_dimensions");
+    let vehicle = &input.rules.vehicle_dimensions;
+    if !vehicle.is_set() {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    let too_big = |segment: &Segment| {
+        let line = segment.get_line().borrow();
+        let tags = line.tags.borrow();
+        [
+            (vehicle.height_m, tags.max_height_m()),
+            (vehicle.width_m, tags.max_width_m()),
+            (vehicle.length_m, tags.max_length_m()),
+            (vehicle.weight_t, tags.max_weight_t()),
+        ]
+        .iter()
+        .any(|limit| match limit {
+            (Some(vehicle), Some(max)) => vehicle > max,
+            _ => false,
+        })
+    };
+    if BranchSegments::new(input.current_fork_segment, input.itinerary.get_to())
+        .any(|segment| too_big(&segment))
+    {
+        return WeightCalcResult::DoNotUse;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
+/// Bans the whole branch up to the next fork, the walker would otherwise pass an avoided way
+/// that starts at a point that is not a fork
+pub fn weight_avoid_way_ids(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_avoid_way_ids");
+    if input.rules.avoid_way_ids.is_empty() {
+        return WeightCalcResult::UseWithWeight(0);
+    }
+    if BranchSegments::new(input.current_fork_segment, input.itinerary.get_to()).any(|segment| {
+        input
+            .rules
+            .avoid_way_ids
+            .contains(&segment.get_line().borrow().way_id)
+    }) {
+        return WeightCalcResult::DoNotUse;
+    }
+
+    WeightCalcResult::UseWithWeight(0)
+}
+
 #[cfg(test)]
 mod test {
 
-    use std::path::PathBuf;
+    use std::{collections::HashMap, path::PathBuf};
 
     use rusty_fork::rusty_fork_test;
     use tracing::info;
@@ -284,14 +736,27 @@ mod test {
         router::{
             itinerary::Itinerary,
             navigator::WeightCalcResult,
-            route::{segment::Segment, segment_list::SegmentList},
-            rules::RouterRules,
+            route::{segment::Segment, segment_list::SegmentList, Route},
+            rules::{
+                BasicRuleCorridor, BasicRuleLoops, BasicRuleRoundaboutAction, BasicRules,
+                RouterRules, RulesTagValueAction,
+            },
             walker::Walker,
         },
-        test_utils::{graph_from_test_file, set_graph_static},
+        test_utils::{
+            graph_from_test_dataset, graph_from_test_file, set_graph_static, test_dataset_1,
+            TestGraphBuilder, WeightCalcInputBuilder,
+        },
     };
 
-    use super::{weight_heading, WeightCalcInput};
+    use super::{
+        get_heading_weight, weight_avoid_hairpins, weight_avoid_way_ids, weight_corridor,
+        weight_heading, weight_no_loops, weight_no_sharp_turns, weight_penalize_destination_access,
+        weight_prefer_consistent_class, weight_prefer_described_ways, weight_prefer_numbered_roads,
+        weight_prefer_same_road, weight_progress_speed, weight_roundabout_preference,
+        weight_rules_bridge, weight_rules_ford, weight_rules_highway, weight_rules_lit,
+        weight_rules_tunnel, weight_vehicle_dimensions, CorridorTrack, WeightCalcInput,
+    };
 
     fn get_route_segment(
         end_point: MapDataPointRef,
@@ -311,6 +776,17 @@ mod test {
         Segment::new(line.clone(), end_point.clone())
     }
 
+    #[test]
+    fn heading_weight_across_north() {
+        // 10 degrees apart, not 350
+        assert_eq!(get_heading_weight(5., -5.), 248);
+        assert_eq!(get_heading_weight(355., 5.), get_heading_weight(5., -5.));
+        assert_eq!(get_heading_weight(5., 355.), get_heading_weight(5., -5.));
+        assert_eq!(get_heading_weight(-175., 175.), get_heading_weight(5., -5.));
+        assert_eq!(get_heading_weight(45., 45.), 255);
+        assert_eq!(get_heading_weight(90., -90.), 128);
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -345,7 +821,8 @@ mod test {
                     from.clone(),
                     to.clone(),
                 ),
-                rules: &RouterRules::default()
+                rules: &RouterRules::default(),
+                corridor: None,
 
             });
             info!("{:#?}", fork_weight);
@@ -366,10 +843,810 @@ mod test {
                     from.clone(),
                     to.clone(),
                 ),
-                rules: &RouterRules::default()
+                rules: &RouterRules::default(),
+                corridor: None,
             });
             info!("{:#?}", fork_weight);
-            assert_eq!(fork_weight, WeightCalcResult::UseWithWeight(162));
+            assert_eq!(fork_weight, WeightCalcResult::UseWithWeight(160));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_motorway_link_test() {
+            // 1 - motorway - 2 - motorway_link - 3
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3])
+                .way(1, &[1, 2], &[("highway", "motorway"), ("ref", "A1")])
+                .way(2, &[2, 3], &[("highway", "motorway_link"), ("ref", "A1")])
+                .build();
+            set_graph_static(map_data);
+            let point_1 = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let point_2 = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let point_3 = MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap();
+
+            let motorway_segment = get_route_segment(point_2.clone(), point_1.clone());
+            let link_segment = get_route_segment(point_3.clone(), point_2.clone());
+            let motorway_tags = motorway_segment.get_line().borrow().tags.borrow().clone();
+            let link_tags = link_segment.get_line().borrow().tags.borrow().clone();
+            assert_eq!(motorway_tags.highway().unwrap(), "motorway");
+            assert!(!motorway_tags.is_link());
+            assert_eq!(link_tags.highway().unwrap(), "motorway_link");
+            assert_eq!(link_tags.highway_class(), Some("motorway"));
+            assert!(link_tags.is_link());
+
+            let inputs = WeightCalcInputBuilder::new(Itinerary::new(
+                point_1.clone(),
+                point_3.clone(),
+                Vec::new(),
+                0.,
+            ))
+            .route(Route::from(vec![motorway_segment]));
+            let input = |rules| inputs.build(&link_segment, rules);
+
+            let rules = RouterRules::default();
+            assert_eq!(
+                weight_prefer_same_road(input(&rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let rules = RouterRules {
+                highway: Some(HashMap::from([(
+                    "motorway".to_string(),
+                    RulesTagValueAction::Avoid,
+                )])),
+                ..RouterRules::default()
+            };
+            assert_eq!(weight_rules_highway(input(&rules)), WeightCalcResult::DoNotUse);
+
+            let rules = RouterRules {
+                highway: Some(HashMap::from([
+                    ("motorway".to_string(), RulesTagValueAction::Avoid),
+                    (
+                        "motorway_link".to_string(),
+                        RulesTagValueAction::Priority { value: 10 },
+                    ),
+                ])),
+                ..RouterRules::default()
+            };
+            assert_eq!(
+                weight_rules_highway(input(&rules)),
+                WeightCalcResult::UseWithWeight(10)
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_bridge_tunnel_ford_test() {
+            // 1 - tunnel - 2 - ford - 3
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3])
+                .way(1, &[1, 2], &[("highway", "primary"), ("tunnel", "yes")])
+                .way(2, &[2, 3], &[("highway", "track"), ("ford", "yes")])
+                .build();
+            set_graph_static(map_data);
+            let point_1 = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let point_2 = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let point_3 = MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap();
+
+            let tunnel_segment = get_route_segment(point_2.clone(), point_1.clone());
+            let ford_segment = get_route_segment(point_3.clone(), point_2.clone());
+            assert_eq!(tunnel_segment.get_line().borrow().tunnel().unwrap(), "yes");
+            assert_eq!(tunnel_segment.get_line().borrow().ford(), None);
+            assert_eq!(ford_segment.get_line().borrow().ford().unwrap(), "yes");
+            assert_eq!(ford_segment.get_line().borrow().bridge(), None);
+
+            let itinerary = Itinerary::new(point_1.clone(), point_3.clone(), Vec::new(), 0.);
+            let inputs = WeightCalcInputBuilder::new(itinerary.clone())
+                .route(Route::from(vec![tunnel_segment.clone()]));
+            let input = |rules| inputs.build(&ford_segment, rules);
+
+            let rules = RouterRules {
+                ford: Some(HashMap::from([(
+                    "yes".to_string(),
+                    RulesTagValueAction::Priority { value: 50 },
+                )])),
+                tunnel: Some(HashMap::from([(
+                    "yes".to_string(),
+                    RulesTagValueAction::Avoid,
+                )])),
+                ..RouterRules::default()
+            };
+            assert_eq!(weight_rules_ford(input(&rules)), WeightCalcResult::UseWithWeight(50));
+            assert_eq!(weight_rules_tunnel(input(&rules)), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(weight_rules_bridge(input(&rules)), WeightCalcResult::UseWithWeight(0));
+
+            let route = Route::from(vec![tunnel_segment, ford_segment]);
+            let stats = route.calc_stats(&itinerary, &rules);
+            assert!(stats.bridge.is_empty());
+            assert_eq!(stats.tunnel.len(), 1);
+            assert_eq!(stats.ford.len(), 1);
+            assert_eq!(stats.tunnel["yes"].len_m + stats.ford["yes"].len_m, stats.len_m);
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_rules_lit_test() {
+            // 1 - lit - 2 - unlit - 3 - no lit tag - 4
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4])
+                .way(1, &[1, 2], &[("highway", "primary"), ("lit", "yes")])
+                .way(2, &[2, 3], &[("highway", "primary"), ("lit", "no")])
+                .way(3, &[3, 4], &[("highway", "primary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let lit_segment = get_route_segment(point(2), point(1));
+            let unlit_segment = get_route_segment(point(3), point(2));
+            let untagged_segment = get_route_segment(point(4), point(3));
+            assert_eq!(lit_segment.get_line().borrow().lit().unwrap(), "yes");
+            assert_eq!(untagged_segment.get_line().borrow().lit(), None);
+
+            let itinerary = Itinerary::new(point(1), point(4), Vec::new(), 0.);
+            let inputs = WeightCalcInputBuilder::new(itinerary.clone());
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules {
+                lit: Some(HashMap::from([
+                    (
+                        "yes".to_string(),
+                        RulesTagValueAction::Priority { value: 50 },
+                    ),
+                    ("no".to_string(), RulesTagValueAction::Avoid),
+                ])),
+                ..RouterRules::default()
+            };
+            assert_eq!(
+                weight_rules_lit(input(&lit_segment, &rules)),
+                WeightCalcResult::UseWithWeight(50)
+            );
+            assert_eq!(
+                weight_rules_lit(input(&unlit_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+            assert_eq!(
+                weight_rules_lit(input(&untagged_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let route = Route::from(vec![lit_segment, unlit_segment, untagged_segment]);
+            let stats = route.calc_stats(&itinerary, &rules);
+            assert_eq!(stats.lit.len(), 2);
+            assert!(stats.lit["yes"].len_m + stats.lit["no"].len_m < stats.len_m);
+        }
+    }
+    #[test]
+    fn distance_to_track() {
+        // 0.01 degrees of latitude is about 1112m
+        let track = CorridorTrack::new(&[(0., 0.), (0., 0.1)]);
+        assert!(track.get_distance_m(0., 0.05) < 1.);
+        assert!((track.get_distance_m(0.01, 0.05) - 1112.).abs() < 2.);
+        assert!((track.get_distance_m(0., -0.01) - 1112.).abs() < 2.);
+        let point = CorridorTrack::new(&[(0., 0.)]);
+        assert!((point.get_distance_m(0.01, 0.) - 1112.).abs() < 2.);
+        // away from the equator the east west distance follows the latitude of the track
+        let northern = CorridorTrack::new(&[(57., 24.), (57., 25.)]);
+        assert!((northern.get_distance_m(57., 25.01) - 606.).abs() < 2.);
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_corridor_test() {
+            // 1 - 2 about 1.1km north, finish 3 about 11km east of 1
+            let map_data = TestGraphBuilder::new()
+                .nodes_at(&[(1, 0., 0.), (2, 0.01, 0.), (3, 0., 0.1)])
+                .way(1, &[3, 1, 2], &[("highway", "primary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let segment = get_route_segment(point(2), point(1));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.));
+            let corridor = |enabled, width_m, reference_track| {
+                let mut rules = RouterRules::default();
+                rules.basic.corridor = BasicRuleCorridor {
+                    enabled,
+                    width_m,
+                    reference_track,
+                };
+                let input = inputs.build(&segment, &rules);
+                let projected = CorridorTrack::from_rules(&rules, input.itinerary);
+                let projected_result = weight_corridor(WeightCalcInput {
+                    corridor: projected.as_ref(),
+                    ..inputs.build(&segment, &rules)
+                });
+                let result = weight_corridor(input);
+                assert_eq!(result, projected_result);
+                result
+            };
+
+            assert_eq!(corridor(false, 500., None), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(corridor(true, 500., None), WeightCalcResult::DoNotUse);
+            assert_eq!(corridor(true, 2000., None), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(
+                corridor(true, 500., Some(vec![(0.01, 0.), (0.01, 0.1)])),
+                WeightCalcResult::UseWithWeight(0)
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_progress_speed_start_is_finish() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let itinerary = Itinerary::new(point(1), point(1), Vec::new(), 0.);
+            let segment = get_route_segment(point(2), point(1));
+            let fork_segment = get_route_segment(point(3), point(2));
+            let progress_speed = |route: &Route, check_steps_back| {
+                let mut rules = RouterRules::default();
+                rules.basic.progression_speed.enabled = true;
+                rules.basic.progression_speed.check_steps_back = check_steps_back;
+                let inputs = WeightCalcInputBuilder::new(itinerary.clone()).route(route.clone());
+                weight_progress_speed(inputs.build(&fork_segment, &rules))
+            };
+
+            let route = Route::from(vec![segment]);
+            assert_eq!(progress_speed(&Route::new(), 1), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(progress_speed(&route, 0), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(progress_speed(&route, 1), WeightCalcResult::UseWithWeight(0));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_penalize_destination_access_test() {
+            // 1 - 2, 2 - 3 access=destination, 2 - 4 access=destination motor_vehicle=yes
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4])
+                .way(1, &[1, 2], &[("highway", "primary")])
+                .way(
+                    2,
+                    &[2, 3],
+                    &[("highway", "residential"), ("access", "destination")],
+                )
+                .way(
+                    3,
+                    &[2, 4],
+                    &[
+                        ("highway", "residential"),
+                        ("access", "destination"),
+                        ("motor_vehicle", "yes"),
+                    ],
+                )
+                .build();
+            set_graph_static(map_data);
+            let point_1 = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let point_2 = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let point_3 = MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap();
+            let point_4 = MapDataGraph::get().test_get_point_ref_by_id(&4).unwrap();
+
+            let primary_segment = get_route_segment(point_2.clone(), point_1.clone());
+            let destination_segment = get_route_segment(point_3.clone(), point_2.clone());
+            let overridden_segment = get_route_segment(point_4.clone(), point_2.clone());
+            assert!(destination_segment.get_line().borrow().is_destination_access());
+            assert!(!overridden_segment.get_line().borrow().is_destination_access());
+
+            let inputs = WeightCalcInputBuilder::new(Itinerary::new(
+                point_1.clone(),
+                point_3.clone(),
+                Vec::new(),
+                0.,
+            ))
+            .route(Route::from(vec![primary_segment]));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules::default();
+            assert_eq!(
+                weight_penalize_destination_access(input(&destination_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            assert_eq!(
+                weight_penalize_destination_access(input(&overridden_segment, &rules)),
+                WeightCalcResult::UseWithWeight(rules.basic.destination_access.priority)
+            );
+
+            let mut rules = RouterRules::default();
+            rules.basic.destination_access.enabled = false;
+            assert_eq!(
+                weight_penalize_destination_access(input(&overridden_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_no_loops_policy() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            // 2 - 3 - 6 - 8 - 4 - 3, back at 3 after one loop
+            let ids = [2, 3, 6, 8, 4, 3];
+            let route = Route::from(
+                ids.windows(2)
+                    .map(|ids| get_route_segment(point(ids[1]), point(ids[0])))
+                    .collect::<Vec<_>>(),
+            );
+            let fork_segment = get_route_segment(point(5), point(3));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(2), point(5), Vec::new(), 0.))
+                    .route(route);
+            let weight = |loops| {
+                let rules = RouterRules {
+                    basic: BasicRules {
+                        loops,
+                        ..BasicRules::default()
+                    },
+                    ..RouterRules::default()
+                };
+                weight_no_loops(inputs.build(&fork_segment, &rules))
+            };
+
+            assert_eq!(weight(BasicRuleLoops::Forbid), WeightCalcResult::DoNotUse);
+            assert_eq!(
+                weight(BasicRuleLoops::AllowUpTo { count: 0 }),
+                WeightCalcResult::DoNotUse
+            );
+            assert_eq!(
+                weight(BasicRuleLoops::AllowUpTo { count: 1 }),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            assert_eq!(
+                weight(BasicRuleLoops::Unlimited),
+                WeightCalcResult::UseWithWeight(0)
+            );
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_prefer_numbered_roads_test() {
+            // 1 - A1 - 2 - 3
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3])
+                .way(1, &[1, 2], &[("highway", "primary"), ("ref", "A1")])
+                .way(2, &[2, 3], &[("highway", "unclassified")])
+                .build();
+            set_graph_static(map_data);
+            let point_1 = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let point_2 = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let point_3 = MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap();
+
+            let numbered_segment = get_route_segment(point_1.clone(), point_2.clone());
+            let unnumbered_segment = get_route_segment(point_3.clone(), point_2.clone());
+            let inputs = WeightCalcInputBuilder::new(Itinerary::new(
+                point_2.clone(),
+                point_3.clone(),
+                Vec::new(),
+                0.,
+            ));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules::default();
+            assert_eq!(
+                weight_prefer_numbered_roads(input(&numbered_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let mut rules = RouterRules::default();
+            rules.basic.prefer_numbered_roads.enabled = true;
+            assert_eq!(
+                weight_prefer_numbered_roads(input(&numbered_segment, &rules)),
+                WeightCalcResult::UseWithWeight(rules.basic.prefer_numbered_roads.priority)
+            );
+            assert_eq!(
+                weight_prefer_numbered_roads(input(&unnumbered_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_prefer_consistent_class_test() {
+            // 1 - primary - 2 - trunk - 3, 2 - primary_link - 4, 2 - residential - 5,
+            // 2 - road - 6
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4, 5, 6])
+                .way(1, &[1, 2], &[("highway", "primary")])
+                .way(2, &[2, 3], &[("highway", "trunk")])
+                .way(3, &[2, 4], &[("highway", "primary_link")])
+                .way(4, &[2, 5], &[("highway", "residential")])
+                .way(5, &[2, 6], &[("highway", "road")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.))
+                    .route(Route::from(vec![get_route_segment(point(2), point(1))]));
+            let weight = |to, rules: &RouterRules| {
+                weight_prefer_consistent_class(
+                    inputs.build(&get_route_segment(point(to), point(2)), rules),
+                )
+            };
+
+            let rules = RouterRules::default();
+            assert_eq!(weight(4, &rules), WeightCalcResult::UseWithWeight(0));
+
+            let mut rules = RouterRules::default();
+            rules.basic.prefer_consistent_class.enabled = true;
+            let priority = rules.basic.prefer_consistent_class.priority;
+            assert_eq!(weight(4, &rules), WeightCalcResult::UseWithWeight(priority));
+            assert_eq!(weight(3, &rules), WeightCalcResult::UseWithWeight(priority / 2));
+            assert_eq!(weight(5, &rules), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(weight(6, &rules), WeightCalcResult::UseWithWeight(0));
+        }
+
+        #[test]
+        fn weight_prefer_described_ways_test() {
+            // 1 - 2 and from 2 to 3 with surface and name, 4 with surface, 5 with neither
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4, 5])
+                .way(1, &[1, 2], &[("highway", "unclassified")])
+                .way(
+                    2,
+                    &[2, 3],
+                    &[
+                        ("highway", "unclassified"),
+                        ("surface", "gravel"),
+                        ("name", "Forest road"),
+                    ],
+                )
+                .way(3, &[2, 4], &[("highway", "unclassified"), ("surface", "gravel")])
+                .way(4, &[2, 5], &[("highway", "unclassified")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.))
+                    .route(Route::from(vec![get_route_segment(point(2), point(1))]));
+            let weight = |to, rules: &RouterRules| {
+                weight_prefer_described_ways(
+                    inputs.build(&get_route_segment(point(to), point(2)), rules),
+                )
+            };
+
+            let rules = RouterRules::default();
+            assert_eq!(weight(3, &rules), WeightCalcResult::UseWithWeight(0));
+
+            let mut rules = RouterRules::default();
+            rules.basic.prefer_described_ways.enabled = true;
+            rules.basic.prefer_described_ways.priority = 30;
+            assert_eq!(weight(3, &rules), WeightCalcResult::UseWithWeight(30));
+            assert_eq!(weight(4, &rules), WeightCalcResult::UseWithWeight(15));
+            assert_eq!(weight(5, &rules), WeightCalcResult::UseWithWeight(0));
+
+            rules.basic.prefer_described_ways.tags = vec!["surface".to_string()];
+            assert_eq!(weight(4, &rules), WeightCalcResult::UseWithWeight(30));
+        }
+
+        #[test]
+        fn weight_roundabout_preference_test() {
+            // 1 - 2 and a roundabout 2 -> 3 -> 4 -> 2, 2 - 5 with no roundabout
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4, 5])
+                .way(1, &[1, 2], &[("highway", "primary")])
+                .way(
+                    2,
+                    &[2, 3, 4, 2],
+                    &[("highway", "primary"), ("junction", "roundabout")],
+                )
+                .way(3, &[2, 5], &[("highway", "primary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let itinerary = Itinerary::new(point(1), point(5), Vec::new(), 0.);
+            let weight = |route: &Route, from, to, rules: &RouterRules| {
+                let inputs = WeightCalcInputBuilder::new(itinerary.clone()).route(route.clone());
+                weight_roundabout_preference(
+                    inputs.build(&get_route_segment(point(to), point(from)), rules),
+                )
+            };
+            let route = Route::from(vec![get_route_segment(point(2), point(1))]);
+
+            let rules = RouterRules::default();
+            assert_eq!(weight(&route, 2, 3, &rules), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(weight(&route, 2, 5, &rules), WeightCalcResult::UseWithWeight(0));
+
+            let mut rules = RouterRules::default();
+            rules.basic.roundabout_preference.action = BasicRuleRoundaboutAction::Avoid;
+            assert_eq!(weight(&route, 2, 3, &rules), WeightCalcResult::UseWithWeight(0));
+            assert_eq!(weight(&route, 2, 5, &rules), WeightCalcResult::UseWithWeight(40));
+
+            // staying on the roundabout is not another entry
+            let mut route = Route::from(vec![get_route_segment(point(2), point(1))]);
+            route.add_segment(get_route_segment(point(3), point(2)));
+            assert_eq!(weight(&route, 3, 4, &rules), WeightCalcResult::UseWithWeight(40));
+
+            route.add_segment(get_route_segment(point(4), point(3)));
+            route.add_segment(get_route_segment(point(2), point(4)));
+            route.add_segment(get_route_segment(point(5), point(2)));
+            let stats = route.calc_stats(&itinerary, &rules);
+            assert_eq!(stats.roundabout_count, 1);
+        }
+
+        #[test]
+        fn weight_avoid_hairpins_test() {
+            //     3   5
+            //     |  / \
+            //     | 4   6
+            //     |/
+            //     2
+            //     |
+            //     1
+            let map_data = TestGraphBuilder::new()
+                .nodes_at(&[
+                    (1, 0., 0.),
+                    (2, 0.001, 0.),
+                    (3, 0.002, 0.),
+                    (4, 0.0015, 0.0005),
+                    (5, 0.002, 0.001),
+                    (6, 0.0015, 0.0015),
+                ])
+                .way(1, &[1, 2, 3], &[("highway", "secondary")])
+                .way(2, &[2, 4, 5, 6], &[("highway", "secondary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let straight_segment = get_route_segment(point(3), point(2));
+            let hairpin_segment = get_route_segment(point(4), point(2));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.))
+                    .route(Route::from(vec![get_route_segment(point(2), point(1))]));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules::default();
+            assert_eq!(
+                weight_avoid_hairpins(input(&hairpin_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let mut rules = RouterRules::default();
+            rules.basic.avoid_hairpins.enabled = true;
+            rules.basic.avoid_hairpins.max_turn_deg = 120.;
+            rules.basic.avoid_hairpins.lookahead_m = 500.;
+            assert_eq!(
+                weight_avoid_hairpins(input(&straight_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            assert_eq!(
+                weight_avoid_hairpins(input(&hairpin_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+
+            let mut short_rules = rules.clone();
+            short_rules.basic.avoid_hairpins.lookahead_m = 50.;
+            assert_eq!(
+                weight_avoid_hairpins(input(&hairpin_segment, &short_rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_no_sharp_turns_test() {
+            //     3
+            //     |
+            //     2 - 4
+            //     | \
+            //     1   5
+            let map_data = TestGraphBuilder::new()
+                .nodes_at(&[
+                    (1, 0., 0.),
+                    (2, 0.001, 0.),
+                    (3, 0.002, 0.),
+                    (4, 0.001, 0.001),
+                    (5, 0.0002, 0.0008),
+                ])
+                .way(1, &[1, 2, 3], &[("highway", "secondary")])
+                .way(2, &[2, 4], &[("highway", "secondary")])
+                .way(3, &[2, 5], &[("highway", "secondary")])
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let straight_segment = get_route_segment(point(3), point(2));
+            let right_segment = get_route_segment(point(4), point(2));
+            let sharp_segment = get_route_segment(point(5), point(2));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.))
+                    .route(Route::from(vec![get_route_segment(point(2), point(1))]));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules::default();
+            assert_eq!(
+                weight_no_sharp_turns(input(&sharp_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let mut rules = RouterRules::default();
+            rules.basic.no_sharp_turns.enabled = true;
+            rules.basic.no_sharp_turns.under_deg = 60.;
+            assert_eq!(
+                weight_no_sharp_turns(input(&straight_segment, &rules)),
+                WeightCalcResult::UseWithWeight(60)
+            );
+            // 90 degrees is a quarter of the way from 60 to straight
+            assert_eq!(
+                weight_no_sharp_turns(input(&right_segment, &rules)),
+                WeightCalcResult::UseWithWeight(15)
+            );
+            assert_eq!(
+                weight_no_sharp_turns(input(&sharp_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+
+            let mut strict_rules = rules.clone();
+            strict_rules.basic.no_sharp_turns.under_deg = 120.;
+            assert_eq!(
+                weight_no_sharp_turns(input(&straight_segment, &strict_rules)),
+                WeightCalcResult::UseWithWeight(60)
+            );
+            assert_eq!(
+                weight_no_sharp_turns(input(&right_segment, &strict_rules)),
+                WeightCalcResult::DoNotUse
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_vehicle_dimensions_test() {
+            // 1 - 2 - 3 maxwidth=6'6" on 3 - 4, 4 forks to 6 maxheight=low and 7,
+            // 2 - 5 maxheight=3.2 maxweight=7.5
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4, 5, 6, 7])
+                .way(1, &[1, 2], &[("highway", "primary")])
+                .way(2, &[2, 3], &[("highway", "primary")])
+                .way(3, &[3, 4], &[("highway", "primary"), ("maxwidth", "6'6\"")])
+                .way(4, &[4, 6], &[("highway", "primary"), ("maxheight", "low")])
+                .way(5, &[4, 7], &[("highway", "primary")])
+                .way(
+                    6,
+                    &[2, 5],
+                    &[
+                        ("highway", "primary"),
+                        ("maxheight", "3.2 m"),
+                        ("maxweight", "7.5"),
+                    ],
+                )
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let height_weight_segment = get_route_segment(point(5), point(2));
+            let width_segment = get_route_segment(point(3), point(2));
+            let unparsed_segment = get_route_segment(point(6), point(4));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(7), Vec::new(), 0.));
+            let inputs_to_3 =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(3), Vec::new(), 0.));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let rules = RouterRules::default();
+            for segment in [&height_weight_segment, &width_segment, &unparsed_segment] {
+                assert_eq!(
+                    weight_vehicle_dimensions(input(segment, &rules)),
+                    WeightCalcResult::UseWithWeight(0)
+                );
+            }
+
+            let mut rules = RouterRules::default();
+            rules.vehicle_dimensions.height_m = Some(3.);
+            rules.vehicle_dimensions.width_m = Some(2.5);
+            assert_eq!(
+                weight_vehicle_dimensions(input(&height_weight_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            // the narrow way is behind point 3 that is not a fork
+            assert_eq!(
+                weight_vehicle_dimensions(input(&width_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+            // the branch ends at the finish before the narrow way
+            assert_eq!(
+                weight_vehicle_dimensions(inputs_to_3.build(&width_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            assert_eq!(
+                weight_vehicle_dimensions(input(&unparsed_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            let mut heavy_rules = RouterRules::default();
+            heavy_rules.vehicle_dimensions.height_m = Some(3.5);
+            heavy_rules.vehicle_dimensions.weight_t = Some(8.);
+            assert_eq!(
+                weight_vehicle_dimensions(input(&height_weight_segment, &heavy_rules)),
+                WeightCalcResult::DoNotUse
+            );
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_avoid_way_ids_test() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let way_1234_segment = get_route_segment(point(2), point(1));
+            let way_5367_segment = get_route_segment(point(6), point(3));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(6), Vec::new(), 0.));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            let mut rules = RouterRules::default();
+            rules.avoid_way_ids.insert(5367);
+            assert_eq!(
+                weight_avoid_way_ids(input(&way_1234_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+            assert_eq!(
+                weight_avoid_way_ids(input(&way_5367_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_avoid_way_ids_branch_test() {
+            // 1 - 2 - 3 - 4, 4 forks to 6 and 7, 2 - 5
+            let primary = [("highway", "primary")];
+            let map_data = TestGraphBuilder::new()
+                .nodes(&[1, 2, 3, 4, 5, 6, 7])
+                .way(1, &[1, 2], &primary)
+                .way(2, &[2, 3], &primary)
+                .way(3, &[3, 4], &primary)
+                .way(4, &[2, 5], &primary)
+                .way(5, &[4, 6], &primary)
+                .way(6, &[4, 7], &primary)
+                .build();
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+
+            let chain_segment = get_route_segment(point(3), point(2));
+            let dead_end_segment = get_route_segment(point(5), point(2));
+            let inputs =
+                WeightCalcInputBuilder::new(Itinerary::new(point(1), point(7), Vec::new(), 0.));
+            let input = |segment, rules| inputs.build(segment, rules);
+
+            // way 3 is reached through point 3 that is not a fork
+            let mut rules = RouterRules::default();
+            rules.avoid_way_ids.insert(3);
+            assert_eq!(
+                weight_avoid_way_ids(input(&chain_segment, &rules)),
+                WeightCalcResult::DoNotUse
+            );
+            assert_eq!(
+                weight_avoid_way_ids(input(&dead_end_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
+
+            // way 5 is past the next fork at 4
+            let mut rules = RouterRules::default();
+            rules.avoid_way_ids.insert(5);
+            assert_eq!(
+                weight_avoid_way_ids(input(&chain_segment, &rules)),
+                WeightCalcResult::UseWithWeight(0)
+            );
         }
     }
 }
@@ -1,20 +1,46 @@
-use std::{num::ParseFloatError, path::PathBuf, string::ParseError, sync::OnceLock, time::Instant};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    num::{ParseFloatError, ParseIntError},
+    path::{Path, PathBuf},
+    string::ParseError,
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use clap::Parser;
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use tracing::{info, trace, warn};
 
 use crate::{
+    geo_utils::{self, BoundingBox},
+    gpx_writer::{GpxInterpolation, GpxOptions},
+    http_handler::{HttpHandler, HttpHandlerError},
     ipc_handler::{
-        CoordsMessage, IpcHandler, IpcHandlerError, ResponseMessage, RouteMessage, RouterResult,
+        CoordsMessage, IpcHandler, IpcHandlerError, RequestMessage, ResponseMessage, RouteMessage,
+        RouterResult, WaypointMessage,
+    },
+    map_data::{
+        graph::{MapDataGraph, MapDataPointRef},
+        poi::PoiCapture,
+        proximity::{PointSearchError, DEFAULT_SEARCH_RINGS},
+    },
+    map_data_cache::{CacheMode, MapDataCache, MapDataCacheError},
+    osm_data_reader::{DataSource, OsmDataReader, OsmDataReaderError},
+    polyline_writer::POLYLINE_PRECISION,
+    result_writer::{DataDestination, OutputFormat, ResultWriter, ResultWriterError},
+    router::{
+        generator::{Generator, GeneratorResult, ITINERARY_VARIATION_DISTANCES},
+        itinerary::Itinerary,
+        navigator::ForkExplanation,
+        route::{file::RouteFileError, Route},
+        rules::{
+            RouterRules, RulesError, RulesPreset, RulesRouteSort, RulesTagValueAction, RulesWeight,
+        },
     },
-    map_data::graph::MapDataGraph,
-    map_data_cache::{MapDataCache, MapDataCacheError},
-    osm_data_reader::DataSource,
-    result_writer::{DataDestination, ResultWriter, ResultWriterError},
-    router::{generator::Generator, route::Route, rules::RouterRules},
 };
 
-use clap::Subcommand;
+use clap::{Args, Subcommand};
 
 #[derive(Debug)]
 pub enum RouterRunnerError {
@@ -24,23 +50,77 @@ pub enum RouterRunnerError {
     OutputFileFormatIncorrect {
         filename: PathBuf,
     },
+    OutputFileMissing {
+        formats: Vec<OutputFormat>,
+    },
     Coords {
         name: String,
         cause: String,
         error: Option<ParseFloatError>,
     },
+    NodeId {
+        name: String,
+        cause: String,
+        error: Option<ParseIntError>,
+    },
     Ipc {
         error: IpcHandlerError,
     },
+    Http {
+        error: HttpHandlerError,
+    },
     PointNotFound {
         point: String,
     },
+    PointFilteredByRules {
+        point: String,
+        filtered: usize,
+    },
+    NoRoadNearby {
+        point: String,
+        lat: f32,
+        lon: f32,
+        nearest_m: f32,
+    },
+    NodeNotFound {
+        point: String,
+        node_id: u64,
+    },
+    BboxFromNodeId {
+        point: String,
+        node_id: u64,
+    },
     ResultWrite {
         error: ResultWriterError,
     },
     CacheWrite {
         error: MapDataCacheError,
     },
+    MapDataRead {
+        error: OsmDataReaderError,
+    },
+    RouteSave {
+        error: RouteFileError,
+    },
+    ExplainWrite {
+        error: io::Error,
+    },
+    ExplainSerialize {
+        error: serde_json::Error,
+    },
+    Rules {
+        errors: Vec<RulesError>,
+    },
+    BatchFileRead {
+        error: io::Error,
+    },
+    BatchFileParse {
+        error: serde_json::Error,
+    },
+    BatchJobsFailed {
+        failed: usize,
+        total: usize,
+    },
 }
 
 #[derive(Parser)]
@@ -49,6 +129,196 @@ pub enum RouterRunnerError {
 struct Cli {
     #[command(subcommand)]
     pub mode: CliMode,
+
+    /// Threads for reading the map data, the cache and route generation, all cores when
+    /// not set
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Serve a page on this address, like `127.0.0.1:8080`, that shows the routes while
+    /// they are generated
+    #[cfg(feature = "live-debug")]
+    #[arg(long, global = true, value_name = "ADDR")]
+    live_debug: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RulesArgs {
+    #[arg(long, value_name = "FILE")]
+    rule_file: Option<PathBuf>,
+
+    /// Start from a preset, values in the rule file override it
+    #[arg(long, value_enum)]
+    preset: Option<RulesPreset>,
+
+    /// Avoid these highway values, comma separated, overrides the rule file
+    #[arg(long, value_name = "VALUES", value_delimiter = ',')]
+    exclude_highway: Vec<String>,
+
+    /// Avoid these surface values, comma separated, overrides the rule file
+    #[arg(long, value_name = "VALUES", value_delimiter = ',')]
+    exclude_surface: Vec<String>,
+
+    /// Avoid these smoothness values, comma separated, overrides the rule file
+    #[arg(long, value_name = "VALUES", value_delimiter = ',')]
+    exclude_smoothness: Vec<String>,
+
+    /// Order of the routes in the output, overrides the rule file
+    #[arg(long, value_enum)]
+    sort_routes: Option<RulesRouteSort>,
+}
+
+impl RulesArgs {
+    fn get_exclude_rules(&self) -> RouterRules {
+        fn avoid(values: &[String]) -> Option<HashMap<String, RulesTagValueAction>> {
+            if values.is_empty() {
+                return None;
+            }
+            Some(
+                values
+                    .iter()
+                    .map(|value| (value.clone(), RulesTagValueAction::Avoid))
+                    .collect(),
+            )
+        }
+        RouterRules {
+            highway: avoid(&self.exclude_highway),
+            surface: avoid(&self.exclude_surface),
+            smoothness: avoid(&self.exclude_smoothness),
+            sort_routes: self.sort_routes,
+            ..RouterRules::default()
+        }
+    }
+
+    /// Merge the flags into `rules`, a weight list that is set gets the weights of the
+    /// exclude flags that were given so the flags are not ignored
+    fn apply_to(&self, rules: RouterRules) -> RouterRules {
+        let mut rules = rules.merge(self.get_exclude_rules());
+        if let Some(weights) = &mut rules.weights {
+            for (values, weight) in [
+                (&self.exclude_highway, RulesWeight::RulesHighway),
+                (&self.exclude_surface, RulesWeight::RulesSurface),
+                (&self.exclude_smoothness, RulesWeight::RulesSmoothness),
+            ] {
+                if !values.is_empty() && !weights.contains(&weight) {
+                    info!("Adding {:?} to the weights for the exclude flags", weight);
+                    weights.push(weight);
+                }
+            }
+        }
+        rules
+    }
+
+    fn read_rules(&self) -> Result<RouterRules, RulesError> {
+        let rules = self.apply_to(RouterRules::read(self.rule_file.clone(), self.preset)?);
+        trace!(rules = debug(&rules), "Rules");
+        Ok(rules)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GpxArgs {
+    /// Add GPX points this many metres apart along segments longer than
+    /// `--gpx-interpolate-min-len`, so that long straight segments follow the great circle
+    #[arg(long, value_name = "M")]
+    gpx_interpolate: Option<f32>,
+
+    /// Only segments longer than this many metres get points added
+    #[arg(long, value_name = "M", default_value_t = 5000.)]
+    gpx_interpolate_min_len: f32,
+
+    /// Write only the best route to the GPX files and leave out the CSV summary, the best is
+    /// the first by `sort_routes` from the rules, or the shortest when it is not set. Reversed
+    /// routes are never the best
+    #[arg(long)]
+    gpx_best_only: bool,
+
+    /// Add the itinerary waypoints each route was steered through as GPX waypoints
+    #[arg(long)]
+    gpx_itinerary_waypoints: bool,
+}
+
+impl GpxArgs {
+    fn get_options(&self, rules: &RouterRules) -> GpxOptions {
+        GpxOptions {
+            interpolation: self.gpx_interpolate.map(|spacing_m| GpxInterpolation {
+                spacing_m,
+                min_segment_m: self.gpx_interpolate_min_len,
+            }),
+            best_by: self
+                .gpx_best_only
+                .then(|| rules.sort_routes.unwrap_or(RulesRouteSort::Length)),
+            itinerary_waypoints: self.gpx_itinerary_waypoints,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MapDataArgs {
+    /// Fail when more ways than this reference nodes that are missing from the input
+    #[arg(long, value_name = "COUNT")]
+    max_skipped_ways: Option<usize>,
+
+    /// Store nodes with these tags as POIs, comma separated `key=value` or `key` for any
+    /// value, e.g. `amenity=fuel,tourism=viewpoint`. Only read from PBF and JSON input. The
+    /// POIs are kept in the map data and the cache, routing does not use them yet
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    capture_pois: Vec<String>,
+
+    /// Log the point, line and junction counts, bounding box and highway values of the
+    /// loaded map data
+    #[arg(long)]
+    graph_info: bool,
+
+    /// How `--cache-dir` is used, `off` to run without it and `rebuild` to replace it with
+    /// freshly read map data. The `cache` command always rebuilds
+    #[arg(long, value_enum, default_value_t)]
+    cache_mode: CacheMode,
+
+    /// Same as `--cache-mode off`
+    #[arg(long, conflicts_with_all = ["cache_mode", "rebuild_cache"])]
+    no_cache: bool,
+
+    /// Same as `--cache-mode rebuild`
+    #[arg(long, conflicts_with = "cache_mode")]
+    rebuild_cache: bool,
+}
+
+impl MapDataArgs {
+    fn get_reader(&self, data_source: &DataSource) -> OsmDataReader {
+        OsmDataReader::new(data_source.clone())
+            .max_skipped_ways(self.max_skipped_ways)
+            .capture_pois(
+                self.capture_pois
+                    .iter()
+                    .map(|capture| PoiCapture::parse(capture))
+                    .collect(),
+            )
+    }
+
+    fn get_cache_mode(&self) -> CacheMode {
+        if self.no_cache {
+            CacheMode::Off
+        } else if self.rebuild_cache {
+            CacheMode::Rebuild
+        } else {
+            self.cache_mode
+        }
+    }
+
+    /// The options that change what is read into the graph, a cache is only reused when
+    /// they match
+    fn get_cache_identity(&self) -> String {
+        let mut captures = self.capture_pois.clone();
+        captures.sort();
+        format!("capture_pois={}", captures.join(","))
+    }
+
+    fn log_graph_info(&self) -> () {
+        if self.graph_info {
+            info!("Graph info {}", MapDataGraph::get().stats());
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -59,6 +329,9 @@ enum CliMode {
 
         #[arg(long, value_name = "FILE")]
         cache_dir: PathBuf,
+
+        #[command(flatten)]
+        map_data_args: MapDataArgs,
     },
     Server {
         #[arg(long, value_name = "FILE")]
@@ -69,50 +342,227 @@ enum CliMode {
 
         #[arg(long, value_name = "NAME")]
         socket_name: Option<String>,
+
+        #[command(flatten)]
+        map_data_args: MapDataArgs,
+    },
+    /// Serve `POST /route` requests over HTTP, the body is JSON with `start`, `finish` and
+    /// optionally `rules` and `reversed`, the response is the JSON of the router result
+    HttpServer {
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+
+        #[arg(long, value_name = "FILE")]
+        cache_dir: Option<PathBuf>,
+
+        /// Address to listen on, like `127.0.0.1:8080`
+        #[arg(long, value_name = "ADDR")]
+        address: String,
+
+        #[command(flatten)]
+        map_data_args: MapDataArgs,
+    },
+    /// Load the map data once and generate the routes for every job in a JSON file, see
+    /// `BatchJob` for the fields of a job
+    Batch {
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+
+        #[arg(long, value_name = "FILE")]
+        cache_dir: Option<PathBuf>,
+
+        /// JSON array of jobs, each with `start`, `finish`, `output` and optionally `rules`,
+        /// `reversed` and `format`
+        #[arg(long, value_name = "FILE")]
+        jobs: PathBuf,
+
+        #[command(flatten)]
+        gpx_args: GpxArgs,
+
+        #[command(flatten)]
+        map_data_args: MapDataArgs,
     },
     Client {
+        /// Output path, `{timestamp}`, `{start_id}`, `{finish_id}` and `{index}` are replaced
+        /// with the run time, the start and finish node ids or coordinates and the lowest
+        /// index that does not overwrite an existing output
         #[arg(long, value_name = "FILE")]
         output: Option<PathBuf>,
 
-        #[arg(long, value_name = "COORDINATES")]
-        start: String,
+        /// Directory for relative output paths, created when missing
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        /// Write these formats, comma separated, to the output path with the extension of
+        /// each format. When not set the format is taken from the output extension
+        #[arg(long, value_enum, value_delimiter = ',')]
+        format: Vec<OutputFormat>,
 
-        #[arg(long, value_name = "COORDINATES")]
-        finish: String,
+        #[command(flatten)]
+        gpx_args: GpxArgs,
+
+        /// Start as `lat,lon` or an exact OSM node as `node:<id>`
+        #[arg(
+            long,
+            value_name = "COORDINATES",
+            required_unless_present_any = ["validate_rules", "jobs"]
+        )]
+        start: Option<String>,
+
+        /// Finish as `lat,lon` or an exact OSM node as `node:<id>`
+        #[arg(
+            long,
+            value_name = "COORDINATES",
+            required_unless_present_any = ["validate_rules", "jobs"]
+        )]
+        finish: Option<String>,
 
         #[arg(long, value_name = "NAME")]
         socket_name: Option<String>,
 
-        #[arg(long, value_name = "FILE")]
-        rule_file: Option<PathBuf>,
+        #[command(flatten)]
+        rules_args: RulesArgs,
+
+        /// Also output each route travelled in the opposite direction
+        #[arg(long)]
+        reversed: bool,
+
+        /// Only read and validate the rules, then exit
+        #[arg(long)]
+        validate_rules: bool,
+
+        /// Send every job of this file, like the jobs of `batch`, to the server in one request
+        /// instead of a single start and finish. The exclude and sort flags apply to the rules
+        /// of each job
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["start", "finish", "output", "output_dir", "format", "reversed", "rule_file", "preset"]
+        )]
+        jobs: Option<PathBuf>,
     },
     Dual {
-        #[arg(long, value_name = "FILE")]
-        input: PathBuf,
+        #[arg(long, value_name = "FILE", required_unless_present = "validate_rules")]
+        input: Option<PathBuf>,
 
         #[arg(long, value_name = "FILE")]
         cache_dir: Option<PathBuf>,
 
+        /// Output path, `{timestamp}`, `{start_id}`, `{finish_id}` and `{index}` are replaced
+        /// with the run time, the start and finish node ids or coordinates and the lowest
+        /// index that does not overwrite an existing output
         #[arg(long, value_name = "FILE")]
         output: Option<PathBuf>,
 
-        #[arg(long, value_name = "COORDINATES")]
-        start: String,
+        /// Directory for relative output paths, created when missing
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        /// Write these formats, comma separated, to the output path with the extension of
+        /// each format. When not set the format is taken from the output extension
+        #[arg(long, value_enum, value_delimiter = ',')]
+        format: Vec<OutputFormat>,
+
+        #[command(flatten)]
+        gpx_args: GpxArgs,
+
+        /// Start as `lat,lon` or an exact OSM node as `node:<id>`
+        #[arg(
+            long,
+            value_name = "COORDINATES",
+            required_unless_present = "validate_rules"
+        )]
+        start: Option<String>,
+
+        /// Finish as `lat,lon` or an exact OSM node as `node:<id>`
+        #[arg(
+            long,
+            value_name = "COORDINATES",
+            required_unless_present = "validate_rules"
+        )]
+        finish: Option<String>,
+
+        #[command(flatten)]
+        rules_args: RulesArgs,
 
-        #[arg(long, value_name = "COORDINATES")]
-        finish: String,
+        /// Also output each route travelled in the opposite direction
+        #[arg(long)]
+        reversed: bool,
 
+        /// Only read and validate the rules, then exit
+        #[arg(long)]
+        validate_rules: bool,
+
+        /// Store each itinerary result here and reuse existing results to resume a run
+        #[arg(long, value_name = "DIR")]
+        checkpoint_dir: Option<PathBuf>,
+
+        /// Also save each route to this dir as `route_<n>.json` in the route file format
+        #[arg(long, value_name = "DIR")]
+        save_routes: Option<PathBuf>,
+
+        /// Write the weight calc results at each fork of the generated routes to this file as
+        /// JSON, routes are in the order of `--save-routes`
         #[arg(long, value_name = "FILE")]
-        rule_file: Option<PathBuf>,
+        explain: Option<PathBuf>,
+
+        /// Only read the map data within this many km of the start, the finish and the
+        /// waypoints generated around them. Start and finish have to be coordinates and the
+        /// cache is not used
+        #[arg(long, value_name = "KM")]
+        bbox_from_route: Option<f32>,
+
+        #[command(flatten)]
+        map_data_args: MapDataArgs,
     },
 }
 
+/// Written by `--explain`, the results at each fork are in the order of `weights`
+#[derive(Debug, Serialize)]
+struct RouteExplanations {
+    weights: Vec<RulesWeight>,
+    routes: Vec<RouteExplanation>,
+}
+
+#[derive(Debug, Serialize)]
+struct RouteExplanation {
+    itinerary_id: String,
+    /// Keyed by the OSM id of the fork point
+    forks: BTreeMap<u64, ForkExplanation>,
+}
+
+/// One route of `batch` mode, `start` and `finish` are like in the HTTP request body and the
+/// formats are taken from the `output` extension when `format` is empty
+#[derive(Debug, Deserialize)]
+struct BatchJob {
+    start: StartFinishPoint,
+    finish: StartFinishPoint,
+    output: PathBuf,
+    #[serde(default)]
+    format: Vec<OutputFormat>,
+    #[serde(default)]
+    rules: RouterRules,
+    #[serde(default)]
+    reversed: bool,
+}
+
+fn read_batch_jobs(file: &Path) -> Result<Vec<BatchJob>, RouterRunnerError> {
+    let contents = fs::read(file).map_err(|error| RouterRunnerError::BatchFileRead { error })?;
+    serde_json::from_slice(&contents).map_err(|error| RouterRunnerError::BatchFileParse { error })
+}
+
+/// Start or finish of a route, either coordinates snapped to the closest point or an exact
+/// OSM node id
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum StartFinishPoint {
+    Coords { lat: f32, lon: f32 },
+    NodeId { id: u64 },
+}
+
 #[derive(Debug)]
 pub struct StartFinish {
-    pub start_lat: f32,
-    pub start_lon: f32,
-    pub finish_lat: f32,
-    pub finish_lon: f32,
+    pub start: StartFinishPoint,
+    pub finish: StartFinishPoint,
 }
 
 #[derive(Debug)]
@@ -120,24 +570,57 @@ pub enum RouterMode {
     Cache {
         data_source: DataSource,
         cache_dir: PathBuf,
+        map_data_args: MapDataArgs,
     },
     Server {
         data_source: DataSource,
         cache_dir: Option<PathBuf>,
         socket_name: Option<String>,
+        map_data_args: MapDataArgs,
+    },
+    HttpServer {
+        data_source: DataSource,
+        cache_dir: Option<PathBuf>,
+        address: String,
+        map_data_args: MapDataArgs,
+    },
+    Batch {
+        data_source: DataSource,
+        cache_dir: Option<PathBuf>,
+        jobs: PathBuf,
+        gpx_args: GpxArgs,
+        map_data_args: MapDataArgs,
     },
     Client {
         start_finish: StartFinish,
-        data_destination: DataDestination,
+        data_destinations: Vec<DataDestination>,
+        gpx_args: GpxArgs,
         socket_name: Option<String>,
-        rule_file: Option<PathBuf>,
+        rules_args: RulesArgs,
+        reversed: bool,
+    },
+    ClientBatch {
+        jobs: PathBuf,
+        gpx_args: GpxArgs,
+        socket_name: Option<String>,
+        rules_args: RulesArgs,
     },
     Dual {
         data_source: DataSource,
         cache_dir: Option<PathBuf>,
         start_finish: StartFinish,
-        data_destination: DataDestination,
-        rule_file: Option<PathBuf>,
+        data_destinations: Vec<DataDestination>,
+        gpx_args: GpxArgs,
+        rules_args: RulesArgs,
+        reversed: bool,
+        checkpoint_dir: Option<PathBuf>,
+        save_routes: Option<PathBuf>,
+        explain: Option<PathBuf>,
+        bbox: Option<BoundingBox>,
+        map_data_args: MapDataArgs,
+    },
+    ValidateRules {
+        rules_args: RulesArgs,
     },
 }
 
@@ -148,54 +631,161 @@ pub struct RouterRunner {
 impl RouterRunner {
     pub fn init() -> Self {
         let cli = Cli::parse();
+        if let Some(threads) = cli.threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .expect("could not build thread pool");
+        }
+        #[cfg(feature = "live-debug")]
+        if let Some(addr) = &cli.live_debug {
+            crate::live_debug::start(addr).expect("could not start live debug server");
+        }
         let mode = match cli.mode {
-            CliMode::Cache { input, cache_dir } => RouterMode::Cache {
+            CliMode::Cache {
+                input,
+                cache_dir,
+                map_data_args,
+            } => RouterMode::Cache {
                 data_source: get_data_source(input).expect("could not get data source"),
                 cache_dir,
+                map_data_args,
             },
             CliMode::Server {
                 input,
                 cache_dir,
                 socket_name,
+                map_data_args,
             } => RouterMode::Server {
                 data_source: get_data_source(input).expect("could not get data source"),
                 cache_dir,
                 socket_name,
+                map_data_args,
+            },
+            CliMode::HttpServer {
+                input,
+                cache_dir,
+                address,
+                map_data_args,
+            } => RouterMode::HttpServer {
+                data_source: get_data_source(input).expect("could not get data source"),
+                cache_dir,
+                address,
+                map_data_args,
+            },
+            CliMode::Batch {
+                input,
+                cache_dir,
+                jobs,
+                gpx_args,
+                map_data_args,
+            } => RouterMode::Batch {
+                data_source: get_data_source(input).expect("could not get data source"),
+                cache_dir,
+                jobs,
+                gpx_args,
+                map_data_args,
             },
             CliMode::Client {
                 output,
+                output_dir,
+                format,
+                gpx_args,
                 start,
                 finish,
                 socket_name,
-                rule_file,
+                rules_args,
+                reversed,
+                validate_rules,
+                jobs,
             } => {
-                let start_finish = get_start_finish(start, finish)
-                    .expect("could not get start/finish coordinates");
+                if validate_rules {
+                    return Self {
+                        mode: RouterMode::ValidateRules { rules_args },
+                    };
+                }
+                if let Some(jobs) = jobs {
+                    return Self {
+                        mode: RouterMode::ClientBatch {
+                            jobs,
+                            gpx_args,
+                            socket_name,
+                            rules_args,
+                        },
+                    };
+                }
+                let start_finish = get_start_finish(
+                    start.expect("start is required without --validate-rules or --jobs"),
+                    finish.expect("finish is required without --validate-rules or --jobs"),
+                )
+                .expect("could not get start/finish coordinates");
+                let data_destinations = get_data_destinations(
+                    get_output_path(output, output_dir, &format, &start_finish),
+                    format,
+                )
+                .expect("could not get data destination");
                 RouterMode::Client {
                     start_finish,
-                    data_destination: get_data_destination(output)
-                        .expect("could not get data destination"),
+                    data_destinations,
+                    gpx_args,
                     socket_name,
-                    rule_file,
+                    rules_args,
+                    reversed,
                 }
             }
             CliMode::Dual {
                 input,
                 cache_dir,
                 output,
+                output_dir,
+                format,
+                gpx_args,
                 start,
                 finish,
-                rule_file,
+                rules_args,
+                reversed,
+                validate_rules,
+                checkpoint_dir,
+                save_routes,
+                explain,
+                bbox_from_route,
+                map_data_args,
             } => {
-                let start_finish = get_start_finish(start, finish)
-                    .expect("could not get start/finish coordinates");
+                if validate_rules {
+                    return Self {
+                        mode: RouterMode::ValidateRules { rules_args },
+                    };
+                }
+                let start_finish = get_start_finish(
+                    start.expect("start is required without --validate-rules"),
+                    finish.expect("finish is required without --validate-rules"),
+                )
+                .expect("could not get start/finish coordinates");
+                let data_destinations = get_data_destinations(
+                    get_output_path(output, output_dir, &format, &start_finish),
+                    format,
+                )
+                .expect("could not get data destination");
+                let bbox = bbox_from_route.map(|buffer_km| {
+                    get_route_bbox(&start_finish, buffer_km)
+                        .expect("could not get the bounding box of the route")
+                });
                 RouterMode::Dual {
-                    data_source: get_data_source(input).expect("could not get data source"),
+                    checkpoint_dir,
+                    save_routes,
+                    explain,
+                    bbox,
+                    map_data_args,
+                    data_source: get_data_source(
+                        input.expect("input is required without --validate-rules"),
+                    )
+                    .expect("could not get data source"),
                     cache_dir,
                     start_finish,
-                    data_destination: get_data_destination(output)
-                        .expect("could not get data destination"),
-                    rule_file,
+                    data_destinations,
+                    gpx_args,
+                    rules_args,
+                    reversed,
                 }
             }
         };
@@ -206,27 +796,232 @@ impl RouterRunner {
     #[tracing::instrument(skip_all)]
     fn generate_route(
         start_finish: &StartFinish,
-        rules: RouterRules,
-    ) -> Result<Vec<Route>, RouterRunnerError> {
-        let start = MapDataGraph::get()
-            .get_closest_to_coords(start_finish.start_lat, start_finish.start_lon)
-            .ok_or(RouterRunnerError::PointNotFound {
-                point: "Start point".to_string(),
-            })?;
+        rules: &RouterRules,
+        checkpoint_dir: Option<PathBuf>,
+        explain: bool,
+    ) -> Result<GeneratorResult, RouterRunnerError> {
+        let start = RouterRunner::get_point_ref(&start_finish.start, "Start point", rules)?;
 
         info!("Start point {start}");
 
-        let finish = MapDataGraph::get()
-            .get_closest_to_coords(start_finish.finish_lat, start_finish.finish_lon)
-            .ok_or(RouterRunnerError::PointNotFound {
-                point: "Finish point".to_string(),
-            })?;
+        let finish = RouterRunner::get_point_ref(&start_finish.finish, "Finish point", rules)?;
 
         info!("Finish point {finish}");
 
-        let route_generator = Generator::new(start.clone(), finish.clone(), rules);
-        let routes = route_generator.generate_routes();
-        Ok(routes)
+        let route_generator = Generator::new(start.clone(), finish.clone(), rules.clone())
+            .checkpoint_dir(checkpoint_dir)
+            .explain(explain);
+        Ok(route_generator.generate_routes())
+    }
+
+    /// A point is usable as start or finish when at least one of its lines is not avoided
+    /// by the highway, surface or smoothness rules
+    fn point_allowed_by_rules(point: &MapDataPointRef, rules: &RouterRules) -> bool {
+        fn is_avoided(
+            rule: &Option<HashMap<String, RulesTagValueAction>>,
+            value: Option<&smartstring::alias::String>,
+        ) -> bool {
+            match (rule, value) {
+                (Some(rule), Some(value)) => {
+                    matches!(rule.get(value.as_str()), Some(RulesTagValueAction::Avoid))
+                }
+                _ => false,
+            }
+        }
+        point.borrow().lines.iter().any(|line| {
+            let line = line.borrow();
+            let tags = line.tags.borrow();
+            !is_avoided(&rules.highway, tags.highway())
+                && !is_avoided(&rules.surface, tags.surface())
+                && !is_avoided(&rules.smoothness, tags.smoothness())
+        })
+    }
+
+    fn get_point_ref(
+        point: &StartFinishPoint,
+        name: &str,
+        rules: &RouterRules,
+    ) -> Result<MapDataPointRef, RouterRunnerError> {
+        match point {
+            StartFinishPoint::Coords { lat, lon } => MapDataGraph::get()
+                .get_closest_to_coords_filtered(*lat, *lon, DEFAULT_SEARCH_RINGS, |point| {
+                    RouterRunner::point_allowed_by_rules(point, rules)
+                })
+                .map_err(|error| match error {
+                    PointSearchError::NoPointsNearby => RouterRunnerError::PointNotFound {
+                        point: name.to_string(),
+                    },
+                    PointSearchError::AllPointsFiltered { filtered } => {
+                        RouterRunnerError::PointFilteredByRules {
+                            point: name.to_string(),
+                            filtered,
+                        }
+                    }
+                })
+                .and_then(|point| {
+                    let nearest_m =
+                        geo_utils::distance((*lat, *lon), (point.borrow().lat, point.borrow().lon));
+                    if nearest_m > rules.get_max_snap_distance_m() {
+                        return Err(RouterRunnerError::NoRoadNearby {
+                            point: name.to_string(),
+                            lat: *lat,
+                            lon: *lon,
+                            nearest_m,
+                        });
+                    }
+                    Ok(point)
+                }),
+            StartFinishPoint::NodeId { id } => MapDataGraph::get()
+                .get_point_ref_by_osm_id(*id)
+                .ok_or(RouterRunnerError::NodeNotFound {
+                    point: name.to_string(),
+                    node_id: *id,
+                }),
+        }
+    }
+
+    fn get_route_message(
+        route: &Route,
+        itinerary: &Itinerary,
+        rules: &RouterRules,
+        reversed: bool,
+    ) -> RouteMessage {
+        let stats = route.calc_stats(itinerary, rules);
+        if stats.retraced_len_m > 0. {
+            warn!(
+                "Route travels {:.0}m on lines it already used",
+                stats.retraced_len_m
+            );
+        }
+        RouteMessage {
+            coords: route
+                .get_points()
+                .iter()
+                .map(|point| CoordsMessage {
+                    lat: point.borrow().lat,
+                    lon: point.borrow().lon,
+                })
+                .collect::<Vec<CoordsMessage>>(),
+            stats,
+            reversed,
+            instructions: route.to_instructions(),
+            way_ids: route.get_way_ids(),
+            profile: route.profile(),
+            refuel_points: rules.refuel_range_km.map_or(Vec::new(), |range_km| {
+                route.get_refuel_points(range_km as f64 * 1000.)
+            }),
+            simplified_coords: rules
+                .simplify_tolerance_m
+                .map_or(Vec::new(), |tolerance_m| {
+                    route
+                        .simplify(tolerance_m as f64)
+                        .into_iter()
+                        .map(|(lat, lon)| CoordsMessage { lat, lon })
+                        .collect()
+                }),
+            itinerary_waypoints: itinerary
+                .get_waypoints()
+                .iter()
+                .map(|waypoint| WaypointMessage {
+                    lat: waypoint.borrow().lat,
+                    lon: waypoint.borrow().lon,
+                    reached: itinerary.get_visited_waypoints().contains(waypoint),
+                })
+                .collect(),
+            polyline: route.to_polyline(POLYLINE_PRECISION),
+        }
+    }
+
+    fn get_router_result(
+        route_result: Result<GeneratorResult, RouterRunnerError>,
+        rules: &RouterRules,
+        reversed: bool,
+    ) -> RouterResult {
+        route_result.map_or_else(
+            |error| RouterResult::Error {
+                message: format!("Error generating route {:?}", error),
+            },
+            |result| RouterResult::Ok {
+                routes: RouterRunner::sort_route_messages(
+                    result
+                        .routes
+                        .iter()
+                        .map(|generated| {
+                            let mut messages = vec![RouterRunner::get_route_message(
+                                &generated.route,
+                                &generated.itinerary,
+                                rules,
+                                false,
+                            )];
+                            if reversed {
+                                match generated.route.reverse() {
+                                    Ok(route) => messages.push(RouterRunner::get_route_message(
+                                        &route,
+                                        &generated.itinerary,
+                                        rules,
+                                        true,
+                                    )),
+                                    Err(error) => warn!("Route can not be reversed {:?}", error),
+                                }
+                            }
+                            messages
+                        })
+                        .collect(),
+                    rules.sort_routes,
+                ),
+                outcomes: result.outcomes,
+            },
+        )
+    }
+
+    /// Sorts by the first message of each group, a reversed route stays right after the route
+    fn sort_route_messages(
+        mut groups: Vec<Vec<RouteMessage>>,
+        sort: Option<RulesRouteSort>,
+    ) -> Vec<RouteMessage> {
+        if let Some(sort) = sort {
+            let key = |group: &Vec<RouteMessage>| {
+                group
+                    .first()
+                    .map_or(0., |message| message.stats.get_sort_key(sort))
+            };
+            groups.sort_by(|a, b| key(a).total_cmp(&key(b)));
+        }
+        groups.into_iter().flatten().collect()
+    }
+
+    fn save_routes(dir: &Path, result: &GeneratorResult) -> Result<(), RouterRunnerError> {
+        fs::create_dir_all(dir).map_err(|error| RouterRunnerError::RouteSave {
+            error: RouteFileError::FileWrite { error },
+        })?;
+        for (idx, generated) in result.routes.iter().enumerate() {
+            generated
+                .route
+                .save(&dir.join(format!("route_{idx}.json")))
+                .map_err(|error| RouterRunnerError::RouteSave { error })?;
+        }
+        Ok(())
+    }
+
+    fn save_explanations(
+        file: &Path,
+        result: &GeneratorResult,
+        rules: &RouterRules,
+    ) -> Result<(), RouterRunnerError> {
+        let explanations = RouteExplanations {
+            weights: rules.get_weights(),
+            routes: result
+                .routes
+                .iter()
+                .map(|generated| RouteExplanation {
+                    itinerary_id: generated.itinerary.id(),
+                    forks: generated.fork_explanations.clone(),
+                })
+                .collect(),
+        };
+        let contents = serde_json::to_string_pretty(&explanations)
+            .map_err(|error| RouterRunnerError::ExplainSerialize { error })?;
+        fs::write(file, contents).map_err(|error| RouterRunnerError::ExplainWrite { error })
     }
 
     #[tracing::instrument(skip_all)]
@@ -235,55 +1030,43 @@ impl RouterRunner {
         data_source: &DataSource,
         cache_dir: Option<PathBuf>,
         start_finish: &StartFinish,
-        data_destination: &DataDestination,
-        rule_file: Option<PathBuf>,
+        data_destinations: &[DataDestination],
+        gpx_args: &GpxArgs,
+        rules: RouterRules,
+        reversed: bool,
+        checkpoint_dir: Option<PathBuf>,
+        save_routes: Option<&Path>,
+        explain: Option<&Path>,
+        bbox: Option<BoundingBox>,
+        map_data_args: &MapDataArgs,
     ) -> Result<(), RouterRunnerError> {
-        let rules = RouterRules::read(rule_file).expect("Failed to read rules");
-        let mut data_cache = MapDataCache::init(cache_dir);
-        let cached_map_data = data_cache.read_cache();
-        let cached_map_data = match cached_map_data {
-            Ok(d) => d,
-            Err(error) => {
-                tracing::error!("Failed to process cache: {:?}", error);
+        RouterRunner::check_rules(&rules)?;
+        let cache_dir = match (bbox, cache_dir) {
+            (Some(_), Some(cache_dir)) => {
+                warn!(
+                    "Not using the cache in {} as only the map data around the route is read",
+                    cache_dir.display()
+                );
                 None
             }
+            (_, cache_dir) => cache_dir,
         };
-        if let Some(packed_data) = cached_map_data {
-            MapDataGraph::unpack(packed_data);
-        } else {
-            MapDataGraph::init(data_source);
-            let packed_data = MapDataGraph::get().pack();
-            if let Err(error) = data_cache.write_cache(packed_data) {
-                tracing::error!("Failed to write cache: {:?}", error);
-            }
+        RouterRunner::load_map_data(data_source, cache_dir, bbox, map_data_args)?;
+        let route_result =
+            RouterRunner::generate_route(start_finish, &rules, checkpoint_dir, explain.is_some());
+        if let (Some(dir), Ok(result)) = (save_routes, &route_result) {
+            RouterRunner::save_routes(dir, result)?;
+        }
+        if let (Some(file), Ok(result)) = (explain, &route_result) {
+            RouterRunner::save_explanations(file, result, &rules)?;
         }
-        let route_result = RouterRunner::generate_route(start_finish, rules);
-        ResultWriter::write(
-            data_destination.clone(),
+        ResultWriter::write_all(
+            data_destinations,
             ResponseMessage {
                 id: "oo".to_string(),
-                result: route_result.map_or_else(
-                    |error| RouterResult::Error {
-                        message: format!("Error generating route {:?}", error),
-                    },
-                    |routes| RouterResult::Ok {
-                        routes: routes
-                            .iter()
-                            .map(|route| RouteMessage {
-                                coords: route
-                                    .clone()
-                                    .into_iter()
-                                    .map(|segment| CoordsMessage {
-                                        lat: segment.get_end_point().borrow().lat,
-                                        lon: segment.get_end_point().borrow().lon,
-                                    })
-                                    .collect::<Vec<CoordsMessage>>(),
-                                stats: route.calc_stats(),
-                            })
-                            .collect(),
-                    },
-                ),
+                result: RouterRunner::get_router_result(route_result, &rules, reversed),
             },
+            gpx_args.get_options(&rules),
         )
         .map_err(|error| RouterRunnerError::ResultWrite { error })?;
         Ok(())
@@ -294,11 +1077,16 @@ impl RouterRunner {
         &self,
         data_source: &DataSource,
         cache_dir: PathBuf,
+        map_data_args: &MapDataArgs,
     ) -> Result<(), RouterRunnerError> {
         let startup_start = Instant::now();
 
-        let data_cache = MapDataCache::init(Some(cache_dir));
-        MapDataGraph::init(data_source);
+        let data_cache = MapDataCache::init(Some(cache_dir))
+            .mode(CacheMode::Rebuild)
+            .identity(map_data_args.get_cache_identity());
+        MapDataGraph::init(map_data_args.get_reader(data_source))
+            .map_err(|error| RouterRunnerError::MapDataRead { error })?;
+        map_data_args.log_graph_info();
         let packed_data = MapDataGraph::get().pack();
         data_cache
             .write_cache(packed_data)
@@ -316,10 +1104,149 @@ impl RouterRunner {
         data_source: &DataSource,
         cache_dir: Option<PathBuf>,
         socket_name: Option<String>,
+        map_data_args: &MapDataArgs,
     ) -> Result<(), RouterRunnerError> {
         let startup_start = Instant::now();
 
-        let mut data_cache = MapDataCache::init(cache_dir);
+        RouterRunner::load_map_data(data_source, cache_dir, None, map_data_args)?;
+
+        let startup_end = startup_start.elapsed();
+        info!("startup took {}s", startup_end.as_secs());
+
+        let ipc =
+            IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
+        dbg!("ipc init done");
+        ipc.listen(RouterRunner::handle_request)
+            .map_err(|error| RouterRunnerError::Ipc { error })?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn run_http_server(
+        &self,
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        address: &str,
+        map_data_args: &MapDataArgs,
+    ) -> Result<(), RouterRunnerError> {
+        let startup_start = Instant::now();
+
+        RouterRunner::load_map_data(data_source, cache_dir, None, map_data_args)?;
+
+        let startup_end = startup_start.elapsed();
+        info!("startup took {}s", startup_end.as_secs());
+
+        let http = HttpHandler::init(address).map_err(|error| RouterRunnerError::Http { error })?;
+        http.listen(|request_message| {
+            if let Err(error) = RouterRunner::check_rules(&request_message.rules) {
+                return ResponseMessage {
+                    id: request_message.id,
+                    result: RouterRunner::get_router_result(
+                        Err(error),
+                        &request_message.rules,
+                        request_message.reversed,
+                    ),
+                };
+            }
+            RouterRunner::handle_request(request_message)
+        })
+        .map_err(|error| RouterRunnerError::Http { error })?;
+        Ok(())
+    }
+
+    /// Run the jobs one after the other against the graph that is loaded once, a failed job
+    /// is logged and the rest still run
+    #[tracing::instrument(skip(self))]
+    fn run_batch(
+        &self,
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        jobs_file: &Path,
+        gpx_args: &GpxArgs,
+        map_data_args: &MapDataArgs,
+    ) -> Result<(), RouterRunnerError> {
+        let jobs = read_batch_jobs(jobs_file)?;
+        RouterRunner::load_map_data(data_source, cache_dir, None, map_data_args)?;
+
+        let total = jobs.len();
+        let mut failed = 0;
+        for (idx, job) in jobs.into_iter().enumerate() {
+            let job_start = Instant::now();
+            if let Err(error) = RouterRunner::run_batch_job(idx, job, gpx_args) {
+                tracing::error!("Batch job {idx} failed: {:?}", error);
+                failed += 1;
+                continue;
+            }
+            info!("Batch job {idx} took {}s", job_start.elapsed().as_secs());
+        }
+        info!("Batch finished {} of {total} jobs", total - failed);
+        if failed > 0 {
+            return Err(RouterRunnerError::BatchJobsFailed { failed, total });
+        }
+        Ok(())
+    }
+
+    fn run_batch_job(
+        idx: usize,
+        job: BatchJob,
+        gpx_args: &GpxArgs,
+    ) -> Result<(), RouterRunnerError> {
+        RouterRunner::check_rules(&job.rules)?;
+        let data_destinations = get_data_destinations(Some(job.output), job.format)?;
+        let route_result = RouterRunner::generate_route(
+            &StartFinish {
+                start: job.start,
+                finish: job.finish,
+            },
+            &job.rules,
+            None,
+            false,
+        );
+        ResultWriter::write_all(
+            &data_destinations,
+            ResponseMessage {
+                id: format!("batch-{idx}"),
+                result: RouterRunner::get_router_result(route_result, &job.rules, job.reversed),
+            },
+            gpx_args.get_options(&job.rules),
+        )
+        .map_err(|error| RouterRunnerError::ResultWrite { error })
+    }
+
+    /// Generate the routes for one request, every request gets its own generator so
+    /// concurrent requests only share the read only map data
+    fn handle_request(request_message: RequestMessage) -> ResponseMessage {
+        let route_res = RouterRunner::generate_route(
+            &StartFinish {
+                start: request_message.start,
+                finish: request_message.finish,
+            },
+            &request_message.rules,
+            None,
+            false,
+        );
+
+        ResponseMessage {
+            id: request_message.id,
+            result: RouterRunner::get_router_result(
+                route_res,
+                &request_message.rules,
+                request_message.reversed,
+            ),
+        }
+    }
+
+    /// Unpack the map data from the cache, or read it from the data source and write the
+    /// cache when there is no usable cache. `bbox` limits what is read from the data source
+    fn load_map_data(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        bbox: Option<BoundingBox>,
+        map_data_args: &MapDataArgs,
+    ) -> Result<(), RouterRunnerError> {
+        let mut data_cache = MapDataCache::init(cache_dir)
+            .mode(map_data_args.get_cache_mode())
+            .identity(map_data_args.get_cache_identity());
         let cached_map_data = data_cache.read_cache();
         let cached_map_data = match cached_map_data {
             Ok(d) => d,
@@ -331,56 +1258,14 @@ impl RouterRunner {
         if let Some(packed_data) = cached_map_data {
             MapDataGraph::unpack(packed_data);
         } else {
-            MapDataGraph::init(data_source);
+            MapDataGraph::init(map_data_args.get_reader(data_source).bbox(bbox))
+                .map_err(|error| RouterRunnerError::MapDataRead { error })?;
             let packed_data = MapDataGraph::get().pack();
             if let Err(error) = data_cache.write_cache(packed_data) {
                 tracing::error!("Failed to write cache: {:?}", error);
             }
         }
-
-        let startup_end = startup_start.elapsed();
-        info!("startup took {}s", startup_end.as_secs());
-
-        let ipc =
-            IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
-        dbg!("ipc init done");
-        ipc.listen(|request_message| {
-            let route_res = RouterRunner::generate_route(
-                &StartFinish {
-                    start_lat: request_message.start.lat,
-                    start_lon: request_message.start.lon,
-                    finish_lat: request_message.finish.lat,
-                    finish_lon: request_message.finish.lon,
-                },
-                request_message.rules,
-            );
-
-            ResponseMessage {
-                id: request_message.id,
-                result: route_res.map_or_else(
-                    |error| RouterResult::Error {
-                        message: format!("Error generating route {:?}", error),
-                    },
-                    |routes| RouterResult::Ok {
-                        routes: routes
-                            .iter()
-                            .map(|route| RouteMessage {
-                                coords: route
-                                    .clone()
-                                    .into_iter()
-                                    .map(|segment| CoordsMessage {
-                                        lat: segment.get_end_point().borrow().lat,
-                                        lon: segment.get_end_point().borrow().lon,
-                                    })
-                                    .collect::<Vec<CoordsMessage>>(),
-                                stats: route.calc_stats(),
-                            })
-                            .collect(),
-                    },
-                ),
-            }
-        })
-        .map_err(|error| RouterRunnerError::Ipc { error })?;
+        map_data_args.log_graph_info();
         Ok(())
     }
 
@@ -388,21 +1273,86 @@ impl RouterRunner {
     fn run_client(
         &self,
         start_finish: &StartFinish,
-        data_destination: &DataDestination,
+        data_destinations: &[DataDestination],
+        gpx_args: &GpxArgs,
         socket_name: Option<String>,
-        rule_file: Option<PathBuf>,
+        rules_args: &RulesArgs,
+        reversed: bool,
     ) -> Result<(), RouterRunnerError> {
-        let rules = RouterRules::read(rule_file).expect("could not read rules");
+        let rules = rules_args.read_rules().expect("could not read rules");
+        RouterRunner::check_rules(&rules)?;
+        let gpx_options = gpx_args.get_options(&rules);
         let ipc =
             IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
         let response = ipc
-            .connect(start_finish, rules)
+            .connect(start_finish, rules, reversed)
             .map_err(|error| RouterRunnerError::Ipc { error })?;
-        ResultWriter::write(data_destination.clone(), response)
+        ResultWriter::write_all(data_destinations, response, gpx_options)
             .map_err(|error| RouterRunnerError::ResultWrite { error })?;
         Ok(())
     }
 
+    /// Send all jobs to the server in one batch request, the results are only written when
+    /// the rules and outputs of every job are valid
+    fn run_client_batch(
+        &self,
+        jobs_file: &Path,
+        gpx_args: &GpxArgs,
+        socket_name: Option<String>,
+        rules_args: &RulesArgs,
+    ) -> Result<(), RouterRunnerError> {
+        let mut requests = Vec::new();
+        let mut outputs = Vec::new();
+        for (idx, job) in read_batch_jobs(jobs_file)?.into_iter().enumerate() {
+            let rules = rules_args.apply_to(job.rules);
+            RouterRunner::check_rules(&rules)?;
+            outputs.push((
+                get_data_destinations(Some(job.output), job.format)?,
+                gpx_args.get_options(&rules),
+            ));
+            requests.push(RequestMessage {
+                id: format!("batch-{idx}"),
+                start: job.start,
+                finish: job.finish,
+                rules,
+                reversed: job.reversed,
+            });
+        }
+        let ipc =
+            IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
+        let responses = ipc
+            .connect_batch(requests)
+            .map_err(|error| RouterRunnerError::Ipc { error })?;
+        for ((data_destinations, gpx_options), response) in outputs.iter().zip(responses) {
+            ResultWriter::write_all(data_destinations, response, *gpx_options)
+                .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+        }
+        Ok(())
+    }
+
+    fn check_rules(rules: &RouterRules) -> Result<(), RouterRunnerError> {
+        let errors = rules.validate();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        for error in &errors {
+            tracing::error!("Invalid rules: {:?}", error);
+        }
+        Err(RouterRunnerError::Rules { errors })
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn run_validate_rules(&self, rules_args: &RulesArgs) -> Result<(), RouterRunnerError> {
+        let rules = rules_args
+            .read_rules()
+            .map_err(|error| RouterRunnerError::Rules {
+                errors: vec![error],
+            })?;
+        RouterRunner::check_rules(&rules)?;
+        info!("Rules are valid");
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn run(&self) -> Result<(), RouterRunnerError> {
         match &self.mode {
@@ -410,92 +1360,159 @@ impl RouterRunner {
                 start_finish,
                 data_source,
                 cache_dir,
-                data_destination,
-                rule_file,
+                data_destinations,
+                gpx_args,
+                rules_args,
+                reversed,
+                checkpoint_dir,
+                save_routes,
+                explain,
+                bbox,
+                map_data_args,
             } => self.run_dual(
                 &data_source,
                 cache_dir.clone(),
                 &start_finish,
-                &data_destination,
-                rule_file.clone(),
+                data_destinations,
+                gpx_args,
+                rules_args.read_rules().expect("Failed to read rules"),
+                *reversed,
+                checkpoint_dir.clone(),
+                save_routes.as_deref(),
+                explain.as_deref(),
+                *bbox,
+                map_data_args,
             ),
             RouterMode::Cache {
                 data_source,
                 cache_dir,
-            } => self.run_cache(data_source, cache_dir.clone()),
+                map_data_args,
+            } => self.run_cache(data_source, cache_dir.clone(), map_data_args),
             RouterMode::Server {
                 data_source,
                 cache_dir,
                 socket_name,
-            } => self.run_server(&data_source, cache_dir.clone(), socket_name.clone()),
+                map_data_args,
+            } => self.run_server(
+                &data_source,
+                cache_dir.clone(),
+                socket_name.clone(),
+                map_data_args,
+            ),
+            RouterMode::HttpServer {
+                data_source,
+                cache_dir,
+                address,
+                map_data_args,
+            } => self.run_http_server(data_source, cache_dir.clone(), address, map_data_args),
+            RouterMode::Batch {
+                data_source,
+                cache_dir,
+                jobs,
+                gpx_args,
+                map_data_args,
+            } => self.run_batch(
+                data_source,
+                cache_dir.clone(),
+                jobs,
+                gpx_args,
+                map_data_args,
+            ),
             RouterMode::Client {
                 start_finish,
-                data_destination,
+                data_destinations,
+                gpx_args,
                 socket_name,
-                rule_file,
+                rules_args,
+                reversed,
             } => self.run_client(
                 &start_finish,
-                &data_destination,
+                data_destinations,
+                gpx_args,
                 socket_name.clone(),
-                rule_file.clone(),
+                rules_args,
+                *reversed,
             ),
+            RouterMode::ClientBatch {
+                jobs,
+                gpx_args,
+                socket_name,
+                rules_args,
+            } => self.run_client_batch(jobs, gpx_args, socket_name.clone(), rules_args),
+            RouterMode::ValidateRules { rules_args } => self.run_validate_rules(rules_args),
         }
     }
 }
 
+/// Bounding box around the start, the finish and the waypoints the generator places around
+/// them, extended by `buffer_km`
+fn get_route_bbox(
+    start_finish: &StartFinish,
+    buffer_km: f32,
+) -> Result<BoundingBox, RouterRunnerError> {
+    let coords = |point: &StartFinishPoint, name: &str| match point {
+        StartFinishPoint::Coords { lat, lon } => Ok((*lat, *lon)),
+        StartFinishPoint::NodeId { id } => Err(RouterRunnerError::BboxFromNodeId {
+            point: name.to_string(),
+            node_id: *id,
+        }),
+    };
+    let points = [
+        coords(&start_finish.start, "Start point")?,
+        coords(&start_finish.finish, "Finish point")?,
+    ];
+    let waypoint_distance_m = ITINERARY_VARIATION_DISTANCES
+        .iter()
+        .fold(0., |max: f32, distance| max.max(*distance));
+    Ok(
+        BoundingBox::around_points(&points, waypoint_distance_m + buffer_km * 1000.)
+            .expect("start and finish make a bounding box"),
+    )
+}
+
 fn get_start_finish(start: String, finish: String) -> Result<StartFinish, RouterRunnerError> {
-    let mut start = start.split(",");
-    let mut finish = finish.split(",");
     Ok(StartFinish {
-        start_lat: start
-            .next()
-            .ok_or_else(|| RouterRunnerError::Coords {
-                name: "Start LAT".to_string(),
-                cause: "missing".to_string(),
-                error: None,
-            })?
-            .parse()
-            .map_err(|error| RouterRunnerError::Coords {
-                name: "Start LAT".to_string(),
-                cause: "not parsable as f64".to_string(),
-                error: Some(error),
-            })?,
-        start_lon: start
-            .next()
-            .ok_or_else(|| RouterRunnerError::Coords {
-                name: "Start LON".to_string(),
-                cause: "missing".to_string(),
-                error: None,
-            })?
-            .parse()
-            .map_err(|error| RouterRunnerError::Coords {
-                name: "Start Lon".to_string(),
-                cause: "not parsable as f64".to_string(),
+        start: get_start_finish_point(&start, "Start")?,
+        finish: get_start_finish_point(&finish, "Finish")?,
+    })
+}
+
+/// Parse either `lat,lon` coordinates or `node:<osm node id>`
+fn get_start_finish_point(value: &str, name: &str) -> Result<StartFinishPoint, RouterRunnerError> {
+    if let Some(id) = value.strip_prefix("node:") {
+        return Ok(StartFinishPoint::NodeId {
+            id: id.parse().map_err(|error| RouterRunnerError::NodeId {
+                name: format!("{name} NODE"),
+                cause: "not parsable as u64".to_string(),
                 error: Some(error),
             })?,
-        finish_lat: finish
+        });
+    }
+    let mut coords = value.split(",");
+    Ok(StartFinishPoint::Coords {
+        lat: coords
             .next()
             .ok_or_else(|| RouterRunnerError::Coords {
-                name: "Finish LAT".to_string(),
+                name: format!("{name} LAT"),
                 cause: "missing".to_string(),
                 error: None,
             })?
             .parse()
             .map_err(|error| RouterRunnerError::Coords {
-                name: "Finish LAT".to_string(),
+                name: format!("{name} LAT"),
                 cause: "not parsable as f64".to_string(),
                 error: Some(error),
             })?,
-        finish_lon: finish
+        lon: coords
             .next()
             .ok_or_else(|| RouterRunnerError::Coords {
-                name: "Finish LON".to_string(),
+                name: format!("{name} LON"),
                 cause: "missing".to_string(),
                 error: None,
             })?
             .parse()
             .map_err(|error| RouterRunnerError::Coords {
-                name: "Finish LON".to_string(),
+                name: format!("{name} LON"),
                 cause: "not parsable as f64".to_string(),
                 error: Some(error),
             })?,
@@ -507,10 +1524,69 @@ fn get_data_source(file: PathBuf) -> Result<DataSource, RouterRunnerError> {
             return Ok(DataSource::JsonFile { file });
         } else if ext == "pbf" {
             return Ok(DataSource::PbfFile { file });
+        } else if ext == "osm" || ext == "xml" {
+            return Ok(DataSource::OsmXmlFile { file });
         }
     }
     Err(RouterRunnerError::InputFileFormatIncorrect { filename: file })
 }
+/// Expand the `--output` template and place relative paths in `output_dir`
+fn get_output_path(
+    output: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    formats: &[OutputFormat],
+    start_finish: &StartFinish,
+) -> Option<PathBuf> {
+    fn point_id(point: &StartFinishPoint) -> String {
+        match point {
+            StartFinishPoint::NodeId { id } => id.to_string(),
+            StartFinishPoint::Coords { lat, lon } => format!("{lat}_{lon}"),
+        }
+    }
+    let output = output?;
+    let output = match output_dir {
+        Some(output_dir) if output.is_relative() => output_dir.join(output),
+        _ => output,
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let template = output
+        .to_string_lossy()
+        .replace("{timestamp}", &timestamp.to_string())
+        .replace("{start_id}", &point_id(&start_finish.start))
+        .replace("{finish_id}", &point_id(&start_finish.finish));
+    if !template.contains("{index}") {
+        return Some(PathBuf::from(template));
+    }
+    (0..)
+        .map(|idx| PathBuf::from(template.replace("{index}", &idx.to_string())))
+        .find(|output| {
+            get_data_destinations(Some(output.clone()), formats.to_vec())
+                .ok()
+                .is_none_or(|dests| {
+                    dests
+                        .iter()
+                        .filter_map(|dest| dest.get_file())
+                        .all(|file| !file.exists())
+                })
+        })
+}
+fn get_data_destinations(
+    output: Option<PathBuf>,
+    formats: Vec<OutputFormat>,
+) -> Result<Vec<DataDestination>, RouterRunnerError> {
+    if formats.is_empty() {
+        return Ok(vec![get_data_destination(output)?]);
+    }
+    match output {
+        None => Err(RouterRunnerError::OutputFileMissing { formats }),
+        Some(output) => Ok(formats
+            .iter()
+            .map(|format| format.get_data_destination(&output))
+            .collect()),
+    }
+}
 fn get_data_destination(output: Option<PathBuf>) -> Result<DataDestination, RouterRunnerError> {
     if let Some(output) = output {
         if let Some(ext) = output.extension() {
@@ -518,6 +1594,8 @@ fn get_data_destination(output: Option<PathBuf>) -> Result<DataDestination, Rout
                 return Ok(DataDestination::Json { file: output });
             } else if ext == "gpx" {
                 return Ok(DataDestination::Gpx { file: output });
+            } else if ext == "txt" {
+                return Ok(DataDestination::Polyline { file: output });
             }
         }
         return Err(RouterRunnerError::OutputFileFormatIncorrect { filename: output });
@@ -525,3 +1603,392 @@ fn get_data_destination(output: Option<PathBuf>) -> Result<DataDestination, Rout
 
     Ok(DataDestination::Stdout)
 }
+
+#[cfg(test)]
+mod test {
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1};
+
+    #[test]
+    fn exclude_flags_add_weights() {
+        let args = RulesArgs {
+            rule_file: None,
+            preset: None,
+            exclude_highway: vec!["motorway".to_string()],
+            exclude_surface: Vec::new(),
+            exclude_smoothness: vec!["bad".to_string()],
+            sort_routes: None,
+        };
+
+        let rules = args.apply_to(RouterRules {
+            weights: Some(vec![RulesWeight::Heading, RulesWeight::RulesSmoothness]),
+            ..RouterRules::default()
+        });
+        assert_eq!(
+            rules.weights,
+            Some(vec![
+                RulesWeight::Heading,
+                RulesWeight::RulesSmoothness,
+                RulesWeight::RulesHighway
+            ])
+        );
+        assert!(matches!(
+            rules.highway.unwrap().get("motorway"),
+            Some(RulesTagValueAction::Avoid)
+        ));
+
+        assert_eq!(args.apply_to(RouterRules::default()).weights, None);
+    }
+
+    #[test]
+    fn validate_rules_args() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(["ridi-router"].iter().chain(args)).map(|cli| cli.mode)
+        };
+
+        for mode in ["client", "dual"] {
+            assert!(matches!(
+                parse(&[mode, "--validate-rules", "--rule-file", "rules.json"]),
+                Ok(CliMode::Client { .. } | CliMode::Dual { .. })
+            ));
+            assert!(parse(&[mode, "--rule-file", "rules.json"]).is_err());
+        }
+        assert!(parse(&["dual", "--start", "57,24", "--finish", "57,25"]).is_err());
+        assert!(matches!(
+            parse(&[
+                "client",
+                "--jobs",
+                "jobs.json",
+                "--exclude-highway",
+                "motorway"
+            ]),
+            Ok(CliMode::Client { jobs: Some(_), .. })
+        ));
+        assert!(parse(&["client", "--jobs", "jobs.json", "--start", "57,24"]).is_err());
+    }
+
+    #[test]
+    fn cache_mode_args() {
+        let cache_mode = |args: &[&str]| {
+            Cli::try_parse_from(
+                ["ridi-router", "server", "--input", "map.pbf"]
+                    .iter()
+                    .chain(args),
+            )
+            .map(|cli| match cli.mode {
+                CliMode::Server { map_data_args, .. } => map_data_args.get_cache_mode(),
+                _ => panic!("not the server mode"),
+            })
+        };
+
+        assert_eq!(cache_mode(&[]).unwrap(), CacheMode::ReadWrite);
+        assert_eq!(
+            cache_mode(&["--cache-mode", "write"]).unwrap(),
+            CacheMode::Write
+        );
+        assert_eq!(cache_mode(&["--no-cache"]).unwrap(), CacheMode::Off);
+        assert_eq!(
+            cache_mode(&["--rebuild-cache"]).unwrap(),
+            CacheMode::Rebuild
+        );
+        assert!(cache_mode(&["--no-cache", "--rebuild-cache"]).is_err());
+        assert!(cache_mode(&["--no-cache", "--cache-mode", "read"]).is_err());
+    }
+
+    #[test]
+    fn start_finish_point_parse() {
+        assert_eq!(
+            get_start_finish_point("57.1,24.5", "Start").unwrap(),
+            StartFinishPoint::Coords {
+                lat: 57.1,
+                lon: 24.5
+            }
+        );
+        assert_eq!(
+            get_start_finish_point("node:123456", "Start").unwrap(),
+            StartFinishPoint::NodeId { id: 123456 }
+        );
+        assert!(matches!(
+            get_start_finish_point("node:abc", "Start"),
+            Err(RouterRunnerError::NodeId { .. })
+        ));
+        assert!(matches!(
+            get_start_finish_point("57.1", "Start"),
+            Err(RouterRunnerError::Coords { .. })
+        ));
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn point_ref_snap_distance() {
+            let mut map_data = graph_from_test_dataset(test_dataset_1());
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let rules = RouterRules::default();
+            let point_ref = |lat, lon, rules: &RouterRules| {
+                RouterRunner::get_point_ref(&StartFinishPoint::Coords { lat, lon }, "Start", rules)
+            };
+
+            assert_eq!(point_ref(1.001, 1.001, &rules).unwrap().borrow().id, 1);
+            // about 2.2km north of point 1
+            assert!(matches!(
+                point_ref(1.02, 1., &rules),
+                Err(RouterRunnerError::NoRoadNearby { nearest_m, .. })
+                    if (nearest_m - 2224.).abs() < 10.
+            ));
+            let rules = RouterRules {
+                max_snap_distance_m: Some(3000.),
+                ..RouterRules::default()
+            };
+            assert_eq!(point_ref(1.02, 1., &rules).unwrap().borrow().id, 1);
+        }
+        #[test]
+        fn route_message_polyline() {
+            use crate::router::route::segment::Segment;
+
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let (line, next_point) = MapDataGraph::get()
+                .get_adjacent(point(1))
+                .into_iter()
+                .find(|(_, next_point)| next_point.borrow().id == 2)
+                .unwrap();
+            let route = Route::from(vec![Segment::new(line, next_point)]);
+            let itinerary = Itinerary::new(point(1), point(2), Vec::new(), 10.);
+
+            let message =
+                RouterRunner::get_route_message(&route, &itinerary, &RouterRules::default(), false);
+            // start point and the segment end point
+            assert_eq!(message.polyline, "_ibE_ibE_ibE_ibE");
+            assert_eq!(message.polyline, route.to_polyline(POLYLINE_PRECISION));
+        }
+    }
+
+    #[test]
+    fn data_destinations_from_formats() {
+        let output = Some(PathBuf::from("out/routes.gpx"));
+        assert!(matches!(
+            get_data_destinations(output.clone(), Vec::new()).unwrap()[..],
+            [DataDestination::Gpx { .. }]
+        ));
+
+        let destinations = get_data_destinations(
+            output.clone(),
+            vec![OutputFormat::Json, OutputFormat::Polyline],
+        )
+        .unwrap();
+        assert!(matches!(
+            &destinations[..],
+            [DataDestination::Json { file: json }, DataDestination::Polyline { file: txt }]
+                if json == &PathBuf::from("out/routes.json")
+                    && txt == &PathBuf::from("out/routes.txt")
+        ));
+
+        assert!(matches!(
+            &get_data_destinations(output.clone(), vec![OutputFormat::Gpx, OutputFormat::GpxTrack])
+                .unwrap()[..],
+            [DataDestination::Gpx { file: routes }, DataDestination::GpxTrack { file: tracks }]
+                if routes == &PathBuf::from("out/routes.gpx")
+                    && tracks == &PathBuf::from("out/routes.track.gpx")
+        ));
+
+        assert!(matches!(
+            get_data_destinations(None, vec![OutputFormat::Gpx]),
+            Err(RouterRunnerError::OutputFileMissing { .. })
+        ));
+    }
+    #[test]
+    fn route_bbox_covers_waypoints() {
+        let start_finish = StartFinish {
+            start: StartFinishPoint::Coords { lat: 57., lon: 24. },
+            finish: StartFinishPoint::Coords {
+                lat: 57.2,
+                lon: 24.1,
+            },
+        };
+        let bbox = get_route_bbox(&start_finish, 5.).unwrap();
+        // waypoints are up to 20km away, plus the 5km buffer
+        assert!((bbox.max_lat - 57.2 - 25. / 111.2).abs() < 0.01);
+        assert!((57. - bbox.min_lat - 25. / 111.2).abs() < 0.01);
+        assert!(bbox.contains(57.1, 24.4));
+        assert!(!bbox.contains(57.1, 24.6));
+
+        assert!(matches!(
+            get_route_bbox(
+                &StartFinish {
+                    start: StartFinishPoint::NodeId { id: 12 },
+                    ..start_finish
+                },
+                5.
+            ),
+            Err(RouterRunnerError::BboxFromNodeId { node_id: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn route_messages_sorted() {
+        use crate::router::route::RouteStats;
+
+        let message = |len_m, direction_change_ratio, reversed| RouteMessage {
+            stats: RouteStats {
+                len_m,
+                direction_change_ratio,
+                ..RouteStats::default()
+            },
+            reversed,
+            ..RouteMessage::default()
+        };
+        let groups = vec![
+            vec![message(300., 0.1, false), message(300., 0.2, true)],
+            vec![message(100., 0.05, false)],
+            vec![message(200., 0.3, false), message(200., 0.3, true)],
+        ];
+        let order = |sort| {
+            RouterRunner::sort_route_messages(groups.clone(), sort)
+                .iter()
+                .map(|message| (message.stats.len_m, message.reversed))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            order(None),
+            vec![
+                (300., false),
+                (300., true),
+                (100., false),
+                (200., false),
+                (200., true)
+            ]
+        );
+        assert_eq!(
+            order(Some(RulesRouteSort::Length)),
+            vec![
+                (100., false),
+                (200., false),
+                (200., true),
+                (300., false),
+                (300., true)
+            ]
+        );
+        assert_eq!(
+            order(Some(RulesRouteSort::Curviness)),
+            vec![
+                (200., false),
+                (200., true),
+                (300., false),
+                (300., true),
+                (100., false)
+            ]
+        );
+    }
+
+    #[test]
+    fn output_path_template() {
+        let start_finish = StartFinish {
+            start: StartFinishPoint::NodeId { id: 12 },
+            finish: StartFinishPoint::Coords {
+                lat: 57.5,
+                lon: 24.25,
+            },
+        };
+        assert_eq!(
+            get_output_path(
+                Some(PathBuf::from("{start_id}-{finish_id}.gpx")),
+                Some(PathBuf::from("out")),
+                &[],
+                &start_finish
+            ),
+            Some(PathBuf::from("out/12-57.5_24.25.gpx"))
+        );
+        assert_eq!(
+            get_output_path(
+                Some(PathBuf::from("/tmp/routes.gpx")),
+                Some(PathBuf::from("out")),
+                &[],
+                &start_finish
+            ),
+            Some(PathBuf::from("/tmp/routes.gpx"))
+        );
+        assert_eq!(get_output_path(None, None, &[], &start_finish), None);
+        let timestamped = get_output_path(
+            Some(PathBuf::from("routes-{timestamp}.gpx")),
+            None,
+            &[],
+            &start_finish,
+        )
+        .unwrap();
+        assert!(!timestamped.to_string_lossy().contains('{'));
+
+        let dir = std::env::temp_dir().join(format!("ridi-router-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("routes-0.json"), "").unwrap();
+        std::fs::write(dir.join("routes-1.gpx"), "").unwrap();
+        let indexed = |formats: &[OutputFormat]| {
+            get_output_path(
+                Some(PathBuf::from("routes-{index}.gpx")),
+                Some(dir.clone()),
+                formats,
+                &start_finish,
+            )
+        };
+        assert_eq!(indexed(&[]), Some(dir.join("routes-0.gpx")));
+        assert_eq!(
+            indexed(&[OutputFormat::Gpx, OutputFormat::Json]),
+            Some(dir.join("routes-2.gpx"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn batch_jobs_read() {
+        let file =
+            std::env::temp_dir().join(format!("ridi-router-jobs-{}.json", std::process::id()));
+        std::fs::write(
+            &file,
+            r#"[
+                {
+                    "start": {"Coords": {"lat": 57.1, "lon": 24.2}},
+                    "finish": {"NodeId": {"id": 5}},
+                    "output": "out/first.gpx"
+                },
+                {
+                    "start": {"NodeId": {"id": 1}},
+                    "finish": {"NodeId": {"id": 2}},
+                    "output": "out/second",
+                    "format": ["gpx_track", "json"],
+                    "reversed": true,
+                    "rules": {"sort_routes": "length"}
+                }
+            ]"#,
+        )
+        .unwrap();
+        let jobs = read_batch_jobs(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(
+            jobs[0].start,
+            StartFinishPoint::Coords {
+                lat: 57.1,
+                lon: 24.2
+            }
+        );
+        assert_eq!(jobs[0].finish, StartFinishPoint::NodeId { id: 5 });
+        assert!(jobs[0].format.is_empty());
+        assert!(!jobs[0].reversed);
+        assert_eq!(
+            jobs[1].format,
+            vec![OutputFormat::GpxTrack, OutputFormat::Json]
+        );
+        assert!(jobs[1].reversed);
+        assert_eq!(jobs[1].rules.sort_routes, Some(RulesRouteSort::Length));
+
+        assert!(matches!(
+            read_batch_jobs(&std::env::temp_dir().join("ridi-router-no-jobs.json")),
+            Err(RouterRunnerError::BatchFileRead { .. })
+        ));
+    }
+}
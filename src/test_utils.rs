@@ -6,7 +6,13 @@ use crate::{
         osm::{OsmNode, OsmRelation, OsmWay},
     },
     osm_data_reader::{DataSource, OsmDataReader},
-    router::route::Route,
+    router::{
+        itinerary::Itinerary,
+        route::{segment::Segment, segment_list::SegmentList, Route},
+        rules::RouterRules,
+        walker::Walker,
+        weights::WeightCalcInput,
+    },
 };
 
 pub type OsmTestData = (Vec<OsmNode>, Vec<OsmWay>, Vec<OsmRelation>);
@@ -349,6 +355,100 @@ pub fn set_graph_static(map_data: MapDataGraph) -> &'static MapDataGraph {
     MAP_DATA_GRAPH.get_or_init(|| map_data)
 }
 
+pub fn make_osm_way(id: u64, point_ids: &[u64], tags: &[(&str, &str)]) -> OsmWay {
+    OsmWay {
+        id,
+        point_ids: point_ids.to_vec(),
+        tags: Some(
+            tags.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        ),
+    }
+}
+
+/// Test graph from ways with tags, nodes are at `id, id` unless given coordinates
+#[derive(Default)]
+pub struct TestGraphBuilder {
+    nodes: Vec<OsmNode>,
+    ways: Vec<OsmWay>,
+    relations: Vec<OsmRelation>,
+}
+
+impl TestGraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Nodes at `id, id`
+    pub fn nodes(mut self, ids: &[u64]) -> Self {
+        self.nodes
+            .extend(ids.iter().map(|id| make_osm_point_with_id(*id)));
+        self
+    }
+    /// Nodes at `(id, lat, lon)`
+    pub fn nodes_at(mut self, nodes: &[(u64, f64, f64)]) -> Self {
+        self.nodes
+            .extend(nodes.iter().map(|(id, lat, lon)| OsmNode {
+                id: *id,
+                lat: *lat,
+                lon: *lon,
+            }));
+        self
+    }
+    pub fn way(mut self, id: u64, point_ids: &[u64], tags: &[(&str, &str)]) -> Self {
+        self.ways.push(make_osm_way(id, point_ids, tags));
+        self
+    }
+    pub fn relation(mut self, relation: OsmRelation) -> Self {
+        self.relations.push(relation);
+        self
+    }
+    pub fn build(self) -> MapDataGraph {
+        graph_from_test_dataset((self.nodes, self.ways, self.relations))
+    }
+}
+
+/// Owns what a `WeightCalcInput` borrows, the walker starts at the end of the fork segment and
+/// heads to the next itinerary point like in the navigator
+pub struct WeightCalcInputBuilder {
+    itinerary: Itinerary,
+    route: Route,
+    all_fork_segments: SegmentList,
+}
+
+impl WeightCalcInputBuilder {
+    pub fn new(itinerary: Itinerary) -> Self {
+        Self {
+            itinerary,
+            route: Route::new(),
+            all_fork_segments: SegmentList::from(Vec::new()),
+        }
+    }
+    /// The route up to the fork
+    pub fn route(mut self, route: Route) -> Self {
+        self.route = route;
+        self
+    }
+    pub fn build<'a>(
+        &'a self,
+        current_fork_segment: &'a Segment,
+        rules: &'a RouterRules,
+    ) -> WeightCalcInput<'a> {
+        WeightCalcInput {
+            route: &self.route,
+            itinerary: &self.itinerary,
+            all_fork_segments: &self.all_fork_segments,
+            current_fork_segment,
+            walker_from_fork: Walker::new(
+                current_fork_segment.get_end_point().clone(),
+                self.itinerary.get_next().clone(),
+            ),
+            rules,
+            corridor: None,
+        }
+    }
+}
+
 pub fn line_is_between_point_ids(line: &MapDataLineRef, id1: u64, id2: u64) -> bool {
     let point_ids = [
         line.borrow().points.0.borrow().id,